@@ -20,18 +20,19 @@
 *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 *   SOFTWARE.
 */
-mod replacer;
+#![allow(clippy::needless_return)]
 
-use replacer::cli::Cli;
-use replacer::error::CliError;
+use rp::errorln;
+use rp::replacer::cli::Cli;
 use std::io::Write;
 
-fn main() -> Result<(), CliError> {
-    match Cli::new().run() {
-        Ok(_) => return Ok(()),
+fn main() {
+    match Cli::new().and_then(|cli| cli.run()) {
+        Ok(status) => std::process::exit(status),
+        Err(error) if error.is_broken_pipe() => std::process::exit(0),
         Err(error) => {
             errorln!("{}", error);
-            return Err(CliError::from(error));
+            std::process::exit(error.exit_code());
         }
     }
 }
@@ -0,0 +1,341 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::{compile, Engine, SizeLimits};
+use super::error::*;
+use super::explain::matches_empty;
+use super::replace::replace_with;
+use super::template::{GroupRef, Template};
+use std::sync::Arc;
+
+/// A compiled pattern with the replacement for its matches.
+#[derive(Debug, Clone)]
+pub struct Replacer {
+    engine: Arc<dyn Engine>,
+    template: Template,
+    matches_empty: bool,
+}
+
+impl Replacer {
+    pub fn engine(&self) -> &Arc<dyn Engine> {
+        return &self.engine;
+    }
+
+    pub fn template(&self) -> &Template {
+        return &self.template;
+    }
+
+    /// Whether the pattern can match the empty string, which `build` let
+    /// through as the result is not written back in place. Always false when
+    /// empty matches were allowed.
+    pub fn matches_empty(&self) -> bool {
+        return self.matches_empty;
+    }
+
+    /// Replaces every match in `text`.
+    pub fn replace(&self, text: &str) -> Result<String, CliError> {
+        let mut index = 0;
+        let (output, _) = replace_with(self.engine.as_ref(), text, |captures, dst| {
+            self.template.expand(captures, index, dst)?;
+            index += 1;
+            return Ok(true);
+        })?;
+        return Ok(output);
+    }
+
+    pub fn into_parts(self) -> (Arc<dyn Engine>, Template) {
+        return (self.engine, self.template);
+    }
+}
+
+/// Compiles a pattern and checks that a replacement goes with it, with the
+/// options the command line has. The command line builds its pattern with it
+/// too, so the two cannot drift apart.
+///
+/// ```
+/// use rp::replacer::builder::ReplacerBuilder;
+///
+/// let replacer = ReplacerBuilder::new(r"^warn: (\w+)$", "WARNING ${1}")
+///     .case_insensitive(true)
+///     .multi_line(true)
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     replacer.replace("Warn: disk\nok\nWARN: memory").unwrap(),
+///     "WARNING disk\nok\nWARNING memory"
+/// );
+/// ```
+///
+/// `build` refuses a replacement that refers to a group the pattern does not
+/// have, and a pattern that can match the empty string when the result
+/// replaces its input:
+///
+/// ```
+/// use rp::replacer::builder::ReplacerBuilder;
+///
+/// assert!(ReplacerBuilder::new("(a)", "${2}").build().is_err());
+/// assert!(ReplacerBuilder::new("a*", "b").build().is_err());
+/// let printed = ReplacerBuilder::new("a*", "b").in_place(false).build().unwrap();
+/// assert!(printed.matches_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplacerBuilder {
+    pattern: String,
+    template: Template,
+    engine: String,
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+    ignore_whitespace: bool,
+    swap_greed: bool,
+    unicode: bool,
+    limits: SizeLimits,
+    allow_missing_groups: bool,
+    allow_empty_match: bool,
+    in_place: bool,
+}
+
+impl ReplacerBuilder {
+    /// Starts from `pattern` for the regex engine and `replacement` parsed as
+    /// a template, with Unicode on, the default size limits, and the result
+    /// taken to replace its input.
+    pub fn new(pattern: &str, replacement: &str) -> ReplacerBuilder {
+        return ReplacerBuilder {
+            pattern: String::from(pattern),
+            template: Template::parse(replacement),
+            engine: String::from("regex"),
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            ignore_whitespace: false,
+            swap_greed: false,
+            unicode: true,
+            limits: SizeLimits::default(),
+            allow_missing_groups: false,
+            allow_empty_match: false,
+            in_place: true,
+        };
+    }
+
+    /// Replaces the pattern, keeping every other setting.
+    pub fn pattern(mut self, pattern: &str) -> ReplacerBuilder {
+        self.pattern = String::from(pattern);
+        return self;
+    }
+
+    /// Uses an already built template for the replacement.
+    pub fn template(mut self, template: Template) -> ReplacerBuilder {
+        self.template = template;
+        return self;
+    }
+
+    /// Picks the engine by name: regex, fancy or pcre2.
+    pub fn engine(mut self, engine: &str) -> ReplacerBuilder {
+        self.engine = String::from(engine);
+        return self;
+    }
+
+    pub fn case_insensitive(mut self, yes: bool) -> ReplacerBuilder {
+        self.case_insensitive = yes;
+        return self;
+    }
+
+    pub fn multi_line(mut self, yes: bool) -> ReplacerBuilder {
+        self.multi_line = yes;
+        return self;
+    }
+
+    pub fn dot_matches_new_line(mut self, yes: bool) -> ReplacerBuilder {
+        self.dot_matches_new_line = yes;
+        return self;
+    }
+
+    pub fn ignore_whitespace(mut self, yes: bool) -> ReplacerBuilder {
+        self.ignore_whitespace = yes;
+        return self;
+    }
+
+    pub fn swap_greed(mut self, yes: bool) -> ReplacerBuilder {
+        self.swap_greed = yes;
+        return self;
+    }
+
+    pub fn unicode(mut self, yes: bool) -> ReplacerBuilder {
+        self.unicode = yes;
+        return self;
+    }
+
+    pub fn size_limit(mut self, bytes: usize) -> ReplacerBuilder {
+        self.limits.regex = bytes;
+        return self;
+    }
+
+    pub fn dfa_size_limit(mut self, bytes: usize) -> ReplacerBuilder {
+        self.limits.dfa = bytes;
+        return self;
+    }
+
+    /// Turns on the options named by `[imsxU]` flags, as in `/pattern/flags`.
+    pub fn flags(mut self, flags: &str) -> ReplacerBuilder {
+        self.case_insensitive |= flags.contains('i');
+        self.multi_line |= flags.contains('m');
+        self.dot_matches_new_line |= flags.contains('s');
+        self.ignore_whitespace |= flags.contains('x');
+        self.swap_greed |= flags.contains('U');
+        return self;
+    }
+
+    /// Lets the replacement refer to groups the pattern does not have, which
+    /// then expand to nothing.
+    pub fn allow_missing_groups(mut self, yes: bool) -> ReplacerBuilder {
+        self.allow_missing_groups = yes;
+        return self;
+    }
+
+    /// Lets the pattern match the empty string, which puts the replacement
+    /// between characters.
+    pub fn allow_empty_match(mut self, yes: bool) -> ReplacerBuilder {
+        self.allow_empty_match = yes;
+        return self;
+    }
+
+    /// Whether the result replaces the input, as with --inplace. Only then
+    /// is a pattern that can match the empty string refused; otherwise it is
+    /// let through and reported by `Replacer::matches_empty`.
+    pub fn in_place(mut self, yes: bool) -> ReplacerBuilder {
+        self.in_place = yes;
+        return self;
+    }
+
+    /// The options as `[imsxU]` flags.
+    pub fn flag_string(&self) -> String {
+        let mut flags = String::new();
+        for (on, flag) in [
+            (self.case_insensitive, 'i'),
+            (self.multi_line, 'm'),
+            (self.dot_matches_new_line, 's'),
+            (self.ignore_whitespace, 'x'),
+            (self.swap_greed, 'U'),
+        ] {
+            if on {
+                flags.push(flag);
+            }
+        }
+        return flags;
+    }
+
+    /// Compiles the pattern and checks that the replacement only refers to its
+    /// groups and that, in place, it cannot match the empty string, unless
+    /// allowed.
+    pub fn build(&self) -> Result<Replacer, CliError> {
+        let flags = self.flag_string();
+        let engine = compile(
+            self.engine.as_str(),
+            self.pattern.as_str(),
+            flags.as_str(),
+            self.unicode,
+            self.limits,
+        )?;
+        if !self.allow_missing_groups {
+            let names = engine.group_names();
+            let unknown = missing_groups(&names, self.template.groups());
+            if !unknown.is_empty() {
+                let names: Vec<String> = names.into_iter().flatten().collect();
+                let hints: Vec<String> = unknown
+                    .iter()
+                    .filter_map(|name| braced_suggestion(name, &names))
+                    .map(|(reference, braced)| {
+                        format!("; did you mean {} for {}?", braced, reference)
+                    })
+                    .collect();
+                let unknown: Vec<String> = unknown
+                    .iter()
+                    .map(|name| format!("${{{}}}", name))
+                    .collect();
+                return Err(CliError::from(format!(
+                    "the replacement refers to groups missing from the pattern: {}{}",
+                    unknown.join(", "),
+                    hints.concat()
+                ))
+                .with_class(ErrorClass::Usage));
+            }
+        }
+        let matches_empty = !self.allow_empty_match
+            && can_match_empty(engine.as_ref(), self.pattern.as_str(), flags.as_str())?;
+        if matches_empty && self.in_place {
+            return Err(CliError::from(
+                "the pattern can match the empty string, which puts the replacement between characters; use --allow-empty-match to edit files in place anyway",
+            )
+            .with_class(ErrorClass::Usage));
+        }
+        return Ok(Replacer {
+            engine,
+            template: self.template.clone(),
+            matches_empty,
+        });
+    }
+}
+
+/// The names or numbers of the groups in `groups` that are not among `names`,
+/// the groups of a pattern, each once.
+pub fn missing_groups(names: &[Option<String>], groups: Vec<&GroupRef>) -> Vec<String> {
+    let mut unknown: Vec<String> = Vec::new();
+    for group in groups {
+        let name = match group {
+            GroupRef::Index(index) => index.to_string(),
+            GroupRef::Name(name) => name.clone(),
+        };
+        if !group.exists(names) && !unknown.contains(&name) {
+            unknown.push(name);
+        }
+    }
+    return unknown;
+}
+
+/// For a reference to the missing group `reference` that starts with the
+/// number or name of a group in `names`, as `$1foo` does, the reference and
+/// the braced form it was probably meant as, e.g. `${1}foo`.
+pub fn braced_suggestion(reference: &str, names: &[String]) -> Option<(String, String)> {
+    let prefix = match reference.find(|c: char| !c.is_ascii_digit()) {
+        Some(0) => names
+            .iter()
+            .filter(|name| reference.starts_with(name.as_str()))
+            .max_by_key(|name| name.len())
+            .map(|name| name.as_str()),
+        Some(digits) => Some(&reference[..digits]),
+        None => None,
+    }?;
+    return Some((
+        format!("${}", reference),
+        format!("${{{}}}{}", prefix, &reference[prefix.len()..]),
+    ));
+}
+
+/// Whether `pattern`, compiled as `engine`, can match the empty string. The
+/// syntax is checked when the regex engine can parse it, otherwise the
+/// compiled pattern is tried on an empty string.
+pub fn can_match_empty(engine: &dyn Engine, pattern: &str, flags: &str) -> Result<bool, CliError> {
+    match matches_empty(pattern, flags) {
+        Some(empty) => return Ok(empty),
+        None => return engine.is_match(""),
+    }
+}
@@ -0,0 +1,209 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::util::{checksum, create_parent};
+use crate::infoln;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const CACHE_VERSION: &str = "rp-cache 1";
+
+/// Remembers the files an earlier run found no matches in, so that later runs
+/// with the same rules can skip them without reading them while they are
+/// unchanged.
+///
+/// The cache is a version line, a `rules <hash>` line, then a
+/// `<size> <modified> <hash> <path>` line per file, with the modification
+/// time in nanoseconds since the epoch. A cache that is damaged, from another
+/// version or written for other rules is started over.
+#[derive(Debug)]
+pub struct Cache {
+    path: String,
+    rules: String,
+    entries: HashMap<String, CacheEntry>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    size: u64,
+    modified: u128,
+    hash: String,
+}
+
+impl Cache {
+    /// Reads the cache at `path` if it was written for `rules`, a hash of
+    /// everything that decides what matches.
+    pub fn open(path: &str, rules: &str) -> Cache {
+        let entries = match std::fs::read(path) {
+            Ok(data) => match parse(&data, rules) {
+                Some(entries) => entries,
+                None => {
+                    infoln!(
+                        "{}: starting the cache over, it is damaged or for other rules",
+                        path
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                infoln!("{}: starting the cache over: {}", path, error);
+                HashMap::new()
+            }
+        };
+        return Cache {
+            path: String::from(path),
+            rules: String::from(rules),
+            entries,
+            hits: 0,
+            misses: 0,
+        };
+    }
+
+    /// Whether `path` is the cache file, so that it is not processed itself.
+    pub fn is_cache(&self, path: &str) -> bool {
+        match (
+            Path::new(path).canonicalize(),
+            Path::new(&self.path).canonicalize(),
+        ) {
+            (Ok(path), Ok(cache)) => return path == cache,
+            _ => return false,
+        }
+    }
+
+    /// Whether `path` is known to have no matches: it has the cached size and
+    /// modification time, or the cached content when only the time differs.
+    /// A file that is not is forgotten until `insert` records it again.
+    pub fn fresh(&mut self, path: &str) -> bool {
+        let fresh = self.check(path);
+        match fresh {
+            true => self.hits += 1,
+            false => {
+                self.misses += 1;
+                self.entries.remove(path);
+            }
+        }
+        return fresh;
+    }
+
+    fn check(&mut self, path: &str) -> bool {
+        let entry = match self.entries.get_mut(path) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let meta = match std::fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(_) => return false,
+        };
+        if meta.len() != entry.size {
+            return false;
+        }
+        let modified = modified(&meta);
+        if modified != 0 && modified == entry.modified {
+            return true;
+        }
+        match std::fs::read(path) {
+            Ok(content) if checksum(&content) == entry.hash => {
+                entry.modified = modified;
+                return true;
+            }
+            _ => return false,
+        }
+    }
+
+    /// Records that `path` has no matches as it is now.
+    pub fn insert(&mut self, path: &str) {
+        if path.contains('\n') {
+            return;
+        }
+        let entry = std::fs::metadata(path).and_then(|meta| {
+            return Ok(CacheEntry {
+                size: meta.len(),
+                modified: modified(&meta),
+                hash: checksum(&std::fs::read(path)?),
+            });
+        });
+        match entry {
+            Ok(entry) => self.entries.insert(String::from(path), entry),
+            Err(_) => self.entries.remove(path),
+        };
+    }
+
+    /// Writes the cache through a temporary file next to it.
+    pub fn save(&self) -> Result<(), CliError> {
+        let mut paths: Vec<&String> = self.entries.keys().collect();
+        paths.sort();
+        let mut out = format!("{}\nrules {}\n", CACHE_VERSION, self.rules);
+        for path in paths {
+            let entry = &self.entries[path];
+            out.push_str(&format!(
+                "{} {} {} {}\n",
+                entry.size, entry.modified, entry.hash, path
+            ));
+        }
+        let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", self.path, error));
+        create_parent(Path::new(&self.path))?;
+        let temporary = PathBuf::from(format!("{}.rp-tmp", self.path));
+        let written =
+            std::fs::write(&temporary, out).and_then(|_| std::fs::rename(&temporary, &self.path));
+        if let Err(error) = written {
+            std::fs::remove_file(&temporary).ok();
+            return Err(wrap(error));
+        }
+        return Ok(());
+    }
+}
+
+fn parse(data: &[u8], rules: &str) -> Option<HashMap<String, CacheEntry>> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut lines = text.lines();
+    if lines.next() != Some(CACHE_VERSION)
+        || lines.next() != Some(format!("rules {}", rules).as_str())
+    {
+        return None;
+    }
+    let mut entries = HashMap::new();
+    for line in lines {
+        let mut fields = line.splitn(4, ' ');
+        let entry = CacheEntry {
+            size: fields.next()?.parse().ok()?,
+            modified: fields.next()?.parse().ok()?,
+            hash: String::from(fields.next()?),
+        };
+        entries.insert(String::from(fields.next()?), entry);
+    }
+    return Some(entries);
+}
+
+/// The modification time of a file in nanoseconds since the epoch, or 0 when
+/// it is not known.
+fn modified(meta: &std::fs::Metadata) -> u128 {
+    return meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+}
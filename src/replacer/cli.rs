@@ -20,22 +20,62 @@
 *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 *   SOFTWARE.
 */
+use super::builder::{braced_suggestion, missing_groups, ReplacerBuilder};
+use super::cache::Cache;
+use super::command::run_replacement;
+use super::config::{Config, Entry};
+use super::delimited::Dialect;
+use super::engine::{Engine, Group, Groups, Normalizing};
 use super::error::*;
+use super::explain::{anchors_text, explain, mentions_newline};
+use super::filetype::{file_types, type_list};
+use super::format::{render_json, Format};
+use super::gzip::{self, Gzip};
+use super::interrupt::{install_handler, interrupted};
+use super::journal::{undo, Journal};
+use super::json::{decode_string, string_values, JsonPath};
+use super::lines::{paragraphs, past_last_line, LineIndex, LineRange};
+use super::log::{enabled, set_level, set_log_file, set_timestamps, Level};
+use super::mapping::Mapping;
+use super::normalize::Form;
+use super::pattern::*;
+use super::preview::*;
+use super::region::{find_regions, Region};
+use super::replace::{replace_with, write_with};
+use super::report::*;
+use super::sed::{Occurrence, SedExpression};
+use super::split::{split, FieldSelector};
+use super::stream::{extract_stream, replace_stream_template};
+use super::template::*;
+use super::terminal::{fit, use_color, width};
+use super::timestamp::Timestamp;
 use super::util::*;
 use super::validators::*;
-use crate::{debug, debugln, errorln};
-use clap::Clap;
+use super::walk::walk;
+use super::watch::*;
+use crate::{debugln, errorln, infoln, traceln};
+use clap::{ArgMatches, Clap, FromArgMatches, IntoApp};
+use ignore::types::Types;
 use regex::Regex;
+use std::borrow::Cow;
+use std::cell::{Cell, OnceCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::IsTerminal;
 use std::io::Read;
 use std::io::Write;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+#[derive(Debug, Clone, Clap)]
 #[clap(
     name = "rp",
     version = "0.2.0",
     author = "Neil F Jones",
     about = "A multiline regex find/replace utility."
 )]
-#[derive(Debug, Clone, Clap)]
 struct Opts {
     #[clap(
         short('i'),
@@ -48,164 +88,4451 @@ struct Opts {
         short('p'),
         long("pattern"),
         takes_value(true),
-        validator(validate_regex),
         conflicts_with("pattern-file"),
         about("Write to file instead of stdout.")
     )]
     pattern: Option<String>,
+    #[clap(
+        long("engine"),
+        takes_value(true),
+        default_value("regex"),
+        possible_values(&["regex", "fancy", "pcre2"]),
+        about("The regex engine. fancy (lookaround, backreferences) and pcre2 (\\K, possessive quantifiers, recursion) need rp built with the feature of the same name.")
+    )]
+    engine: String,
+    #[clap(
+        long("unescape"),
+        takes_value(false),
+        about("Interpret escapes like \\n, \\t, \\xNN and \\u{...} in the replacement, and in the pattern with --fixed-strings.")
+    )]
+    unescape: bool,
+    #[clap(
+        long("allow-missing-groups"),
+        takes_value(false),
+        about("Allow the replacement to refer to groups the pattern does not have.")
+    )]
+    allow_missing_groups: bool,
+    #[clap(
+        short('O'),
+        long("output"),
+        takes_value(true),
+        conflicts_with_all(&["inplace", "check", "dry-run", "only-matching", "files-with-matches", "files-without-match"]),
+        about("Write to this file, or with several inputs recreate their paths under this directory. - is stdout.")
+    )]
+    output: Option<String>,
+    #[clap(
+        long("recursive"),
+        takes_value(false),
+        about("Process the files below directories, or below . without any files.")
+    )]
+    recursive: bool,
+    #[clap(
+        long("hidden"),
+        takes_value(false),
+        about("Include hidden files and directories in --recursive.")
+    )]
+    hidden: bool,
+    #[clap(
+        long("no-ignore"),
+        takes_value(false),
+        about("Do not skip files matched by .gitignore, .ignore or the global git excludes in --recursive.")
+    )]
+    no_ignore: bool,
+    #[clap(
+        short('t'),
+        long("type"),
+        takes_value(true),
+        multiple_occurrences(true),
+        number_of_values(1),
+        about("Only process files of this type in --recursive, e.g. rust or py. Repeatable; see --type-list.")
+    )]
+    file_type: Vec<String>,
+    #[clap(
+        long("type-add"),
+        takes_value(true),
+        multiple_occurrences(true),
+        number_of_values(1),
+        about("Define a file type for this run as NAME:GLOB,GLOB..., e.g. 'web:*.html,*.css,*.js'. Globs are added to an existing type of the same name.")
+    )]
+    type_add: Vec<String>,
+    #[clap(
+        long("type-list"),
+        takes_value(false),
+        about("Print the file types known to --type and their globs.")
+    )]
+    type_list: bool,
+    #[clap(
+        long("rename"),
+        takes_value(false),
+        conflicts_with_all(&["output", "check", "only-matching", "files-with-matches", "files-without-match", "watch"]),
+        about("Apply the replacement to the file names and rename the files. Contents are also replaced with --inplace.")
+    )]
+    rename: bool,
+    #[clap(
+        long("rename-path"),
+        takes_value(false),
+        conflicts_with_all(&["output", "check", "only-matching", "files-with-matches", "files-without-match", "watch"]),
+        about("Like --rename, but apply the replacement to the whole path.")
+    )]
+    rename_path: bool,
+    #[clap(
+        long("journal"),
+        takes_value(true),
+        requires("inplace"),
+        about(
+            "Record the original content of every file changed in place to this file, for --undo."
+        )
+    )]
+    journal: Option<String>,
+    #[clap(
+        long("allow-empty-match"),
+        takes_value(false),
+        about("Allow a pattern that can match the empty string, which inserts the replacement between characters, with --inplace. Not needed with --delete-lines, --insert-before or --insert-after.")
+    )]
+    allow_empty_match: bool,
+    #[clap(
+        long("until-stable"),
+        takes_value(false),
+        conflicts_with_all(&["only-matching", "files-with-matches", "files-without-match"]),
+        about("Run the replacement again over its own output until the text stops changing.")
+    )]
+    until_stable: bool,
+    #[clap(
+        long("max-passes"),
+        takes_value(true),
+        requires("until-stable"),
+        validator(validate_passes),
+        about("The number of passes after which --until-stable gives up. Defaults to 10.")
+    )]
+    max_passes: Option<usize>,
+    #[clap(
+        long("explain"),
+        takes_value(false),
+        about("Print how the regex engine reads the pattern: its syntax tree, groups, anchors and literal prefixes.")
+    )]
+    explain: bool,
+    #[clap(
+        long("test"),
+        takes_value(false),
+        requires("sample"),
+        about("Print each match of the pattern in --sample with its groups, and the result with a replacement. Exits with 1 when nothing matches.")
+    )]
+    test: bool,
+    #[clap(
+        long("sample"),
+        takes_value(true),
+        requires("test"),
+        about("The text --test matches the pattern against.")
+    )]
+    sample: Option<String>,
+    #[clap(
+        long("undo"),
+        takes_value(true),
+        about("Restore the files recorded in a journal written by --journal.")
+    )]
+    undo: Option<String>,
+    #[clap(
+        long("force"),
+        takes_value(false),
+        about("Edit read-only files in place, or restore with --undo even when files changed after the journal was written.")
+    )]
+    force: bool,
+    #[clap(
+        long("anchored"),
+        takes_value(false),
+        conflicts_with("line-buffered"),
+        about(
+            "Require the pattern to match each input as a whole, and fail on inputs it does not."
+        )
+    )]
+    anchored: bool,
+    #[clap(
+        long("field"),
+        takes_value(true),
+        conflicts_with_all(&["only-matching", "extract", "split", "line-mode", "null-data", "lines", "within-start", "delete-lines", "insert-before", "insert-after", "replace-cmd"]),
+        about("Only replace within these 1-based fields of each delimited record, e.g. 3 or 1,3-5.")
+    )]
+    field: Option<String>,
+    #[clap(
+        long("field-delimiter"),
+        takes_value(true),
+        requires("field"),
+        about("The character between fields for --field. Escapes like \\t are interpreted. Defaults to a comma.")
+    )]
+    field_delimiter: Option<String>,
+    #[clap(
+        long("csv"),
+        takes_value(false),
+        requires("field"),
+        about("Read --field records as CSV, where quoted fields may hold delimiters, quotes and newlines.")
+    )]
+    csv: bool,
+    #[clap(
+        long("skip-lines"),
+        takes_value(true),
+        requires("field"),
+        about("The number of records at the start of each input to leave untouched with --field, e.g. 1 for a header.")
+    )]
+    skip_lines: Option<usize>,
+    #[clap(
+        long("json-strings"),
+        takes_value(false),
+        conflicts_with_all(&["only-matching", "extract", "split", "field", "line-mode", "null-data", "lines", "within-start", "delete-lines", "insert-before", "insert-after", "replace-cmd"]),
+        about("Read each input as JSON and only replace within its string values, keeping keys, numbers and layout as they are.")
+    )]
+    json_strings: bool,
+    #[clap(
+        long("json-path"),
+        takes_value(true),
+        requires("json-strings"),
+        about("Only replace within the strings at or below this path with --json-strings, e.g. $.items[*].description.")
+    )]
+    json_path: Option<String>,
+    #[clap(
+        long("line-mode"),
+        takes_value(false),
+        conflicts_with_all(&["dry-run", "check", "files-with-matches", "files-without-match", "lines", "within-start", "if-matches", "unless-matches", "anchored", "null-data", "journal", "rename", "rename-path", "output", "strip-bom", "add-bom", "match-timeout"]),
+        about("Replace in each line on its own, without its newline, writing lines out as they are done. ^ and $ match at the start and end of each line.")
+    )]
+    line_mode: bool,
+    #[clap(
+        long("line-buffered"),
+        takes_value(false),
+        conflicts_with_all(&["dry-run", "check", "files-with-matches", "files-without-match", "lines", "within-start", "if-matches", "unless-matches"]),
+        about("Process and flush stdin line by line, e.g. for tail -f. Automatic when stdout is a terminal.")
+    )]
+    line_buffered: bool,
+    #[clap(
+        long("watch"),
+        takes_value(false),
+        conflicts_with("check"),
+        about("After the first pass, keep processing the files again whenever they change until Ctrl-C.")
+    )]
+    watch: bool,
+    #[clap(
+        long("no-config"),
+        takes_value(false),
+        about("Do not read defaults from ~/.config/rp/config.toml or .rp.toml.")
+    )]
+    no_config: bool,
+    #[clap(
+        short('q'),
+        long("quiet"),
+        takes_value(false),
+        about("Print nothing but errors, e.g. when only the exit status matters.")
+    )]
+    quiet: bool,
+    #[clap(
+        long("stats"),
+        takes_value(false),
+        about("Print a summary of the run to stderr. Implied by --verbose.")
+    )]
+    stats: bool,
+    #[clap(
+        long("timings"),
+        takes_value(false),
+        about("Print where the time went to stderr: compiling, reading, replacing and writing, and the slowest files. Added to --report.")
+    )]
+    timings: bool,
+    #[clap(
+        long("newer-than"),
+        takes_value(true),
+        about("Only process files modified after this RFC 3339 time, @SECONDS since the epoch, or the modification time of this file.")
+    )]
+    newer_than: Option<String>,
+    #[clap(
+        long("older-than"),
+        takes_value(true),
+        about("Only process files modified before this RFC 3339 time, @SECONDS since the epoch, or the modification time of this file.")
+    )]
+    older_than: Option<String>,
+    #[clap(
+        long("cache"),
+        takes_value(true),
+        about("Remember in this file which files had no matches, and skip them in later runs with the same options while their size and modification time or content stay the same.")
+    )]
+    cache: Option<String>,
+    #[clap(
+        long("no-cache"),
+        takes_value(false),
+        about("Ignore --cache, e.g. one set in the config file.")
+    )]
+    no_cache: bool,
+    #[clap(
+        long("check"),
+        takes_value(false),
+        conflicts_with_all(&["inplace", "dry-run", "only-matching", "files-with-matches", "files-without-match"]),
+        about("Write nothing, print each file that would change and exit 1 if there are any. Errors exit as without --check: 4 for IO errors and 5 when only some files failed.")
+    )]
+    check: bool,
+    #[clap(
+        long("show-matches"),
+        takes_value(false),
+        requires("check"),
+        about(
+            "With --check, print the line number and text of each match instead of just the file."
+        )
+    )]
+    show_matches: bool,
+    #[clap(
+        long("strict-replacement"),
+        takes_value(false),
+        about("Treat ambiguous group references like $1foo in the replacement as errors instead of warnings.")
+    )]
+    strict_replacement: bool,
+    #[clap(
+        long("no-expand-groups"),
+        takes_value(false),
+        conflicts_with_all(&["numeric", "replace-cmd", "map-file", "expand-env"]),
+        about("Write the replacement as it is: $1, ${name}, $$, \\U and ${rp:...} are not expanded.")
+    )]
+    no_expand_groups: bool,
+    #[clap(
+        long("keep-trailing-newline"),
+        takes_value(false),
+        about("Keep the trailing newline of --pattern-file and --replacement-file.")
+    )]
+    keep_trailing_newline: bool,
+    #[clap(
+        short('x'),
+        long("extended"),
+        takes_value(false),
+        conflicts_with("fixed-strings"),
+        about("Ignore whitespace and # comments in the pattern, e.g. to format a long --pattern-file.")
+    )]
+    extended: bool,
+    #[clap(
+        long("no-pattern-flags"),
+        takes_value(false),
+        about("Treat a pattern like /pattern/flags literally instead of as a pattern with flags. Without it, /usr/ matches usr; // always matches two slashes.")
+    )]
+    no_pattern_flags: bool,
     #[clap(
         short('r'),
         long("replacement"),
         takes_value(true),
         conflicts_with("replacement-file"),
-        about("The replacement text to write. Supports groups (${1}, ${named_group}, etc.)")
+        about("The replacement text to write. Supports groups (${1}, ${named_group}, etc.) and case escapes (\\U, \\L, \\E, \\u, \\l).")
     )]
     replacement: Option<String>,
     #[clap(
         short('P'),
         long("pattern-file"),
         takes_value(true),
-        validator(validate_regex_file),
+        multiple_occurrences(true),
+        number_of_values(1),
         conflicts_with("pattern"),
-        about("The file to read the regex pattern from.")
+        about("The file to read the regex pattern from, or - for stdin when the input is files. Repeatable with --patterns-are-lines.")
+    )]
+    pattern_file: Vec<String>,
+    #[clap(
+        long("pattern-file-raw"),
+        takes_value(false),
+        requires("pattern-file"),
+        about("Keep the carriage returns of Windows line endings in --pattern-file as part of the pattern.")
+    )]
+    pattern_file_raw: bool,
+    #[clap(
+        long("patterns-are-lines"),
+        takes_value(false),
+        requires("pattern-file"),
+        conflicts_with("fixed-strings"),
+        about("Match any line of the --pattern-file files, taken literally. Empty lines and lines starting with # are skipped.")
+    )]
+    patterns_are_lines: bool,
+    #[clap(
+        long("lines-are-regex"),
+        takes_value(false),
+        requires("patterns-are-lines"),
+        about("Take the lines of --patterns-are-lines as regexes instead of literals.")
     )]
-    pattern_file: Option<String>,
+    lines_are_regex: bool,
     #[clap(
         short('R'),
         long("replacement-file"),
         takes_value(true),
         conflicts_with("replacement"),
-        about("The file to read the replacement text from.")
+        about(
+            "The file to read the replacement text from, or - for stdin when the input is files."
+        )
     )]
     replacement_file: Option<String>,
+    #[clap(
+        long("wrap"),
+        takes_value(true),
+        number_of_values(2),
+        value_names(&["prefix", "suffix"]),
+        conflicts_with_all(&["replacement", "replacement-file", "replace-cmd", "map-file", "insert-after", "insert-before", "delete-lines", "only-matching", "no-expand-groups"]),
+        about("Put PREFIX before and SUFFIX after each match. Both are written as they are, like -r 'PREFIX${0}SUFFIX' with $ and \\ escaped.")
+    )]
+    wrap: Vec<String>,
+    #[clap(
+        long("wrap-group"),
+        takes_value(true),
+        requires("wrap"),
+        about("The capture group (index or name) to wrap instead of the whole match. Matches where it did not take part are kept as they are.")
+    )]
+    wrap_group: Option<String>,
     #[clap(
         short('e'),
         long("escape"),
         takes_value(false),
-        about("Print the pattern with regex characters escaped.")
+        about("Print the pattern (or stdin without one) with regex characters escaped.")
     )]
     escape: bool,
+    #[clap(
+        long("escape-replacement"),
+        takes_value(false),
+        conflicts_with("escape"),
+        about("Print the replacement (or stdin without one) with $ and \\ escaped so it is used literally.")
+    )]
+    escape_replacement: bool,
     #[clap(
         short('v'),
         long("verbose"),
+        parse(from_occurrences),
+        about("Log progress to stderr. Repeat for per-match (-vv) and streaming (-vvv) details.")
+    )]
+    verbose: u64,
+    #[clap(
+        long("log-timestamps"),
         takes_value(false),
-        about("Print verbose output to stderr.")
+        about("Prefix log lines with the time.")
     )]
-    verbose: bool,
-    #[clap(multiple(true), about("Print verbose output to stderr."))]
-    files: Vec<String>,
-}
-
-#[derive(Debug, Clone)]
-struct ParsedOpts {
-    pattern: String,
-    replacement: String,
-}
-
-#[derive(Debug, Clone)]
-pub struct Cli {
-    opts: Opts,
-    parsed_opts: ParsedOpts,
-}
-
-impl Cli {
-    pub fn new() -> Cli {
-        let opts = Opts::parse();
-        let parsed_opts = ParsedOpts {
-            pattern: Cli::get_arg_or_file(opts.pattern.clone(), opts.pattern_file.clone()),
-            replacement: Cli::get_arg_or_file(
-                opts.replacement.clone(),
-                opts.replacement_file.clone(),
-            ),
-        };
-        return Cli { opts, parsed_opts };
-    }
-
-    fn get_arg_or_file(arg: Option<String>, path: Option<String>) -> String {
-        match arg {
-            Some(arg) => return arg,
-            None => match path {
-                Some(path) => return read_file(path.as_str()).unwrap(),
-                None => return String::new(),
-            },
-        }
-    }
-
-    fn escape_pattern(&self) {
-        let escaped = regex::escape(self.parsed_opts.pattern.as_str());
-        print!("{}", escaped);
-    }
-
-    fn process_text(&self, text: String) -> Result<String, CliError> {
-        return Ok(String::from(
-            Regex::new(self.parsed_opts.pattern.as_str())?
-                .replace_all(text.as_str(), self.parsed_opts.replacement.as_str())
-                .as_ref(),
-        ));
-    }
-
-    fn process_file(&self, path: &str) -> Result<(), CliError> {
-        debug!("Processing: {} => ", path);
-        return read_file(path).and_then(|text| -> Result<(), CliError> {
-            let result = self.process_text(text);
-            match result {
-                Ok(result) => {
-                    debugln!("replaced");
-                    match self.opts.inplace {
-                        true => return write_file(path, result),
-                        false => {
-                            print!("{}", result);
-                            return Ok(());
-                        }
-                    }
-                }
-                Err(error) => {
-                    debugln!("skipped");
-                    return Err(error);
-                }
-            }
-        });
-    }
-
-    fn process_files(&self) -> Result<(), CliError> {
-        for path in self.opts.files.iter() {
-            match self.process_file(path.as_str()) {
-                Ok(_) => (),
-                Err(error) => errorln!("{}", error),
-            }
-        }
-        return Ok(());
-    }
-
-    fn process_stdin(&self) -> Result<(), CliError> {
-        debugln!("Reading stdin");
-        let mut text = String::new();
-
-        match std::io::stdin().read_to_string(&mut text) {
-            Ok(_) => {
-                let result = self.process_text(text.clone());
-                match result {
-                    Ok(result) => print!("{}", result),
-                    Err(_) => (),
-                }
-            }
-            Err(error) => return Err(CliError::from(error)),
-        }
-        return Ok(());
-    }
-
-    fn process_pattern(&self) -> Result<(), CliError> {
-        match self.opts.files.len() > 0 {
-            true => return self.process_files(),
-            false => return self.process_stdin(),
-        }
-    }
-
-    pub fn run(&self) -> Result<(), CliError> {
-        set_debug(self.opts.verbose);
-        match self.opts.escape {
-            true => return Ok(self.escape_pattern()),
-            false => return self.process_pattern(),
+    log_timestamps: bool,
+    #[clap(
+        long("log-file"),
+        takes_value(true),
+        about("Write the log to this file instead of stderr.")
+    )]
+    log_file: Option<String>,
+    #[clap(
+        long("exit-status"),
+        takes_value(false),
+        about("Exit with 1 when nothing matched. Failures exit with 2 for usage errors, 3 for invalid patterns, 4 for IO errors and 5 when only some files failed.")
+    )]
+    exit_status: bool,
+    #[clap(
+        long("max-logged-matches"),
+        takes_value(true),
+        about("The number of replacements per file whose location --verbose and --report show. Defaults to 20.")
+    )]
+    max_logged_matches: Option<usize>,
+    #[clap(
+        long("report"),
+        takes_value(true),
+        possible_values(&["json"]),
+        about("Print a report of the replacements made in each file.")
+    )]
+    report: Option<String>,
+    #[clap(
+        long("report-file"),
+        takes_value(true),
+        requires("report"),
+        about("Write the report to a file instead of stdout.")
+    )]
+    report_file: Option<String>,
+    #[clap(
+        short('o'),
+        long("only-matching"),
+        takes_value(false),
+        conflicts_with("inplace"),
+        about("Print only the matched text of each match instead of replacing it.")
+    )]
+    only_matching: bool,
+    #[clap(
+        long("group"),
+        takes_value(true),
+        requires("only-matching"),
+        about("The capture group (index or name) to print in --only-matching mode.")
+    )]
+    group: Option<String>,
+    #[clap(
+        long("extract"),
+        takes_value(true),
+        conflicts_with_all(&["inplace", "only-matching", "replacement", "replacement-file", "dry-run", "check", "files-with-matches", "files-without-match", "until-stable"]),
+        about("Print only the text of this capture group (index or name) from each match and nothing else.")
+    )]
+    extract: Option<String>,
+    #[clap(
+        long("split"),
+        takes_value(false),
+        conflicts_with_all(&["inplace", "only-matching", "extract", "replacement", "replacement-file", "dry-run", "check", "files-with-matches", "files-without-match", "until-stable"]),
+        about("Use the pattern as a delimiter and print the fields between its matches.")
+    )]
+    split: bool,
+    #[clap(
+        long("split-json"),
+        takes_value(false),
+        requires("split"),
+        about("Print the fields of --split as a JSON array.")
+    )]
+    split_json: bool,
+    #[clap(
+        long("keep-empty"),
+        takes_value(false),
+        requires("split"),
+        about("Keep empty fields at the start and end of the text in --split mode.")
+    )]
+    keep_empty: bool,
+    #[clap(
+        long("fields"),
+        takes_value(true),
+        requires("split"),
+        about("The 1-based fields to print in --split mode, e.g. 1,3-5 or 4-.")
+    )]
+    fields: Option<String>,
+    #[clap(
+        long("separator"),
+        takes_value(true),
+        about("Print this after each group printed by --extract or field printed by --split instead of a newline. Escapes like \\n are interpreted.")
+    )]
+    separator: Option<String>,
+    #[clap(
+        long("format"),
+        takes_value(true),
+        conflicts_with_all(&["group", "format-json"]),
+        about("Print each match through this template in --only-matching mode, e.g. '{ip} {status}'. {name} and {1} refer to groups. With --dry-run, 'patch' prints a diff for git apply.")
+    )]
+    format: Option<String>,
+    #[clap(
+        long("format-json"),
+        takes_value(false),
+        requires("only-matching"),
+        conflicts_with("group"),
+        about("Print each match as a JSON object of its groups in --only-matching mode.")
+    )]
+    format_json: bool,
+    #[clap(
+        short('0'),
+        long("null"),
+        takes_value(false),
+        about("Separate printed matches with NUL instead of newline.")
+    )]
+    null: bool,
+    #[clap(
+        short('z'),
+        long("null-data"),
+        takes_value(false),
+        about("Treat input as NUL separated records that are processed independently. Implies --null.")
+    )]
+    null_data: bool,
+    #[clap(
+        long("paragraph-mode"),
+        takes_value(false),
+        conflicts_with_all(&["null-data", "line-buffered", "lines", "per-line-limit", "field", "json-strings"]),
+        about("Treat input as paragraphs separated by blank lines that are processed independently, so \\A and \\z anchor to each. --if-matches and --unless-matches apply to each paragraph. Blank lines are kept as they are.")
+    )]
+    paragraph_mode: bool,
+    #[clap(
+        short('l'),
+        long("files-with-matches"),
+        takes_value(false),
+        conflicts_with_all(&["inplace", "only-matching", "files-without-match"]),
+        about("Print only the paths of files containing at least one match.")
+    )]
+    files_with_matches: bool,
+    #[clap(
+        short('L'),
+        long("files-without-match"),
+        takes_value(false),
+        conflicts_with_all(&["inplace", "only-matching", "files-with-matches"]),
+        about("Print only the paths of files containing no match.")
+    )]
+    files_without_match: bool,
+    #[clap(
+        long("no-filename"),
+        takes_value(false),
+        about("Do not prefix printed matches with the file path.")
+    )]
+    no_filename: bool,
+    #[clap(
+        long("color"),
+        takes_value(true),
+        default_value("auto"),
+        possible_values(&["auto", "always", "never"]),
+        about("Highlight replaced text in printed output. With auto, NO_COLOR and CLICOLOR_FORCE are honored.")
+    )]
+    color: String,
+    #[clap(
+        long("width"),
+        takes_value(true),
+        about("Cut --dry-run and --show-matches lines to this many columns instead of the terminal's width. 0 keeps them whole.")
+    )]
+    width: Option<usize>,
+    #[clap(
+        short('n'),
+        long("dry-run"),
+        takes_value(false),
+        conflicts_with_all(&["only-matching", "files-with-matches", "files-without-match"]),
+        about("Print the changed lines instead of writing or printing the result.")
+    )]
+    dry_run: bool,
+    #[clap(
+        short('C'),
+        long("context"),
+        takes_value(true),
+        requires("dry-run"),
+        about("The number of lines to show around each change in --dry-run mode.")
+    )]
+    context: Option<usize>,
+    #[clap(
+        short('W'),
+        long("pump-limit"),
+        takes_value(true),
+        default_value("1MiB"),
+        validator(validate_size),
+        about("The window size used when streaming stdin (e.g. 64KiB, 1MiB).")
+    )]
+    pump_limit: String,
+    #[clap(
+        long("max-filesize"),
+        takes_value(true),
+        validator(validate_size),
+        about("Treat files larger than this as large, see --large-files.")
+    )]
+    max_filesize: Option<String>,
+    #[clap(
+        long("large-files"),
+        takes_value(true),
+        default_value("skip"),
+        possible_values(&["skip", "stream", "error"]),
+        about("What to do with files over --max-filesize: skip them, stream them without reading them into memory, or fail them.")
+    )]
+    large_files: String,
+    #[clap(
+        long("invalid-utf8"),
+        takes_value(true),
+        default_value("error"),
+        possible_values(&["error", "lossy", "skip"]),
+        about("What to do with input that is not valid UTF-8: fail it, decode it replacing invalid bytes with U+FFFD in the output, or leave it untouched.")
+    )]
+    invalid_utf8: String,
+    #[clap(
+        long("compressed"),
+        takes_value(true),
+        default_value("extension"),
+        possible_values(&["extension", "auto", "none"]),
+        about("Which files are gzip compressed: those ending in .gz, those also starting with the gzip magic bytes, or none. They are decompressed to be matched and compressed again when written.")
+    )]
+    compressed: String,
+    #[clap(
+        long("strip-bom"),
+        takes_value(false),
+        conflicts_with("add-bom"),
+        about("Remove the UTF-8 byte order mark from the start of files instead of keeping it.")
+    )]
+    strip_bom: bool,
+    #[clap(
+        long("add-bom"),
+        takes_value(false),
+        about("Start the output of every file with a UTF-8 byte order mark.")
+    )]
+    add_bom: bool,
+    #[clap(
+        long("normalize"),
+        takes_value(true),
+        default_value("none"),
+        possible_values(&["nfc", "nfd", "none"]),
+        about("Match the input and the pattern in this Unicode normalization form. Needs rp built with the normalize feature.")
+    )]
+    normalize: String,
+    #[clap(
+        long("normalize-output"),
+        takes_value(false),
+        about("With --normalize, write the whole normalized text instead of only replacing the matched parts of the original.")
+    )]
+    normalize_output: bool,
+    #[clap(
+        long("symlinks"),
+        takes_value(true),
+        default_value("follow"),
+        possible_values(&["follow", "replace", "skip"]),
+        about("How to treat symlinked inputs: edit the file they point to, replace the link with a regular file, or skip them.")
+    )]
+    symlinks: String,
+    #[clap(
+        long("follow-links"),
+        takes_value(false),
+        about("Descend into symlinked directories with --recursive.")
+    )]
+    follow_links: bool,
+    #[clap(
+        long("allow-special"),
+        takes_value(false),
+        about(
+            "Read named pipes, devices and other special files given as inputs, e.g. /dev/stdin."
+        )
+    )]
+    allow_special: bool,
+    #[clap(
+        long("overwrite-changed"),
+        takes_value(false),
+        requires("inplace"),
+        about("Write files in place even when they changed while being processed.")
+    )]
+    overwrite_changed: bool,
+    #[clap(
+        long("max-change-ratio"),
+        takes_value(true),
+        requires("inplace"),
+        validator(validate_ratio),
+        about("Hold back in-place edits that rewrite more than this fraction of a file's bytes, e.g. 0.5. See --on-excessive-change.")
+    )]
+    max_change_ratio: Option<f64>,
+    #[clap(
+        long("on-excessive-change"),
+        takes_value(true),
+        default_value("skip"),
+        possible_values(&["skip", "prompt", "allow"]),
+        about("What to do with files over --max-change-ratio: skip them with a warning, ask whether to write them, or write them anyway.")
+    )]
+    on_excessive_change: String,
+    #[clap(
+        long("preserve-mtime"),
+        takes_value(false),
+        requires("inplace"),
+        conflicts_with("touch"),
+        about("Keep the access and modification times of files edited in place.")
+    )]
+    preserve_mtime: bool,
+    #[clap(
+        long("touch"),
+        takes_value(false),
+        requires("inplace"),
+        about("Update the modification time of files processed in place even when their content is unchanged.")
+    )]
+    touch: bool,
+    #[clap(
+        long("print-filenames"),
+        takes_value(false),
+        conflicts_with("inplace"),
+        about("Print a ==> path <== header before the output of each file.")
+    )]
+    print_filenames: bool,
+    #[clap(
+        long("file-separator"),
+        takes_value(true),
+        allow_hyphen_values(true),
+        conflicts_with("inplace"),
+        about("Print this between the outputs of files. Escapes like \\n are interpreted.")
+    )]
+    file_separator: Option<String>,
+    #[clap(
+        long("mmap"),
+        takes_value(false),
+        conflicts_with("no-mmap"),
+        about("Memory map files instead of reading them. Automatic for files over 64MiB when the options allow it. Files must not be changed by others while mapped.")
+    )]
+    mmap: bool,
+    #[clap(long("no-mmap"), takes_value(false), about("Never memory map files."))]
+    no_mmap: bool,
+    #[clap(
+        long("regex-size-limit"),
+        takes_value(true),
+        default_value("10MiB"),
+        validator(validate_size),
+        about("The most memory the compiled pattern may use.")
+    )]
+    regex_size_limit: String,
+    #[clap(
+        long("dfa-size-limit"),
+        takes_value(true),
+        default_value("2MiB"),
+        validator(validate_size),
+        about("The most memory the regex engine's lazy DFA may use per search.")
+    )]
+    dfa_size_limit: String,
+    #[clap(
+        long("match-timeout"),
+        takes_value(true),
+        validator(validate_seconds),
+        about("Give up on a file after this many seconds of matching and report it as an error. The search given up on keeps a thread busy until it ends or rp exits, so each timeout costs CPU for the rest of the run.")
+    )]
+    match_timeout: Option<String>,
+    #[clap(
+        long("counter-start"),
+        takes_value(true),
+        default_value("1"),
+        allow_hyphen_values(true),
+        about("The first value of ${rp:count} in the replacement.")
+    )]
+    counter_start: i64,
+    #[clap(
+        long("counter-step"),
+        takes_value(true),
+        default_value("1"),
+        allow_hyphen_values(true),
+        about("The amount ${rp:count} changes by for each match.")
+    )]
+    counter_step: i64,
+    #[clap(
+        long("counter-global"),
+        takes_value(false),
+        about("Keep counting ${rp:count} across files instead of restarting per file.")
+    )]
+    counter_global: bool,
+    #[clap(
+        long("stdin-filename"),
+        takes_value(true),
+        about("The path used for ${rp:path} and related variables when reading stdin.")
+    )]
+    stdin_filename: Option<String>,
+    #[clap(
+        long("timestamp"),
+        takes_value(true),
+        about("The RFC 3339 time used for ${rp:date} and ${rp:datetime} instead of the current time, e.g. 2024-05-01T12:00:00Z.")
+    )]
+    timestamp: Option<String>,
+    #[clap(
+        long("local-time"),
+        takes_value(false),
+        about("Show ${rp:date} and ${rp:datetime} in the local time zone instead of UTC.")
+    )]
+    local_time: bool,
+    #[clap(
+        long("expand-env"),
+        takes_value(false),
+        about("Expand ${env:NAME} and ${env:NAME:-default} in the replacement.")
+    )]
+    expand_env: bool,
+    #[clap(
+        long("numeric"),
+        takes_value(false),
+        about("Evaluate integer arithmetic on groups in the replacement, e.g. ${1+1}.")
+    )]
+    numeric: bool,
+    #[clap(
+        long("replace-cmd"),
+        takes_value(true),
+        conflicts_with_all(&["replacement", "replacement-file"]),
+        about("Run a command for each match and replace it with the command's output.")
+    )]
+    replace_cmd: Option<String>,
+    #[clap(
+        long("replace-cmd-shell"),
+        takes_value(false),
+        requires("replace-cmd"),
+        about("Run --replace-cmd through sh -c.")
+    )]
+    replace_cmd_shell: bool,
+    #[clap(
+        long("map-file"),
+        takes_value(true),
+        conflicts_with_all(&["pattern", "pattern-file", "replacement", "replacement-file", "replace-cmd"]),
+        about("Replace every key of a two-column mapping file with its value.")
+    )]
+    map_file: Option<String>,
+    #[clap(
+        long("map-delimiter"),
+        takes_value(true),
+        default_value("\t"),
+        about("The column delimiter of --map-file.")
+    )]
+    map_delimiter: char,
+    #[clap(
+        long("map-word-boundaries"),
+        takes_value(false),
+        requires("map-file"),
+        about("Only replace --map-file keys at word boundaries.")
+    )]
+    map_word_boundaries: bool,
+    #[clap(
+        short('F'),
+        long("fixed-strings"),
+        takes_value(false),
+        about("Treat the pattern as a literal string instead of a regex.")
+    )]
+    fixed_strings: bool,
+    #[clap(
+        short('w'),
+        long("word-regexp"),
+        takes_value(false),
+        about("Only match the pattern as a whole word.")
+    )]
+    word_regexp: bool,
+    #[clap(
+        long("ignore-pattern-whitespace"),
+        takes_value(false),
+        conflicts_with_all(&["extended", "map-file", "patterns-are-lines"]),
+        about("Match the pattern however it is spaced: whitespace in it matches any whitespace, and none between word characters. With --fixed-strings, any token boundary may also be spaced. Whitespace in character classes or escaped is kept.")
+    )]
+    ignore_pattern_whitespace: bool,
+    #[clap(
+        long("lines"),
+        takes_value(true),
+        multiple_occurrences(true),
+        number_of_values(1),
+        validator(validate_line_range),
+        about("Only replace matches starting within START:END (1-based, inclusive, repeatable).")
+    )]
+    lines: Vec<String>,
+    #[clap(
+        long("per-line-limit"),
+        takes_value(true),
+        validator(validate_per_line_limit),
+        about("Replace at most this many matches on each line. 1 replaces only the first, like sed's s/// without g. Matches that span lines are left alone.")
+    )]
+    per_line_limit: Option<u64>,
+    #[clap(
+        long("within-start"),
+        takes_value(true),
+        requires("within-end"),
+        about("Only replace inside regions opened by this pattern.")
+    )]
+    within_start: Option<String>,
+    #[clap(
+        long("within-end"),
+        takes_value(true),
+        requires("within-start"),
+        about("The pattern closing a --within-start region.")
+    )]
+    within_end: Option<String>,
+    #[clap(
+        long("within-unterminated"),
+        takes_value(true),
+        default_value("extend"),
+        possible_values(&["extend", "error"]),
+        about("Whether a region left open extends to EOF or is an error.")
+    )]
+    within_unterminated: String,
+    #[clap(
+        long("outside"),
+        takes_value(false),
+        requires("within-start"),
+        about("Only replace outside of the --within-start/--within-end regions.")
+    )]
+    outside: bool,
+    #[clap(
+        long("if-matches"),
+        takes_value(true),
+        about(
+            "Only process files that also match this pattern, or paragraphs with --paragraph-mode."
+        )
+    )]
+    if_matches: Option<String>,
+    #[clap(
+        long("unless-matches"),
+        takes_value(true),
+        about("Only process files that do not match this pattern, or paragraphs with --paragraph-mode.")
+    )]
+    unless_matches: Option<String>,
+    #[clap(
+        long("delete-lines"),
+        takes_value(false),
+        conflicts_with_all(&["replacement", "replacement-file", "replace-cmd", "map-file", "only-matching"]),
+        about("Delete every line on which a match starts, including all lines a match spans.")
+    )]
+    delete_lines: bool,
+    #[clap(
+        long("insert-after"),
+        takes_value(true),
+        conflicts_with_all(&["replacement", "replacement-file", "replace-cmd", "map-file", "only-matching", "delete-lines", "insert-before"]),
+        about("Keep each match and insert this text after it. Supports the same expansions as --replacement.")
+    )]
+    insert_after: Option<String>,
+    #[clap(
+        long("insert-before"),
+        takes_value(true),
+        conflicts_with_all(&["replacement", "replacement-file", "replace-cmd", "map-file", "only-matching", "delete-lines"]),
+        about("Keep each match and insert this text before it. Supports the same expansions as --replacement.")
+    )]
+    insert_before: Option<String>,
+    #[clap(
+        long("insert-newline"),
+        takes_value(false),
+        about("Insert on a line of its own after (or before) the line containing the match.")
+    )]
+    insert_newline: bool,
+    #[clap(
+        long("copy-indent"),
+        takes_value(false),
+        requires("insert-newline"),
+        about("Indent inserted lines like the line containing the match.")
+    )]
+    copy_indent: bool,
+    #[clap(
+        multiple(true),
+        about("The files to process. The first may instead be a sed-style s/pattern/replacement/flags expression. Without --pattern, the first is the pattern and the second the replacement; files after -- are never taken as either.")
+    )]
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ParsedOpts {
+    pattern: String,
+    flags: String,
+    replacement: String,
+    regex: Arc<dyn Engine>,
+    /// Whether the pattern can match the empty string, which is warned about.
+    matches_empty: bool,
+    mapping: Option<Mapping>,
+    template: Template,
+    line_ranges: Vec<LineRange>,
+    within: Option<(Regex, Regex)>,
+    if_matches: Option<Regex>,
+    unless_matches: Option<Regex>,
+    occurrence: Occurrence,
+    pump_limit: usize,
+    roots: Vec<String>,
+    types: Types,
+    format: Option<Format>,
+    unanchored: Option<Arc<dyn Engine>>,
+    max_filesize: Option<u64>,
+    match_timeout: Option<Duration>,
+    separator: Option<String>,
+    fields: Option<FieldSelector>,
+    field: Option<FieldSelector>,
+    dialect: Dialect,
+    json_path: Option<JsonPath>,
+    file_separator: Option<String>,
+    normalize: Option<Form>,
+    timestamp: Timestamp,
+    compile_time: Duration,
+    mtime_skipped: Option<u64>,
+}
+
+/// The UTF-8 byte order mark, kept out of the text that is matched.
+const BOM: char = '\u{feff}';
+
+/// How many replacements are logged and reported per file by default.
+const MAX_LOGGED_MATCHES: usize = 20;
+
+/// How much of the matched and replacement text is shown for each logged
+/// replacement.
+const PREVIEW_CHARS: usize = 40;
+
+/// How many passes --until-stable makes before giving up.
+const MAX_PASSES: usize = 10;
+
+/// How many lines of context --format=patch shows around each change by
+/// default.
+const PATCH_CONTEXT: usize = 3;
+
+/// Files at least this large are memory mapped when possible.
+const MMAP_THRESHOLD: u64 = 64 << 20;
+
+/// What to do with a rendered file.
+#[derive(Debug)]
+enum Output {
+    Nothing,
+    Print(String),
+    Emit(String),
+    Unchanged,
+}
+
+struct Scope {
+    lines: Option<LineIndex>,
+    regions: Option<Vec<Region>>,
+    seen: Cell<u64>,
+    /// The line of the last match in scope and the matches on it so far, for
+    /// --per-line-limit.
+    on_line: Cell<(usize, u64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cli {
+    opts: Opts,
+    parsed_opts: ParsedOpts,
+    started: Instant,
+    counted: Cell<u64>,
+    inputs: OnceCell<Vec<PathBuf>>,
+    renames: OnceCell<HashMap<String, String>>,
+    journal: OnceCell<Journal>,
+    printed: Cell<u64>,
+    /// Whether the first empty match has been pointed out.
+    warned_empty: Cell<bool>,
+}
+
+impl Cli {
+    /// Parses the command line. Errors that are not about the pattern or
+    /// reading a file are usage errors.
+    pub fn new() -> Result<Cli, CliError> {
+        return Cli::parse().map_err(|error| error.or_class(ErrorClass::Usage));
+    }
+
+    fn parse() -> Result<Cli, CliError> {
+        let matches = Opts::into_app().get_matches();
+        let mut opts = Opts::from_arg_matches(&matches);
+        if !opts.no_config {
+            Cli::apply_config(&mut opts, &matches, &Config::discover()?)?;
+        }
+        if let Some(path) = &opts.log_file {
+            set_log_file(path.as_str())?;
+        }
+        match opts.quiet && opts.log_file.is_none() {
+            true => set_level(Level::Off),
+            false => set_level(Level::from_verbosity(opts.verbose)),
+        }
+        set_timestamps(opts.log_timestamps);
+        // --extract is --only-matching on a single group without file names.
+        if opts.extract.is_some() {
+            opts.only_matching = true;
+            opts.group = opts.extract.clone();
+            opts.no_filename = true;
+        }
+        if opts.separator.is_some() && opts.extract.is_none() && !opts.split {
+            return Err(CliError::from("--separator needs --extract or --split"));
+        }
+        if opts.format.is_some() && !opts.only_matching {
+            match opts.dry_run && opts.format.as_deref() == Some("patch") {
+                true => {}
+                false => {
+                    return Err(CliError::from(
+                        "--format needs --only-matching, or --dry-run with --format=patch",
+                    ))
+                }
+            }
+        }
+        // --split prints through --only-matching with the fields in place of
+        // the matches.
+        if opts.split {
+            opts.only_matching = true;
+        }
+        let expression = match opts.files.first() {
+            Some(first)
+                if !Path::new(first).exists() && !Cli::replacement_follows(&opts, &matches) =>
+            {
+                SedExpression::parse(first).ok()
+            }
+            _ => None,
+        };
+        if let Some(expression) = &expression {
+            if opts.pattern.is_some()
+                || !opts.pattern_file.is_empty()
+                || opts.replacement.is_some()
+                || opts.replacement_file.is_some()
+                || !opts.wrap.is_empty()
+            {
+                return Err(CliError::from(
+                    "a sed expression cannot be combined with --pattern, --replacement or --wrap",
+                ));
+            }
+            debugln!("sed expression from the first argument: {:?}", expression);
+            opts.files.remove(0);
+        }
+        if expression.is_none() {
+            Cli::take_positional_pattern(&mut opts, &matches)?;
+        }
+        if opts.recursive && opts.files.is_empty() {
+            opts.files.push(String::from("."));
+        }
+        let roots = opts.files.clone();
+        let from_stdin = opts
+            .pattern_file
+            .iter()
+            .chain(opts.replacement_file.iter())
+            .filter(|path| *path == "-")
+            .count();
+        if from_stdin > 1 {
+            return Err(CliError::from(
+                "stdin can only be read once, for one --pattern-file or --replacement-file",
+            ));
+        }
+        if from_stdin == 1 && opts.files.is_empty() {
+            return Err(CliError::from(
+                "stdin holds the pattern or replacement, so the input must be given as files",
+            ));
+        }
+        let types = file_types(&opts.file_type, &opts.type_add)?;
+        if opts.recursive {
+            let (files, errors) = walk(
+                &opts.files,
+                opts.hidden,
+                opts.no_ignore,
+                opts.follow_links,
+                &types,
+            );
+            for error in errors {
+                errorln!("{}", error);
+            }
+            opts.files = files;
+        }
+        let (files, inputs) = Cli::unique_inputs(std::mem::take(&mut opts.files), opts.quiet);
+        opts.files = files;
+        let mtime_skipped = match opts.newer_than.is_some() || opts.older_than.is_some() {
+            true => Some(Cli::keep_modified(
+                &mut opts.files,
+                opts.newer_than
+                    .as_deref()
+                    .map(Timestamp::reference)
+                    .transpose()?,
+                opts.older_than
+                    .as_deref()
+                    .map(Timestamp::reference)
+                    .transpose()?,
+            )),
+            false => None,
+        };
+        if let Some(output) = opts.output.as_deref().filter(|output| *output != "-") {
+            if let Ok(canonical) = Path::new(output).canonicalize() {
+                if inputs.contains(&canonical) {
+                    return Err(CliError::from(format!(
+                        "{}: --output is also an input file",
+                        output
+                    )));
+                }
+            }
+        }
+        let wrap = match opts.wrap.as_slice() {
+            [] => None,
+            [prefix, suffix] if opts.unescape => {
+                Some((unescape(prefix, false)?, unescape(suffix, false)?))
+            }
+            [prefix, suffix] => Some((prefix.clone(), suffix.clone())),
+            _ => return Err(CliError::from("--wrap takes one prefix and one suffix")),
+        };
+        let wrap_group = opts.wrap_group.clone().unwrap_or_else(|| String::from("0"));
+        let mut replacement = match (
+            &expression,
+            opts.insert_after.as_ref().or(opts.insert_before.as_ref()),
+        ) {
+            _ if wrap.is_some() => String::new(),
+            (_, Some(text)) => text.clone(),
+            (Some(expression), None) => expression.replacement.clone(),
+            (None, None) => Cli::get_arg_or_file(
+                opts.replacement.clone(),
+                opts.replacement_file.clone(),
+                opts.keep_trailing_newline,
+            )?,
+        };
+        if opts.unescape {
+            replacement = unescape(replacement.as_str(), !opts.no_expand_groups)?;
+        }
+        if opts.expand_env {
+            replacement = expand_env(replacement.as_str())?;
+        }
+        // Only shown and checked for ambiguous references; --wrap-group cannot
+        // be written as a replacement.
+        if let Some((prefix, suffix)) = &wrap {
+            replacement = format!(
+                "{}${{{}}}{}",
+                escape_replacement(prefix),
+                wrap_group,
+                escape_replacement(suffix)
+            );
+        }
+        let mapping = match &opts.map_file {
+            Some(path) => Some(Mapping::load(path.as_str(), opts.map_delimiter)?),
+            None => None,
+        };
+        let mut pattern = match (&mapping, &expression) {
+            (Some(mapping), _) => mapping.pattern(opts.map_word_boundaries),
+            (None, Some(expression)) => expression.pattern.clone(),
+            (None, None) if opts.patterns_are_lines => {
+                let (pattern, count) = line_alternation(
+                    &opts.pattern_file,
+                    opts.lines_are_regex,
+                    opts.pattern_file_raw,
+                )?;
+                infoln!("pattern: {} alternatives, {} bytes", count, pattern.len());
+                pattern
+            }
+            (None, None) if opts.pattern_file.len() > 1 => {
+                return Err(CliError::from(
+                    "several --pattern-file need --patterns-are-lines",
+                ))
+            }
+            (None, None) => match opts.pattern_file.first() {
+                Some(path) if opts.pattern.is_none() => read_pattern_file(
+                    path.as_str(),
+                    opts.keep_trailing_newline,
+                    opts.pattern_file_raw,
+                )?,
+                _ => opts.pattern.clone().unwrap_or_default(),
+            },
+        };
+        let mut flags = String::new();
+        if mapping.is_none() && expression.is_none() && !opts.no_pattern_flags {
+            if let Some((inner, inline)) = split_flags(pattern.as_str()) {
+                flags = String::from(inline);
+                pattern = String::from(inner);
+            }
+        }
+        if opts.extended && !flags.contains('x') {
+            flags.push('x');
+        }
+        if opts.fixed_strings && mapping.is_none() {
+            if opts.unescape {
+                pattern = unescape(pattern.as_str(), false)?;
+            }
+            pattern = match opts.ignore_pattern_whitespace {
+                true => loosen_literal(pattern.as_str()),
+                false => regex::escape(pattern.as_str()),
+            };
+        } else if opts.ignore_pattern_whitespace {
+            pattern = loosen_pattern(pattern.as_str());
+        }
+        if opts.ignore_pattern_whitespace {
+            debugln!("pattern with loosened whitespace: {}", pattern);
+        }
+        if opts.word_regexp {
+            pattern = word_pattern(pattern.as_str());
+        }
+        if let Some(expression) = expression
+            .as_ref()
+            .filter(|expression| !expression.flags.is_empty())
+        {
+            pattern = format!("(?{}){}", expression.flags, pattern);
+        }
+        // Taken once so that every file in the run gets the same stamp.
+        let timestamp = match &opts.timestamp {
+            Some(timestamp) => Timestamp::parse(timestamp)?,
+            None => Timestamp::now(),
+        };
+        let timestamp = match opts.local_time {
+            true => timestamp.local(),
+            false => timestamp.utc(),
+        };
+        let normalize = Form::parse(opts.normalize.as_str())?;
+        if opts.normalize_output && normalize.is_none() {
+            return Err(CliError::from(
+                "--normalize-output needs --normalize nfc or nfd",
+            ));
+        }
+        if let Some(form) = normalize {
+            pattern = form.normalize(pattern.as_str()).into_owned();
+        }
+        // Modes that stop before reading any input do not use the replacement.
+        let replaces = !(opts.type_list
+            || opts.escape
+            || opts.escape_replacement
+            || opts.undo.is_some()
+            || opts.explain
+            || opts.test);
+        // Whole lines are deleted or inserted around, so an empty match does
+        // not put anything between characters.
+        let edits_lines =
+            opts.delete_lines || opts.insert_after.is_some() || opts.insert_before.is_some();
+        let builder = ReplacerBuilder::new("", "")
+            .engine(opts.engine.as_str())
+            .flags(flags.as_str())
+            .size_limit(parse_size(opts.regex_size_limit.as_str())? as usize)
+            .dfa_size_limit(parse_size(opts.dfa_size_limit.as_str())? as usize)
+            .template(match (&wrap, opts.no_expand_groups) {
+                (Some((prefix, suffix)), _) => {
+                    Template::wrap(GroupRef::parse(wrap_group.as_str()), prefix, suffix)
+                }
+                (None, true) => Template::literal(replacement.as_str()),
+                (None, false) => Template::parse_with(replacement.as_str(), opts.numeric)
+                    .with_counter(opts.counter_start, opts.counter_step),
+            })
+            .allow_missing_groups(opts.allow_missing_groups || !replaces)
+            .allow_empty_match(opts.allow_empty_match || mapping.is_some())
+            .in_place(opts.inplace && replaces && !edits_lines);
+        let normalizing = |regex: Arc<dyn Engine>| -> Arc<dyn Engine> {
+            match (normalize, opts.normalize_output) {
+                (Some(form), false) => return Arc::new(Normalizing::new(regex, form)),
+                _ => return regex,
+            }
+        };
+        let unanchored = match opts.anchored {
+            true => {
+                // Only the anchored pattern below is validated.
+                let (regex, _) = builder
+                    .clone()
+                    .pattern(pattern.as_str())
+                    .allow_missing_groups(true)
+                    .allow_empty_match(true)
+                    .build()?
+                    .into_parts();
+                Some(normalizing(regex))
+            }
+            false => None,
+        };
+        if opts.anchored {
+            pattern = anchored_pattern(pattern.as_str());
+        }
+        let delimiter = match &opts.field_delimiter {
+            Some(delimiter) => {
+                let delimiter = unescape(delimiter, false)?;
+                let mut chars = delimiter.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != '\n' && c != '\r' && c != '"' => c,
+                    _ => {
+                        return Err(CliError::from(format!(
+                            "invalid field delimiter: {:?}, expected a single character other than a quote or newline",
+                            delimiter
+                        )))
+                    }
+                }
+            }
+            None => ',',
+        };
+        let compiling = Instant::now();
+        let replacer = builder.pattern(pattern.as_str()).build()?;
+        let matches_empty = replacer.matches_empty();
+        let (regex, template) = replacer.into_parts();
+        let regex = normalizing(regex);
+        let compile_time = compiling.elapsed();
+        let parsed_opts = ParsedOpts {
+            regex,
+            matches_empty,
+            compile_time,
+            mtime_skipped,
+            pattern,
+            flags,
+            mapping,
+            template,
+            replacement,
+            line_ranges: opts
+                .lines
+                .iter()
+                .map(|range| LineRange::parse(range.as_str()))
+                .collect::<Result<Vec<LineRange>, CliError>>()?,
+            within: match (&opts.within_start, &opts.within_end) {
+                (Some(start), Some(end)) => Some((Regex::new(start)?, Regex::new(end)?)),
+                _ => None,
+            },
+            if_matches: opts
+                .if_matches
+                .as_ref()
+                .map(|guard| Regex::new(guard))
+                .transpose()?,
+            unless_matches: opts
+                .unless_matches
+                .as_ref()
+                .map(|guard| Regex::new(guard))
+                .transpose()?,
+            occurrence: match &expression {
+                Some(expression) => expression.occurrence,
+                None => Occurrence::all(),
+            },
+            pump_limit: parse_size(opts.pump_limit.as_str())? as usize,
+            roots,
+            types,
+            format: match opts.dry_run {
+                true => None,
+                false => opts
+                    .format
+                    .as_ref()
+                    .map(|format| Format::parse(format))
+                    .transpose()?,
+            },
+            unanchored,
+            max_filesize: opts
+                .max_filesize
+                .as_ref()
+                .map(|size| parse_size(size))
+                .transpose()?,
+            match_timeout: opts
+                .match_timeout
+                .as_ref()
+                .map(|timeout| parse_seconds(timeout))
+                .transpose()?,
+            separator: opts
+                .separator
+                .as_ref()
+                .map(|separator| unescape(separator, false))
+                .transpose()?,
+            fields: opts
+                .fields
+                .as_ref()
+                .map(|fields| FieldSelector::parse(fields))
+                .transpose()?,
+            field: opts
+                .field
+                .as_ref()
+                .map(|field| FieldSelector::parse(field))
+                .transpose()?,
+            dialect: Dialect {
+                delimiter,
+                csv: opts.csv,
+            },
+            json_path: opts
+                .json_path
+                .as_ref()
+                .map(|path| JsonPath::parse(path))
+                .transpose()?,
+            file_separator: opts
+                .file_separator
+                .as_ref()
+                .map(|separator| unescape(separator, false))
+                .transpose()?,
+            normalize,
+            timestamp,
+        };
+        return Ok(Cli {
+            opts,
+            parsed_opts,
+            started: Instant::now(),
+            counted: Cell::new(0),
+            inputs: OnceCell::from(inputs),
+            renames: OnceCell::new(),
+            journal: OnceCell::new(),
+            printed: Cell::new(0),
+            warned_empty: Cell::new(false),
+        });
+    }
+
+    /// Fills in the options that were not given on the command line from the
+    /// config files.
+    fn apply_config(
+        opts: &mut Opts,
+        matches: &ArgMatches,
+        config: &Config,
+    ) -> Result<(), CliError> {
+        let choice = |entry: &Entry, choices: &[&str]| -> Result<String, CliError> {
+            let value = entry.string()?;
+            match choices.contains(&value.as_str()) {
+                true => return Ok(value),
+                false => {
+                    return Err(entry.invalid(format!("one of {}", choices.join(", ")).as_str()))
+                }
+            }
+        };
+        for entry in config.entries.iter() {
+            if matches.occurrences_of(entry.key.as_str()) > 0 {
+                continue;
+            }
+            match entry.key.as_str() {
+                "color" => opts.color = choice(entry, &["auto", "always", "never"])?,
+                "engine" => opts.engine = choice(entry, &["regex", "fancy", "pcre2"])?,
+                "pump-limit" => {
+                    opts.pump_limit = entry.string()?;
+                    validate_size(opts.pump_limit.as_str()).map_err(|_| entry.invalid("a size"))?;
+                }
+                "context" => match usize::try_from(entry.integer()?) {
+                    Ok(context) => opts.context = Some(context),
+                    Err(_) => return Err(entry.invalid("a non-negative integer")),
+                },
+                "stats" => opts.stats = entry.bool()?,
+                "cache" => opts.cache = Some(entry.string()?),
+                "no-filename" => opts.no_filename = entry.bool()?,
+                "extended" => opts.extended = entry.bool()?,
+                "recursive" => opts.recursive = entry.bool()?,
+                "hidden" => opts.hidden = entry.bool()?,
+                "no-ignore" => opts.no_ignore = entry.bool()?,
+                _ => {
+                    if !opts.quiet {
+                        errorln!(
+                            "warning: {}:{}: unknown key {}",
+                            entry.path,
+                            entry.line,
+                            entry.key
+                        );
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Drops the files modified at or before `newer` or at or after `older`,
+    /// returning how many there were. Files whose modification time cannot be
+    /// read are kept for the error to show up when they are processed.
+    fn keep_modified(
+        files: &mut Vec<String>,
+        newer: Option<SystemTime>,
+        older: Option<SystemTime>,
+    ) -> u64 {
+        let before = files.len();
+        files.retain(|file| {
+            let modified = match std::fs::metadata(file).and_then(|meta| meta.modified()) {
+                Ok(modified) => modified,
+                Err(_) => return true,
+            };
+            let keep = newer.is_none_or(|newer| modified > newer)
+                && older.is_none_or(|older| modified < older);
+            if !keep {
+                debugln!("{}: skipped (modification time)", file);
+            }
+            return keep;
+        });
+        return (before - files.len()) as u64;
+    }
+
+    /// Drops repeated inputs, keeping the first spelling of each file, and
+    /// returns the remaining files along with their canonical paths.
+    fn unique_inputs(files: Vec<String>, quiet: bool) -> (Vec<String>, Vec<PathBuf>) {
+        let mut seen: HashMap<PathBuf, String> = HashMap::new();
+        #[cfg(unix)]
+        let mut inodes: HashMap<(u64, u64), String> = HashMap::new();
+        let mut unique = Vec::with_capacity(files.len());
+        let mut inputs = Vec::with_capacity(files.len());
+        for file in files {
+            let canonical = Path::new(&file)
+                .canonicalize()
+                .unwrap_or_else(|_| PathBuf::from(&file));
+            if let Some(first) = seen.get(&canonical) {
+                infoln!("{}: already listed as {}, processing it once", file, first);
+                continue;
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                if let Ok(meta) = std::fs::metadata(&file) {
+                    if let Some(other) = inodes.insert((meta.dev(), meta.ino()), file.clone()) {
+                        if !quiet {
+                            errorln!(
+                                "warning: {} and {} are hard links to the same file",
+                                other,
+                                file
+                            );
+                        }
+                    }
+                }
+            }
+            seen.insert(canonical.clone(), file.clone());
+            inputs.push(canonical);
+            unique.push(file);
+        }
+        #[cfg(not(unix))]
+        let _ = quiet;
+        return (unique, inputs);
+    }
+
+    /// Counts the file arguments that come before `--`.
+    fn positionals_before_separator(matches: &ArgMatches) -> usize {
+        let separator = std::env::args_os().position(|arg| arg == "--");
+        match (matches.indices_of("files"), separator) {
+            (Some(indices), Some(separator)) => {
+                return indices.filter(|index| *index < separator).count()
+            }
+            (Some(indices), None) => return indices.count(),
+            (None, _) => return 0,
+        }
+    }
+
+    /// Whether the second argument reads as the replacement of a positional
+    /// pattern rather than a file, in which case the first argument is the
+    /// pattern even when it looks like a sed expression.
+    fn replacement_follows(opts: &Opts, matches: &ArgMatches) -> bool {
+        return Cli::positionals_before_separator(matches) >= 2
+            && !Path::new(opts.files[1].as_str()).exists();
+    }
+
+    /// Without --pattern, --pattern-file or --map-file, `rp PATTERN REPLACEMENT
+    /// FILE...` takes the pattern and then the replacement from the positionals
+    /// before any --. The replacement is not taken when it is given as an
+    /// option or the mode does not use one. Positionals after -- are files.
+    fn take_positional_pattern(opts: &mut Opts, matches: &ArgMatches) -> Result<(), CliError> {
+        if opts.pattern.is_some() || !opts.pattern_file.is_empty() || opts.map_file.is_some() {
+            return Ok(());
+        }
+        let takes_replacement = opts.replacement.is_none()
+            && opts.replacement_file.is_none()
+            && opts.wrap.is_empty()
+            && opts.replace_cmd.is_none()
+            && opts.insert_after.is_none()
+            && opts.insert_before.is_none()
+            && !opts.only_matching
+            && !opts.delete_lines
+            && !opts.explain
+            && !opts.escape
+            && !opts.files_with_matches
+            && !opts.files_without_match;
+        let wanted = match takes_replacement {
+            true => 2,
+            false => 1,
+        };
+        let separator = std::env::args_os().position(|arg| arg == "--");
+        match (Cli::positionals_before_separator(matches), separator) {
+            (0, _) => return Ok(()),
+            (count, Some(_)) if count < wanted => {
+                return Err(CliError::from(
+                    "the arguments before -- are the pattern and the replacement, but the replacement is missing",
+                ))
+            }
+            (count, None) if count < wanted => return Ok(()),
+            _ => {}
+        }
+        let pattern = opts.files.remove(0);
+        if Path::new(pattern.as_str()).exists() {
+            return Err(CliError::from(format!(
+                "{}: the first argument is taken as the pattern but is also an existing file; give the pattern with --pattern, or the files after --",
+                pattern
+            )));
+        }
+        debugln!("pattern from the first argument: {}", pattern);
+        opts.pattern = Some(pattern);
+        if takes_replacement {
+            let replacement = opts.files.remove(0);
+            if opts.files.is_empty() && Path::new(replacement.as_str()).exists() {
+                return Err(CliError::from(format!(
+                    "{}: the second argument is taken as the replacement but is also an existing file, and no files follow; give the replacement with --replacement, or the files after --",
+                    replacement
+                )));
+            }
+            debugln!("replacement from the second argument: {}", replacement);
+            opts.replacement = Some(replacement);
+        }
+        return Ok(());
+    }
+
+    fn get_arg_or_file(
+        arg: Option<String>,
+        path: Option<String>,
+        keep_newline: bool,
+    ) -> Result<String, CliError> {
+        match arg {
+            Some(arg) => return Ok(arg),
+            None => match path {
+                Some(path) if keep_newline => return read_file_or_stdin(path.as_str()),
+                Some(path) => return read_file_or_stdin(path.as_str()).map(strip_trailing_newline),
+                None => return Ok(String::new()),
+            },
+        }
+    }
+
+    fn read_stdin(&self) -> Result<String, CliError> {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        match self.opts.keep_trailing_newline {
+            true => return Ok(text),
+            false => return Ok(strip_trailing_newline(text)),
+        }
+    }
+
+    fn escape_pattern(&self) -> Result<(), CliError> {
+        let pattern = match self.opts.pattern.is_some() || !self.opts.pattern_file.is_empty() {
+            true => self.parsed_opts.pattern.clone(),
+            false => self.read_stdin()?,
+        };
+        std::io::stdout()
+            .lock()
+            .write_all(regex::escape(pattern.as_str()).as_bytes())?;
+        return Ok(());
+    }
+
+    /// Warns about a pattern that can match the empty string, which the
+    /// builder only refuses with --inplace. Modes that edit whole lines do
+    /// not mind.
+    fn check_empty_match(&self) {
+        if self.parsed_opts.matches_empty
+            && !self.opts.quiet
+            && !self.opts.delete_lines
+            && !self.insert_mode()
+        {
+            errorln!("warning: the pattern can match the empty string, which puts the replacement between characters");
+        }
+    }
+
+    /// Refuses patterns that spell out a newline with --line-mode, since lines
+    /// are matched without them.
+    fn check_line_mode(&self) -> Result<(), CliError> {
+        if !self.opts.line_mode {
+            return Ok(());
+        }
+        return self.check_single_line("--line-mode", "matches each line without it");
+    }
+
+    /// Refuses patterns that spell out a newline with --per-line-limit, since
+    /// their matches do not belong to one line. Other matches that happen to
+    /// span lines are skipped by `in_scope`.
+    fn check_per_line_limit(&self) -> Result<(), CliError> {
+        if self.opts.per_line_limit.is_none() {
+            return Ok(());
+        }
+        return self.check_single_line("--per-line-limit", "counts the matches on each line");
+    }
+
+    /// Refuses a pattern that spells out a newline under `flag`, which `why`
+    /// explains.
+    fn check_single_line(&self, flag: &str, why: &str) -> Result<(), CliError> {
+        let pattern = self.parsed_opts.pattern.as_str();
+        let newline = mentions_newline(pattern, self.parsed_opts.flags.as_str())
+            .unwrap_or_else(|| pattern.contains('\n') || pattern.contains("\\n"));
+        match newline {
+            true => {
+                return Err(CliError::from(format!(
+                    "the pattern matches a newline, but {} {}; leave out {} to match across lines",
+                    flag, why, flag
+                )))
+            }
+            false => return Ok(()),
+        }
+    }
+
+    /// Whether the pattern asserts the start or end of the whole input, which
+    /// a batch of lines read with --line-buffered is not.
+    fn anchors_text(&self) -> bool {
+        let pattern = self.parsed_opts.pattern.as_str();
+        return anchors_text(pattern, self.parsed_opts.flags.as_str())
+            .unwrap_or_else(|| pattern.contains("\\A") || pattern.contains("\\z"));
+    }
+
+    /// Warns that --line-buffered matches `\A` and `\z` at every batch of
+    /// lines. Without the option, such patterns are not line buffered.
+    fn check_line_buffered(&self) {
+        if self.opts.line_buffered && !self.opts.quiet && self.anchors_text() {
+            errorln!("warning: with --line-buffered, the start and end of the input are those of each batch of lines read");
+        }
+    }
+
+    /// Points out where the pattern first matched the empty string.
+    fn warn_empty_match(&self, path: &str, text: &str, found: &Group) {
+        if found.start() != found.end()
+            || self.warned_empty.get()
+            || self.opts.allow_empty_match
+            || self.opts.quiet
+        {
+            return;
+        }
+        self.warned_empty.set(true);
+        let line = text[..found.start()].matches('\n').count() + 1;
+        errorln!(
+            "warning: {}:{}: first empty match, at byte {}",
+            path,
+            line,
+            found.start()
+        );
+    }
+
+    /// Prints the matches in --sample for --test.
+    fn test_sample(&self) -> Result<i32, CliError> {
+        let sample = self.opts.sample.as_deref().unwrap_or_default();
+        let regex = self.parsed_opts.regex.as_ref();
+        let names = regex.group_names();
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        let mut matched = false;
+        for captures in regex.captures_iter(sample) {
+            let captures = captures?;
+            let found = captures.found();
+            matched = true;
+            writeln!(
+                out,
+                "match {}..{}: {}",
+                found.start(),
+                found.end(),
+                found.as_str()
+            )?;
+            for (index, name) in names.iter().enumerate().skip(1) {
+                let label = match name {
+                    Some(name) => format!("{} ({})", index, name),
+                    None => index.to_string(),
+                };
+                match captures.get(index) {
+                    Some(group) => writeln!(out, "  {}: {}", label, group.as_str())?,
+                    None => writeln!(out, "  {}: (unmatched)", label)?,
+                }
+            }
+        }
+        if !matched {
+            writeln!(out, "no match")?;
+            return Ok(1);
+        }
+        if self.opts.replacement.is_some() || self.opts.replacement_file.is_some() {
+            let mut report = FileReport::new("-");
+            let result = self.process_text(sample, &self.stdin_template()?, &mut report)?;
+            writeln!(out, "result: {}", result)?;
+        }
+        return Ok(0);
+    }
+
+    fn escape_replacement_text(&self) -> Result<(), CliError> {
+        let replacement = match (&self.opts.replacement, &self.opts.replacement_file) {
+            (None, None) => self.read_stdin()?,
+            (arg, path) => {
+                Cli::get_arg_or_file(arg.clone(), path.clone(), self.opts.keep_trailing_newline)?
+            }
+        };
+        std::io::stdout()
+            .lock()
+            .write_all(escape_replacement(replacement.as_str()).as_bytes())?;
+        return Ok(());
+    }
+
+    fn use_color(&self) -> bool {
+        return use_color(self.opts.color.as_str());
+    }
+
+    /// The columns preview lines are cut to: --width, or the terminal's width
+    /// when printing to one.
+    fn preview_width(&self) -> Option<usize> {
+        match self.opts.width {
+            Some(0) => return None,
+            Some(width) => return Some(width),
+            None if std::io::stdout().is_terminal() => return Some(width()),
+            None => return None,
+        }
+    }
+
+    /// Whether replacements are highlighted in the output. They are not when
+    /// the output is matched again or re-encoded.
+    fn color_replacements(&self) -> bool {
+        return self.use_color()
+            && !self.opts.inplace
+            && !self.opts.until_stable
+            && !self.replaces_values();
+    }
+
+    /// Whether the replacement runs on values taken out of the text one at a
+    /// time, the fields of --field or the strings of --json-strings.
+    fn replaces_values(&self) -> bool {
+        return self.parsed_opts.field.is_some() || self.opts.json_strings;
+    }
+
+    fn file_template(&self, path: &str) -> Result<Template, CliError> {
+        return self.parsed_opts.template.resolve(|name| {
+            path_variable(path, name).or_else(|| self.parsed_opts.timestamp.variable(name))
+        });
+    }
+
+    fn stdin_template(&self) -> Result<Template, CliError> {
+        match &self.opts.stdin_filename {
+            Some(path) => return self.file_template(path.as_str()),
+            None => {
+                return self.parsed_opts.template.resolve(|name| {
+                    path_variable("-", name)
+                        .map(|_| String::from("-"))
+                        .or_else(|| self.parsed_opts.timestamp.variable(name))
+                })
+            }
+        }
+    }
+
+    fn expand(
+        &self,
+        template: &Template,
+        captures: &Groups,
+        report: &FileReport,
+        dst: &mut String,
+    ) -> Result<(), CliError> {
+        let index = match self.opts.counter_global {
+            true => self.counted.replace(self.counted.get() + 1),
+            false => report.replacements,
+        };
+        if let Some(mapping) = &self.parsed_opts.mapping {
+            let found = captures.get(0).unwrap().as_str();
+            dst.push_str(mapping.get(found).unwrap_or(found));
+            return Ok(());
+        }
+        let expanded = match &self.opts.replace_cmd {
+            Some(command) => run_replacement(
+                command.as_str(),
+                self.opts.replace_cmd_shell,
+                self.parsed_opts.regex.as_ref(),
+                captures,
+            )
+            .map(|replacement| dst.push_str(replacement.as_str())),
+            None => template.expand(captures, index, dst),
+        };
+        match expanded {
+            Ok(_) => return Ok(()),
+            Err(error) => {
+                return Err(CliError::from(format!(
+                    "{}: byte {}: {}",
+                    report.path,
+                    captures.get(0).unwrap().start(),
+                    error
+                )))
+            }
+        }
+    }
+
+    fn scope(&self, text: &str) -> Result<Scope, CliError> {
+        let lines =
+            match self.parsed_opts.line_ranges.is_empty() && self.opts.per_line_limit.is_none() {
+                true => None,
+                false => Some(LineIndex::new(text)),
+            };
+        let regions = match &self.parsed_opts.within {
+            Some((start, end)) => Some(find_regions(
+                text,
+                start,
+                end,
+                self.opts.within_unterminated == "extend",
+            )?),
+            None => None,
+        };
+        return Ok(Scope {
+            lines,
+            regions,
+            seen: Cell::new(0),
+            on_line: Cell::new((0, 0)),
+        });
+    }
+
+    fn in_scope(&self, scope: &Scope, found: &Group) -> bool {
+        if let Some(lines) = &scope.lines {
+            let line = lines.line_of(found.start()) + 1;
+            if !self.parsed_opts.line_ranges.is_empty()
+                && !self
+                    .parsed_opts
+                    .line_ranges
+                    .iter()
+                    .any(|range| range.contains(line))
+            {
+                return false;
+            }
+        }
+        if let Some(regions) = &scope.regions {
+            let (start, end) = (found.start(), found.end());
+            let inside = match self.opts.outside {
+                true => !regions.iter().any(|region| region.overlaps(start, end)),
+                false => regions.iter().any(|region| region.contains(start, end)),
+            };
+            if !inside {
+                return false;
+            }
+        }
+        if let (Some(limit), Some(lines)) = (self.opts.per_line_limit, &scope.lines) {
+            // A match that runs onto the next line, e.g. through `\s`, does
+            // not belong to one line, so it is never counted or replaced.
+            if found.as_str().contains('\n') {
+                return false;
+            }
+            let line = lines.line_of(found.start());
+            let on_line = match scope.on_line.get() {
+                (last, count) if last == line => count + 1,
+                _ => 1,
+            };
+            scope.on_line.set((line, on_line));
+            if on_line > limit {
+                return false;
+            }
+        }
+        scope.seen.set(scope.seen.get() + 1);
+        return self.parsed_opts.occurrence.accepts(scope.seen.get());
+    }
+
+    fn deleted_lines(
+        &self,
+        text: &str,
+        report: &mut FileReport,
+    ) -> Result<Vec<(usize, usize)>, CliError> {
+        let scope = self.scope(text)?;
+        let lines = LineIndex::new(text);
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        let mut count = 0;
+        let mut deleted: Option<usize> = None;
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let found = captures?.found();
+            if !self.in_scope(&scope, &found) || past_last_line(text, found.start()) {
+                continue;
+            }
+            report.matches += 1;
+            let (first, last) = lines.span_lines(found.start(), found.end());
+            let first = match deleted {
+                Some(line) => first.max(line + 1),
+                None => first,
+            };
+            if first > last {
+                continue;
+            }
+            count += last - first + 1;
+            deleted = Some(last);
+            let (start, end) = (lines.line_start(first), lines.line_end(last));
+            match spans.last_mut() {
+                Some(span) if span.1 == start => span.1 = end,
+                _ => spans.push((start, end)),
+            }
+        }
+        report.replacements = spans.len() as u64;
+        report.changed = !spans.is_empty();
+        debugln!("deleting {} lines", count);
+        return Ok(spans);
+    }
+
+    fn delete_text(&self, text: &str, report: &mut FileReport) -> Result<String, CliError> {
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end) in self.deleted_lines(text, report)? {
+            result.push_str(&text[last..start]);
+            last = end;
+        }
+        result.push_str(&text[last..]);
+        return Ok(result);
+    }
+
+    fn insert_mode(&self) -> bool {
+        return self.opts.insert_after.is_some() || self.opts.insert_before.is_some();
+    }
+
+    fn insert_edits(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<Vec<Edit>, CliError> {
+        let scope = self.scope(text)?;
+        let lines = LineIndex::new(text);
+        let after = self.opts.insert_after.is_some();
+        let mut edits = Vec::new();
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let captures = captures?;
+            let found = captures.found();
+            if !self.in_scope(&scope, &found)
+                || (self.opts.insert_newline && past_last_line(text, found.start()))
+            {
+                continue;
+            }
+            let mut inserted = String::new();
+            self.expand(template, &captures, report, &mut inserted)?;
+            report.matches += 1;
+            report.replacements += 1;
+            report.changed |= !inserted.is_empty();
+            let (first, last) = lines.span_lines(found.start(), found.end());
+            let position = match (self.opts.insert_newline, after) {
+                (false, true) => found.end(),
+                (false, false) => found.start(),
+                (true, true) => lines.line_end(last),
+                (true, false) => lines.line_start(first),
+            };
+            if self.opts.insert_newline {
+                let line = &text[lines.line_start(first)..lines.line_end(first)];
+                let indent = match self.opts.copy_indent {
+                    true => &line[..line.len() - line.trim_start_matches([' ', '\t']).len()],
+                    false => "",
+                };
+                inserted = match after && !text[..position].ends_with('\n') {
+                    true => format!("\n{}{}", indent, inserted),
+                    false => format!("{}{}\n", indent, inserted),
+                };
+            }
+            edits.push(Edit {
+                start: position,
+                end: position,
+                replacement: inserted,
+            });
+        }
+        return Ok(edits);
+    }
+
+    fn insert_text(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        let colored = self.color_replacements();
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for edit in self.insert_edits(text, template, report)? {
+            result.push_str(&text[last..edit.start]);
+            match colored {
+                true => result.push_str(&format!(
+                    "{}{}{}",
+                    COLOR_INSERTED, edit.replacement, COLOR_RESET
+                )),
+                false => result.push_str(edit.replacement.as_str()),
+            }
+            last = edit.start;
+        }
+        result.push_str(&text[last..]);
+        return Ok(result);
+    }
+
+    /// Runs `process` on each NUL terminated record of `text` with
+    /// --null-data, on each paragraph that passes the guards with
+    /// --paragraph-mode, or on the whole text otherwise. The NULs, blank lines
+    /// and skipped paragraphs are kept in the output when `keep_separators`.
+    fn each_record<F>(
+        &self,
+        text: &str,
+        keep_separators: bool,
+        mut process: F,
+    ) -> Result<String, CliError>
+    where
+        F: FnMut(&str) -> Result<String, CliError>,
+    {
+        if self.opts.paragraph_mode {
+            let mut output = String::with_capacity(text.len());
+            for (paragraph, separator) in paragraphs(text) {
+                match !paragraph.is_empty() && self.passes_guards(paragraph) {
+                    true => output.push_str(process(paragraph)?.as_str()),
+                    false if keep_separators => output.push_str(paragraph),
+                    false => {}
+                }
+                if keep_separators {
+                    output.push_str(separator);
+                }
+            }
+            return Ok(output);
+        }
+        if !self.opts.null_data {
+            return process(text);
+        }
+        let mut output = String::with_capacity(text.len());
+        for record in text.split_inclusive('\0') {
+            let (record, terminated) = match record.strip_suffix('\0') {
+                Some(record) => (record, true),
+                None => (record, false),
+            };
+            output.push_str(process(record)?.as_str());
+            if terminated && keep_separators {
+                output.push('\0');
+            }
+        }
+        return Ok(output);
+    }
+
+    fn process_text(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        if !self.opts.until_stable {
+            return self.process_pass(text, template, report);
+        }
+        let limit = self.opts.max_passes.unwrap_or(MAX_PASSES);
+        let mut previous: Option<String> = None;
+        let mut current = String::from(text);
+        for pass in 1..=limit {
+            let replacements = report.replacements;
+            let next = self.process_pass(current.as_str(), template, report)?;
+            infoln!(
+                "{}: pass {}: {} replacements",
+                report.path,
+                pass,
+                report.replacements - replacements
+            );
+            if next == current {
+                return Ok(next);
+            }
+            if previous.as_deref() == Some(next.as_str()) {
+                return Err(CliError::from(format!(
+                    "{}: did not converge, pass {} undid pass {}",
+                    report.path,
+                    pass,
+                    pass - 1
+                )));
+            }
+            previous = Some(std::mem::replace(&mut current, next));
+        }
+        return Err(CliError::from(format!(
+            "{}: did not converge after {} passes",
+            report.path, limit
+        )));
+    }
+
+    fn process_pass(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        if let Some(selector) = &self.parsed_opts.field {
+            return self.process_fields(text, selector, template, report);
+        }
+        if self.opts.json_strings {
+            return self.process_json(text, template, report);
+        }
+        return self.each_record(text, true, |record| {
+            self.process_record(record, template, report)
+        });
+    }
+
+    /// Replaces within the selected fields of each delimited record on their
+    /// own, past the first --skip-lines records, and writes them back quoted
+    /// as needed. Everything else is kept as it is.
+    fn process_fields(
+        &self,
+        text: &str,
+        selector: &FieldSelector,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        let dialect = self.parsed_opts.dialect;
+        let records = dialect
+            .records(text)
+            .map_err(|error| CliError::from(format!("{}: {}", report.path, error)))?;
+        let mut output = String::with_capacity(text.len());
+        let mut last = 0;
+        for record in records.iter().skip(self.opts.skip_lines.unwrap_or(0)) {
+            for (index, field) in record.fields.iter().enumerate() {
+                if !selector.contains(index + 1) {
+                    continue;
+                }
+                let value = dialect.value(text, field);
+                let result = self.process_record(&value, template, report)?;
+                if result == value {
+                    continue;
+                }
+                output.push_str(&text[last..field.start]);
+                output.push_str(&dialect.encode(result.as_str(), field.quoted));
+                last = field.end;
+            }
+        }
+        output.push_str(&text[last..]);
+        return Ok(output);
+    }
+
+    /// Replaces within the string values of the JSON document in `text`, or
+    /// those below --json-path, on their own. Keys, numbers and whitespace are
+    /// kept byte for byte.
+    fn process_json(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        let strings = string_values(text)
+            .map_err(|error| CliError::from(format!("{}: {}", report.path, error)))?;
+        let mut output = String::with_capacity(text.len());
+        let mut last = 0;
+        for string in strings {
+            if let Some(path) = &self.parsed_opts.json_path {
+                if !path.selects(&string.path) {
+                    continue;
+                }
+            }
+            let value = match decode_string(&text[string.start..string.end]) {
+                Some(value) => value,
+                None => continue,
+            };
+            let result = self.process_record(value.as_str(), template, report)?;
+            if result == value {
+                continue;
+            }
+            output.push_str(&text[last..string.start]);
+            output.push_str(&json_string(result.as_str()));
+            last = string.end;
+        }
+        output.push_str(&text[last..]);
+        return Ok(output);
+    }
+
+    fn process_record(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        if self.opts.delete_lines {
+            return self.delete_text(text, report);
+        }
+        if self.insert_mode() {
+            return self.insert_text(text, template, report);
+        }
+        let scope = self.scope(text)?;
+        let (result, replaced) = replace_with(
+            self.parsed_opts.regex.as_ref(),
+            text,
+            self.replacer(text, &scope, template, report),
+        )?;
+        report.changed_bytes += replaced.changed_bytes(text, result.as_str()) as u64;
+        return Ok(result);
+    }
+
+    /// Like `process_record`, but writes the result to `out` as it goes so
+    /// that it is never held in memory next to `text`. Only for the plain
+    /// replacement, see `writes_through`.
+    fn write_record(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+        out: &mut dyn Write,
+    ) -> Result<(), CliError> {
+        let scope = self.scope(text)?;
+        let changed = write_with(
+            self.parsed_opts.regex.as_ref(),
+            text,
+            out,
+            self.replacer(text, &scope, template, report),
+        )?;
+        report.changed_bytes += changed as u64;
+        return Ok(());
+    }
+
+    /// Returns what replaces each match in `text` that is in `scope`, for
+    /// `replace_with` and `write_with`.
+    fn replacer<'a>(
+        &'a self,
+        text: &'a str,
+        scope: &'a Scope,
+        template: &'a Template,
+        report: &'a mut FileReport,
+    ) -> impl FnMut(&Groups, &mut String) -> Result<bool, CliError> + 'a {
+        let colored = self.color_replacements();
+        let mut expanded = String::new();
+        let mut lines: Option<LineIndex> = None;
+        return move |captures, dst| {
+            let found = captures.found();
+            if !self.in_scope(scope, &found) {
+                return Ok(false);
+            }
+            self.warn_empty_match(report.path.as_str(), text, &found);
+            expanded.clear();
+            self.expand(template, captures, report, &mut expanded)?;
+            match colored {
+                true => dst.push_str(&format!("{}{}{}", COLOR_INSERTED, expanded, COLOR_RESET)),
+                false => dst.push_str(expanded.as_str()),
+            }
+            self.log_match(text, &mut lines, &found, expanded.as_str(), report);
+            report.matches += 1;
+            report.replacements += 1;
+            report.changed |= expanded != found.as_str();
+            return Ok(true);
+        };
+    }
+
+    /// Logs where a replacement was made under --verbose and adds it to the
+    /// --report, up to --max-logged-matches per file.
+    fn log_match(
+        &self,
+        text: &str,
+        lines: &mut Option<LineIndex>,
+        found: &Group,
+        replacement: &str,
+        report: &mut FileReport,
+    ) {
+        let logging = enabled(Level::Info);
+        if !logging && self.opts.report.is_none() {
+            return;
+        }
+        let limit = self.opts.max_logged_matches.unwrap_or(MAX_LOGGED_MATCHES);
+        if report.replacements as usize >= limit {
+            if report.replacements as usize == limit {
+                infoln!("{}: more matches are not shown", report.path);
+            }
+            return;
+        }
+        let lines = lines.get_or_insert_with(|| LineIndex::new(text));
+        let (line, column) = lines.position(text, found.start());
+        let location = MatchLocation {
+            line,
+            column,
+            offset: found.start(),
+            matched: truncate_preview(found.as_str(), PREVIEW_CHARS),
+            replacement: truncate_preview(replacement, PREVIEW_CHARS),
+        };
+        infoln!(
+            "{}:{}:{}: matched {:?} -> {:?} at byte {}",
+            report.path,
+            location.line,
+            location.column,
+            location.matched,
+            location.replacement,
+            location.offset
+        );
+        if self.opts.report.is_some() {
+            report.locations.push(location);
+        }
+    }
+
+    fn preview_text(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        if self.opts.until_stable || self.replaces_values() {
+            let result = self.process_text(text, template, report)?;
+            let edits: Vec<Edit> = Edit::between(text, result.as_str()).into_iter().collect();
+            return Ok(self.render_preview(report.path.as_str(), text, &edits));
+        }
+        if self.opts.delete_lines || self.insert_mode() {
+            let edits = match self.opts.delete_lines {
+                true => self
+                    .deleted_lines(text, report)?
+                    .into_iter()
+                    .map(|(start, end)| Edit {
+                        start,
+                        end,
+                        replacement: String::new(),
+                    })
+                    .collect::<Vec<Edit>>(),
+                false => self.insert_edits(text, template, report)?,
+            };
+            return Ok(self.render_preview(report.path.as_str(), text, &edits));
+        }
+        let scope = self.scope(text)?;
+        let (output, replaced) =
+            replace_with(self.parsed_opts.regex.as_ref(), text, |captures, dst| {
+                let found = captures.found();
+                if !self.in_scope(&scope, &found) {
+                    return Ok(false);
+                }
+                self.expand(template, captures, report, dst)?;
+                report.matches += 1;
+                report.replacements += 1;
+                return Ok(true);
+            })?;
+        report.changed |= replaced.changed;
+        let edits = replaced
+            .replaced(output.as_str())
+            .map(|(span, replacement)| Edit {
+                start: span.start,
+                end: span.end,
+                replacement: String::from(replacement),
+            })
+            .collect::<Vec<Edit>>();
+        return Ok(self.render_preview(report.path.as_str(), text, &edits));
+    }
+
+    /// Whether --dry-run prints a patch instead of the changed lines.
+    fn patch_format(&self) -> bool {
+        return self.opts.dry_run && self.opts.format.as_deref() == Some("patch");
+    }
+
+    fn render_preview(&self, path: &str, text: &str, edits: &[Edit]) -> String {
+        match self.patch_format() {
+            true => {
+                return render_patch(
+                    patch_path(path).as_str(),
+                    text,
+                    edits,
+                    self.opts.context.unwrap_or(PATCH_CONTEXT),
+                )
+            }
+            false => {
+                return render_hunks(
+                    path,
+                    text,
+                    edits,
+                    self.opts.context.unwrap_or(0),
+                    self.use_color(),
+                    self.preview_width(),
+                )
+            }
+        }
+    }
+
+    /// Stops at the first match whose replacement differs from it.
+    fn would_change(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<bool, CliError> {
+        if self.opts.delete_lines || self.insert_mode() || self.replaces_values() {
+            self.process_text(text, template, report)?;
+            return Ok(report.changed);
+        }
+        let scope = self.scope(text)?;
+        let mut expanded = String::new();
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let captures = captures?;
+            let found = captures.found();
+            if !self.in_scope(&scope, &found) {
+                continue;
+            }
+            expanded.clear();
+            self.expand(template, &captures, report, &mut expanded)?;
+            report.matches += 1;
+            report.replacements += 1;
+            if expanded != found.as_str() {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+
+    fn check_text(
+        &self,
+        text: &str,
+        template: &Template,
+        report: &mut FileReport,
+    ) -> Result<String, CliError> {
+        match self.opts.quiet {
+            true => report.changed = self.would_change(text, template, report)?,
+            false => {
+                self.process_text(text, template, report)?;
+            }
+        }
+        if !report.changed {
+            return Ok(String::new());
+        }
+        if !self.opts.show_matches {
+            return Ok(format!("{}{}", report.path, self.separator()));
+        }
+        let scope = self.scope(text)?;
+        let lines = LineIndex::new(text);
+        let mut output = String::new();
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let found = captures?.found();
+            if !self.in_scope(&scope, &found) {
+                continue;
+            }
+            let line = format!(
+                "{}:{}:{}",
+                report.path,
+                lines.line_of(found.start()) + 1,
+                found.as_str()
+            );
+            match self.preview_width() {
+                Some(width) => output.push_str(&fit(line.as_str(), width)),
+                None => output.push_str(&line),
+            }
+            output.push_str(self.separator());
+        }
+        return Ok(output);
+    }
+
+    fn separator(&self) -> &str {
+        if let Some(separator) = &self.parsed_opts.separator {
+            return separator.as_str();
+        }
+        match self.opts.null || self.opts.null_data {
+            true => return "\0",
+            false => return "\n",
+        }
+    }
+
+    fn validate_group(&self) -> Result<(), CliError> {
+        if let Some(group) = &self.opts.group {
+            if !GroupRef::parse(group).exists(&self.parsed_opts.regex.group_names()) {
+                return Err(CliError::from(format!(
+                    "capture group '{}' does not exist in the pattern",
+                    group
+                )));
+            }
+        }
+        if let Some(format) = &self.parsed_opts.format {
+            let unknown = self.missing_groups(format.groups(), |name| format!("{{{}}}", name));
+            if !unknown.is_empty() {
+                return Err(CliError::from(format!(
+                    "the format refers to groups missing from the pattern: {}",
+                    unknown.join(", ")
+                )));
+            }
+        }
+        return Ok(());
+    }
+
+    fn match_text(&self, text: &str, report: &mut FileReport) -> Result<String, CliError> {
+        if self.opts.split {
+            return self.each_record(text, false, |record| self.split_record(record, report));
+        }
+        return self.each_record(text, false, |record| self.match_record(record, report));
+    }
+
+    fn match_prefix(&self, report: &FileReport) -> String {
+        let many = self.opts.files.len() > 1 || self.opts.recursive;
+        match many && !self.opts.no_filename {
+            true => return format!("{}:", report.path),
+            false => return String::new(),
+        }
+    }
+
+    fn match_record(&self, text: &str, report: &mut FileReport) -> Result<String, CliError> {
+        let prefix = self.match_prefix(report);
+        let scope = self.scope(text)?;
+        let names = self.parsed_opts.regex.group_names();
+        let mut output = String::new();
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let captures = captures?;
+            if !self.in_scope(&scope, &captures.found()) {
+                continue;
+            }
+            report.matches += 1;
+            if self.opts.format_json {
+                render_json(&names, &captures, &mut output);
+                output.push_str(self.separator());
+                continue;
+            }
+            if let Some(format) = &self.parsed_opts.format {
+                output.push_str(prefix.as_str());
+                format.render(&captures, &mut output);
+                output.push_str(self.separator());
+                continue;
+            }
+            let found = match &self.opts.group {
+                Some(group) => GroupRef::parse(group).get(&captures),
+                None => captures.get(0),
+            };
+            if let Some(found) = found {
+                output.push_str(prefix.as_str());
+                output.push_str(found.as_str());
+                output.push_str(self.separator());
+            }
+        }
+        return Ok(output);
+    }
+
+    fn split_record(&self, text: &str, report: &mut FileReport) -> Result<String, CliError> {
+        let prefix = self.match_prefix(report);
+        let fields = split(self.parsed_opts.regex.as_ref(), text, self.opts.keep_empty)?;
+        let selected = fields
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                self.parsed_opts
+                    .fields
+                    .as_ref()
+                    .is_none_or(|fields| fields.contains(index + 1))
+            })
+            .map(|(_, field)| field)
+            .collect::<Vec<&str>>();
+        report.matches += selected.len() as u64;
+        let mut output = String::new();
+        if self.opts.split_json {
+            output.push_str(prefix.as_str());
+            output.push('[');
+            output.push_str(
+                &selected
+                    .iter()
+                    .map(|field| json_string(field))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            );
+            output.push(']');
+            output.push_str(self.separator());
+            return Ok(output);
+        }
+        for field in selected {
+            output.push_str(prefix.as_str());
+            output.push_str(field);
+            output.push_str(self.separator());
+        }
+        return Ok(output);
+    }
+
+    fn print_output(&self) -> bool {
+        return !self.opts.quiet && (self.opts.report.is_none() || self.opts.report_file.is_some());
+    }
+
+    fn open_report(&self) -> Result<Option<Box<dyn Write>>, CliError> {
+        match (&self.opts.report, &self.opts.report_file) {
+            (None, _) => return Ok(None),
+            (Some(_), Some(path)) => return Ok(Some(Box::new(std::fs::File::create(path)?))),
+            (Some(_), None) => return Ok(Some(Box::new(std::io::stdout()))),
+        }
+    }
+
+    fn write_report(
+        &self,
+        writer: &mut Option<Box<dyn Write>>,
+        report: &FileReport,
+    ) -> Result<(), CliError> {
+        if let Some(writer) = writer {
+            writeln!(writer, "{}", report.to_json())?;
+        }
+        return Ok(());
+    }
+
+    fn summary(&self) -> Summary {
+        return Summary {
+            timings: match self.opts.timings {
+                true => Some(Timings::new(self.parsed_opts.compile_time)),
+                false => None,
+            },
+            mtime_skipped: self.parsed_opts.mtime_skipped,
+            ..Summary::default()
+        };
+    }
+
+    fn write_summary(
+        &self,
+        writer: &mut Option<Box<dyn Write>>,
+        summary: &Summary,
+    ) -> Result<(), CliError> {
+        if let Some(writer) = writer {
+            writeln!(writer, "{}", summary.to_json(self.started.elapsed()))?;
+            writer.flush()?;
+        }
+        return Ok(());
+    }
+
+    fn list_mode(&self) -> bool {
+        return self.opts.files_with_matches || self.opts.files_without_match;
+    }
+
+    fn list_text(&self, text: &str, report: &mut FileReport) -> Result<String, CliError> {
+        let scope = self.scope(text)?;
+        let unscoped = self.parsed_opts.occurrence == Occurrence::all();
+        let matched = match unscoped && scope.lines.is_none() && scope.regions.is_none() {
+            true => self.parsed_opts.regex.is_match(text)?,
+            false => {
+                let mut matched = false;
+                for captures in self.parsed_opts.regex.captures_iter(text) {
+                    if self.in_scope(&scope, &captures?.found()) {
+                        matched = true;
+                        break;
+                    }
+                }
+                matched
+            }
+        };
+        report.matches = matched as u64;
+        match matched == self.opts.files_with_matches {
+            true => return Ok(format!("{}{}", report.path, self.separator())),
+            false => return Ok(String::new()),
+        }
+    }
+
+    /// With --paragraph-mode the guards are checked for each paragraph, and
+    /// only a file where none passes them is skipped as a whole.
+    fn file_passes_guards(&self, text: &str) -> bool {
+        match self.opts.paragraph_mode {
+            true => {
+                return paragraphs(text)
+                    .iter()
+                    .any(|(paragraph, _)| !paragraph.is_empty() && self.passes_guards(paragraph))
+            }
+            false => return self.passes_guards(text),
+        }
+    }
+
+    fn passes_guards(&self, text: &str) -> bool {
+        if let Some(guard) = &self.parsed_opts.if_matches {
+            if !guard.is_match(text) {
+                return false;
+            }
+        }
+        if let Some(guard) = &self.parsed_opts.unless_matches {
+            if guard.is_match(text) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Explains why the anchored pattern did not match `text`.
+    fn check_anchored(&self, path: &str, text: &str) -> Result<(), CliError> {
+        let unanchored = match &self.parsed_opts.unanchored {
+            Some(unanchored) => unanchored,
+            None => return Ok(()),
+        };
+        if self.parsed_opts.regex.is_match(text)? {
+            return Ok(());
+        }
+        let reason = match unanchored.captures_iter(text).next().transpose()? {
+            None => String::from("the pattern did not match"),
+            Some(captures) if captures.found().start() > 0 => format!(
+                "the pattern matched bytes {}..{} of {} but does not start at 0",
+                captures.found().start(),
+                captures.found().end(),
+                text.len()
+            ),
+            Some(captures) => format!(
+                "the pattern matched only bytes 0..{} of {}",
+                captures.found().end(),
+                text.len()
+            ),
+        };
+        return Err(CliError::from(format!("{}: {}", path, reason)));
+    }
+
+    fn skip_guarded(&self, path: &str, text: &str) -> Output {
+        infoln!("{}: skipped (guard)", path);
+        let special =
+            self.opts.dry_run || self.list_mode() || self.opts.only_matching || self.opts.check;
+        match !special && (!self.opts.inplace || self.rename_target(path).is_some()) {
+            true => return Output::Emit(String::from(text)),
+            false => return Output::Nothing,
+        }
+    }
+
+    /// Returns where the output for the input `path` goes, or `None` for stdout.
+    fn output_path(&self, path: &str) -> Result<Option<PathBuf>, CliError> {
+        let output = match self.opts.output.as_deref() {
+            None | Some("-") => return Ok(None),
+            Some(output) => Path::new(output),
+        };
+        if self.opts.files.len() <= 1 && !self.opts.recursive {
+            return Ok(Some(output.to_path_buf()));
+        }
+        if output.is_file() {
+            return Err(CliError::from(format!(
+                "{}: --output must be a directory when there are multiple inputs",
+                output.display()
+            )));
+        }
+        // Going by components treats / and \ alike on Windows. Drive letters
+        // and UNC shares become directories so inputs from different volumes
+        // do not collide.
+        let mut target = output.to_path_buf();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => target.push(part),
+                Component::Prefix(prefix) => match prefix.kind() {
+                    Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                        target.push(char::from(letter).to_string())
+                    }
+                    Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                        target.push(server);
+                        target.push(share);
+                    }
+                    Prefix::Verbatim(part) | Prefix::DeviceNS(part) => target.push(part),
+                },
+                Component::ParentDir => {
+                    return Err(CliError::from(format!(
+                        "{}: cannot recreate a path with .. under --output",
+                        path
+                    )))
+                }
+                _ => {}
+            }
+        }
+        return Ok(Some(target));
+    }
+
+    fn write_output(&self, target: &Path, content: &[u8]) -> Result<(), CliError> {
+        self.create_output(target)?.write_all(content)?;
+        return Ok(());
+    }
+
+    /// Creates the --output file `target` and the directories above it.
+    fn create_output(&self, target: &Path) -> Result<std::fs::File, CliError> {
+        if let Ok(canonical) = target.canonicalize() {
+            let inputs = self.inputs.get_or_init(|| {
+                self.opts
+                    .files
+                    .iter()
+                    .filter_map(|file| Path::new(file).canonicalize().ok())
+                    .collect()
+            });
+            if inputs.contains(&canonical) {
+                return Err(CliError::from(format!(
+                    "{}: refusing to overwrite an input file",
+                    target.display()
+                )));
+            }
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        return Ok(std::fs::File::create(target)?);
+    }
+
+    /// Returns the file an in-place edit of `path` replaces: the file a symlink
+    /// points to with `--symlinks follow`, otherwise `path` itself.
+    fn inplace_target(&self, path: &str) -> Result<String, CliError> {
+        let linked =
+            std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink());
+        match linked && self.opts.symlinks == "follow" {
+            true => {
+                let target = std::fs::canonicalize(path)
+                    .map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+                return Ok(target.to_string_lossy().into_owned());
+            }
+            false => return Ok(String::from(path)),
+        }
+    }
+
+    /// Writes the processed `content` of `path` in place, to --output or to stdout.
+    fn emit(&self, path: &str, content: String, out: &mut dyn Write) -> Result<(), CliError> {
+        if !self.opts.inplace && self.rename_target(path).is_none() {
+            match self.output_path(path)? {
+                Some(target) => {
+                    return self.write_output(&target, &self.encoded(path, content.as_str())?)
+                }
+                None => {
+                    if self.print_output() {
+                        if path != "-" {
+                            self.print_file_header(path, out)?;
+                        }
+                        out.write_all(content.as_bytes())?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        let content = self.encoded(path, content.as_str())?;
+        if let Some(journal) = self.journal.get() {
+            journal.record(path, self.rename_target(path).unwrap_or(path), &content)?;
+        }
+        if let Some(target) = self.rename_target(path) {
+            return write_renamed(path, target, &content);
+        }
+        let target = self.inplace_target(path)?;
+        let times = self.saved_times(target.as_str())?;
+        return with_write_access(target.as_str(), || {
+            write_atomic(target.as_str(), &content)?;
+            return self.restore_times(target.as_str(), times);
+        });
+    }
+
+    /// Whether `path` is read and written gzip compressed, see --compressed.
+    fn gzipped(&self, path: &str) -> bool {
+        if path == "-" {
+            return false;
+        }
+        match self.opts.compressed.as_str() {
+            "none" => return false,
+            "auto" if !path.ends_with(".gz") => {
+                let mut magic = [0u8; 2];
+                return std::fs::File::open(path)
+                    .and_then(|mut file| file.read_exact(&mut magic))
+                    .is_ok_and(|_| magic == gzip::MAGIC);
+            }
+            _ => return path.ends_with(".gz"),
+        }
+    }
+
+    /// Returns `content` as it is written to files for the input `path`,
+    /// compressed again when the input was.
+    fn encoded<'a>(&self, path: &str, content: &'a str) -> Result<Cow<'a, [u8]>, CliError> {
+        match self.gzipped(path) {
+            true => {
+                let compressed = Gzip::of(path)?.compress(path, content.as_bytes())?;
+                return Ok(Cow::Owned(compressed));
+            }
+            false => return Ok(Cow::Borrowed(content.as_bytes())),
+        }
+    }
+
+    /// Reads `path`, decoding it according to --invalid-utf8.
+    fn read_text(&self, path: &str) -> Result<Option<String>, CliError> {
+        let mut bytes = std::fs::read(path)?;
+        if self.gzipped(path) {
+            bytes = gzip::decompress(path, &bytes)?;
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => return Ok(Some(text)),
+            Err(error) => {
+                let text = self.decode(path, error.as_bytes(), 0)?;
+                return Ok(text.map(Cow::into_owned));
+            }
+        }
+    }
+
+    /// Decodes `bytes` read from `name` according to --invalid-utf8, or
+    /// returns `None` when the input is to be left untouched. `offset` is
+    /// where `bytes` start in the input.
+    fn decode<'a>(
+        &self,
+        name: &str,
+        bytes: &'a [u8],
+        offset: usize,
+    ) -> Result<Option<Cow<'a, str>>, CliError> {
+        let error = match std::str::from_utf8(bytes) {
+            Ok(text) => return Ok(Some(Cow::Borrowed(text))),
+            Err(error) => error,
+        };
+        let position = offset + error.valid_up_to();
+        match self.opts.invalid_utf8.as_str() {
+            "lossy" => {
+                infoln!(
+                    "{}: invalid UTF-8 at byte {}, replacing it with U+FFFD",
+                    name,
+                    position
+                );
+                return Ok(Some(String::from_utf8_lossy(bytes)));
+            }
+            "skip" => {
+                if !self.opts.quiet {
+                    errorln!(
+                        "warning: {}: skipped, invalid UTF-8 at byte {}",
+                        name,
+                        position
+                    );
+                }
+                return Ok(None);
+            }
+            _ => {
+                return Err(CliError::from(format!(
+                    "{}: invalid UTF-8 at byte {}",
+                    name, position
+                )))
+            }
+        }
+    }
+
+    fn print_file_header(&self, path: &str, out: &mut dyn Write) -> Result<(), CliError> {
+        if self.printed.get() > 0 {
+            if let Some(separator) = &self.parsed_opts.file_separator {
+                out.write_all(separator.as_bytes())?;
+            }
+        }
+        if self.opts.print_filenames {
+            writeln!(out, "==> {} <==", path)?;
+        }
+        self.printed.set(self.printed.get() + 1);
+        return Ok(());
+    }
+
+    fn render_file(&self, path: &str) -> Result<(FileReport, Output), CliError> {
+        let reading = Instant::now();
+        return self
+            .read_text(path)
+            .and_then(|text| -> Result<(FileReport, Output), CliError> {
+                let mut report = FileReport::new(path);
+                report.read = reading.elapsed();
+                let mut text = match text {
+                    Some(text) => text,
+                    None => return Ok((report, Output::Nothing)),
+                };
+                let bom = text.starts_with(BOM);
+                if bom {
+                    text.replace_range(..BOM.len_utf8(), "");
+                }
+                if let Cow::Owned(normalized) = self.normalize_output(text.as_str()) {
+                    text = normalized;
+                }
+                report.size = text.len() as u64;
+                if !self.file_passes_guards(text.as_str()) {
+                    match self.skip_guarded(path, text.as_str()) {
+                        Output::Emit(text) => {
+                            return Ok((report, Output::Emit(self.with_bom(bom, text))))
+                        }
+                        output => return Ok((report, output)),
+                    }
+                }
+                self.check_anchored(path, text.as_str())?;
+                if self.opts.dry_run {
+                    let template = self.file_template(path)?;
+                    let result = self.preview_text(text.as_str(), &template, &mut report)?;
+                    infoln!("{}: previewed", path);
+                    return Ok((report, Output::Print(result)));
+                }
+                if self.opts.check {
+                    let template = self.file_template(path)?;
+                    let result = self.check_text(text.as_str(), &template, &mut report)?;
+                    infoln!("{}: checked", path);
+                    return Ok((report, Output::Print(result)));
+                }
+                if self.list_mode() {
+                    let result = self.list_text(text.as_str(), &mut report)?;
+                    infoln!("{}: listed", path);
+                    return Ok((report, Output::Print(result)));
+                }
+                if self.opts.only_matching {
+                    let result = self.match_text(text.as_str(), &mut report)?;
+                    infoln!("{}: matched", path);
+                    return Ok((report, Output::Print(result)));
+                }
+                let template = self.file_template(path)?;
+                let result = self.process_text(text.as_str(), &template, &mut report);
+                match result {
+                    Ok(result) => {
+                        if self.opts.inplace
+                            && result == text
+                            && self.keeps_bom(bom) == bom
+                            && self.rename_target(path).is_none()
+                        {
+                            infoln!("{}: unchanged", path);
+                            return Ok((report, Output::Unchanged));
+                        }
+                        match self.opts.delete_lines {
+                            true => infoln!("{}: deleted", path),
+                            false => infoln!("{}: replaced", path),
+                        }
+                        return Ok((report, Output::Emit(self.with_bom(bom, result))));
+                    }
+                    Err(error) => {
+                        infoln!("{}: skipped", path);
+                        return Err(error);
+                    }
+                }
+            });
+    }
+
+    /// Returns `text` normalized when --normalize-output asks for it.
+    fn normalize_output<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match (self.parsed_opts.normalize, self.opts.normalize_output) {
+            (Some(form), true) => return form.normalize(text),
+            _ => return Cow::Borrowed(text),
+        }
+    }
+
+    /// Whether the output starts with a byte order mark, given whether the
+    /// input did.
+    fn keeps_bom(&self, bom: bool) -> bool {
+        return (bom && !self.opts.strip_bom) || self.opts.add_bom;
+    }
+
+    /// Keeps a byte order mark at the start of `bytes`, the first read of an
+    /// input processed in pieces, out of matching so that `^` matches after
+    /// it. Writes one to `writer` when it is kept or added, and returns the
+    /// length to skip.
+    fn take_bom(&self, bytes: &[u8], writer: &mut dyn Write) -> Result<usize, CliError> {
+        let skip = match bytes.starts_with(BOM.to_string().as_bytes()) {
+            true => BOM.len_utf8(),
+            false => 0,
+        };
+        if self.keeps_bom(skip > 0) && !self.opts.only_matching && !self.opts.quiet {
+            writer.write_all(BOM.to_string().as_bytes())?;
+        }
+        return Ok(skip);
+    }
+
+    fn with_bom(&self, bom: bool, mut content: String) -> String {
+        if self.keeps_bom(bom) {
+            content.insert(0, BOM);
+        }
+        return content;
+    }
+
+    fn deliver(&self, path: &str, output: Output, out: &mut dyn Write) -> Result<(), CliError> {
+        match output {
+            Output::Nothing => return Ok(()),
+            Output::Print(result) => {
+                if self.print_output() {
+                    out.write_all(result.as_bytes())?;
+                }
+                return Ok(());
+            }
+            Output::Emit(result) => return self.emit(path, result, out),
+            Output::Unchanged => return self.touch(path),
+        }
+    }
+
+    fn saved_times(&self, target: &str) -> Result<Option<Timestamps>, CliError> {
+        match self.opts.preserve_mtime {
+            true => return Ok(Some(Timestamps::of(target)?)),
+            false => return Ok(None),
+        }
+    }
+
+    fn restore_times(&self, target: &str, times: Option<Timestamps>) -> Result<(), CliError> {
+        match times {
+            Some(times) => return times.apply(target),
+            None => return Ok(()),
+        }
+    }
+
+    /// Bumps the times of an in-place file that was left untouched, for --touch.
+    fn touch(&self, path: &str) -> Result<(), CliError> {
+        if !self.opts.touch {
+            return Ok(());
+        }
+        let target = self.inplace_target(path)?;
+        return Timestamps::now().apply(target.as_str());
+    }
+
+    fn process_file(&self, path: &str, out: &mut dyn Write) -> Result<FileReport, CliError> {
+        if self.opts.symlinks == "skip"
+            && std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            infoln!("{}: skipped (symlink)", path);
+            return Ok(FileReport::new(path));
+        }
+        if !self.opts.allow_special {
+            if let Ok(meta) = std::fs::metadata(path) {
+                if !meta.is_file() && !meta.is_dir() {
+                    if !self.opts.quiet {
+                        errorln!(
+                            "warning: {}: skipped (not a regular file), use --allow-special to read it",
+                            path
+                        );
+                    }
+                    return Ok(FileReport::new(path));
+                }
+            }
+        }
+        if self.opts.inplace && !self.opts.force {
+            let target = self.inplace_target(path)?;
+            if std::fs::metadata(&target).is_ok_and(|meta| meta.permissions().readonly()) {
+                return Err(CliError::from(format!(
+                    "{} is read-only (use --force to override)",
+                    path
+                )));
+            }
+        }
+        if self.opts.line_mode {
+            return self.process_file_lines(path, out);
+        }
+        if let Some(limit) = self.parsed_opts.max_filesize {
+            let size = std::fs::metadata(path)
+                .map_err(|error| CliError::from(format!("{}: {}", path, error)))?
+                .len();
+            if size > limit {
+                return self.process_large_file(path, size, limit, out);
+            }
+        }
+        if self.use_mmap(path) {
+            if let Some(report) = self.process_mapped(path, out)? {
+                return Ok(report);
+            }
+        }
+        let stamp = match self.opts.inplace && !self.opts.overwrite_changed {
+            true => Some(FileStamp::of(path)?),
+            false => None,
+        };
+        let (mut report, output) = match self.parsed_opts.match_timeout {
+            Some(timeout) => self.render_file_within(path, timeout)?,
+            None => self.render_file(path)?,
+        };
+        if let (Some(stamp), Output::Emit(_)) = (&stamp, &output) {
+            if !stamp.unchanged(path) {
+                return Err(CliError::from(format!(
+                    "{}: file changed during processing, use --overwrite-changed to write it anyway",
+                    path
+                )));
+            }
+        }
+        if let Output::Emit(_) = &output {
+            if !self.accept_change(path, &report)? {
+                return Ok(report);
+            }
+        }
+        let writing = Instant::now();
+        self.deliver(path, output, out)?;
+        report.write = writing.elapsed();
+        return Ok(report);
+    }
+
+    /// Whether the replacements in `path` may be written. With
+    /// --max-change-ratio, in-place edits that rewrite too much of the file
+    /// are skipped, asked about or let through as --on-excessive-change says.
+    fn accept_change(&self, path: &str, report: &FileReport) -> Result<bool, CliError> {
+        let limit = match self.opts.max_change_ratio {
+            Some(limit) if self.opts.inplace => limit,
+            _ => return Ok(true),
+        };
+        let ratio = match report.size {
+            0 => 0.0,
+            size => report.changed_bytes as f64 / size as f64,
+        };
+        if ratio <= limit {
+            return Ok(true);
+        }
+        let change = format!(
+            "the replacement rewrites {:.0}% of its bytes, over --max-change-ratio {}",
+            ratio * 100.0,
+            limit
+        );
+        match self.opts.on_excessive_change.as_str() {
+            "allow" => {
+                infoln!("{}: {}, writing it anyway", path, change);
+                return Ok(true);
+            }
+            "prompt" if std::io::stdin().is_terminal() => {
+                crate::error!("{}: {}. Write it anyway? [y/N] ", path, change);
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                let answer = answer.trim().to_ascii_lowercase();
+                return Ok(answer == "y" || answer == "yes");
+            }
+            "prompt" => {
+                if !self.opts.quiet {
+                    errorln!(
+                        "warning: {}: skipped, {}; cannot ask as stdin is not a terminal",
+                        path,
+                        change
+                    );
+                }
+                return Ok(false);
+            }
+            _ => {
+                if !self.opts.quiet {
+                    errorln!("warning: {}: skipped, {}", path, change);
+                }
+                return Ok(false);
+            }
+        }
+    }
+
+    fn process_file_lines(&self, path: &str, out: &mut dyn Write) -> Result<FileReport, CliError> {
+        infoln!("{}: processing by line", path);
+        let file = std::fs::File::open(path)
+            .map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+        let reader: Box<dyn BufRead> = match self.gzipped(path) {
+            true => Box::new(BufReader::new(gzip::decoder(BufReader::new(file)))),
+            false => Box::new(BufReader::new(file)),
+        };
+        let template = self.file_template(path)?;
+        let mut report = FileReport::new(path);
+        self.write_streamed(path, out, |writer| {
+            let changed = self.process_lines(path, &template, reader, writer, &mut report)?;
+            return Ok(changed && self.accept_change(path, &report)?);
+        })?;
+        return Ok(report);
+    }
+
+    /// Runs the replacement on each line of `reader` on its own, without its
+    /// terminator, and writes the result to `writer` as soon as the line is
+    /// done. Lines longer than --pump-limit are still read whole so that `^`
+    /// and `$` keep their meaning. Returns whether anything changed.
+    fn process_lines<R: BufRead>(
+        &self,
+        path: &str,
+        template: &Template,
+        mut reader: R,
+        writer: &mut dyn Write,
+        report: &mut FileReport,
+    ) -> Result<bool, CliError> {
+        let name = match path {
+            "-" => "stdin",
+            path => path,
+        };
+        let wrap = |error: std::io::Error| match error.kind() {
+            std::io::ErrorKind::BrokenPipe => CliError::from(error),
+            _ => CliError::from(format!("{}: {}", name, error)),
+        };
+        let mut line: Vec<u8> = Vec::new();
+        let mut offset = 0;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).map_err(wrap)? == 0 {
+                break;
+            }
+            if line.len() > self.parsed_opts.pump_limit {
+                debugln!(
+                    "{}: line at byte {} is {} bytes, over --pump-limit",
+                    name,
+                    offset,
+                    line.len()
+                );
+            }
+            let end = match (line.ends_with(b"\r\n"), line.ends_with(b"\n")) {
+                (true, _) => line.len() - 2,
+                (false, true) => line.len() - 1,
+                (false, false) => line.len(),
+            };
+            let start = match offset {
+                0 => self.take_bom(&line, writer)?,
+                _ => 0,
+            };
+            report.size += line.len() as u64;
+            match self.decode(name, &line[start..end], offset + start)? {
+                Some(text) => {
+                    let text = self.normalize_output(&text);
+                    match self.opts.only_matching {
+                        true => writer.write_all(self.match_text(&text, report)?.as_bytes()),
+                        false => writer
+                            .write_all(self.process_text(&text, template, report)?.as_bytes())
+                            .and_then(|_| writer.write_all(&line[end..])),
+                    }
+                }
+                None => writer.write_all(&line[start..]),
+            }
+            .map_err(wrap)?;
+            offset += line.len();
+        }
+        writer.flush().map_err(wrap)?;
+        return Ok(report.changed);
+    }
+
+    fn process_large_file(
+        &self,
+        path: &str,
+        size: u64,
+        limit: u64,
+        out: &mut dyn Write,
+    ) -> Result<FileReport, CliError> {
+        let mut report = FileReport::new(path);
+        report.large = true;
+        match self.opts.large_files.as_str() {
+            "stream" => {}
+            "error" => {
+                let error = CliError::from(format!(
+                    "{}: {} bytes is over --max-filesize of {}",
+                    path, size, limit
+                ));
+                errorln!("{}", error);
+                report.error = Some(format!("{}", error));
+                return Ok(report);
+            }
+            _ => {
+                infoln!(
+                    "{}: skipped, {} bytes is over --max-filesize of {}",
+                    path,
+                    size,
+                    limit
+                );
+                return Ok(report);
+            }
+        }
+        if !self.can_stream()
+            || self.journal.get().is_some()
+            || self.renaming()
+            || self.opts.invalid_utf8 != "error"
+        {
+            return Err(CliError::from(format!(
+                "{}: {} bytes is over --max-filesize and cannot be streamed with these options",
+                path, size
+            )));
+        }
+        infoln!("{}: streaming {} bytes", path, size);
+        let file = std::fs::File::open(path)
+            .map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+        let reader: Box<dyn Read> = match self.gzipped(path) {
+            true => Box::new(gzip::decoder(BufReader::new(file))),
+            false => Box::new(file),
+        };
+        let template = self.file_template(path)?;
+        let mut replacements = 0;
+        self.write_streamed(path, out, |writer| {
+            replacements = self
+                .stream_through(&template, reader, writer)
+                .map_err(|error| match error.is_broken_pipe() {
+                    true => error,
+                    false => CliError::from(format!("{}: {}", path, error)),
+                })?;
+            return Ok(replacements > 0);
+        })?;
+        report.matches = replacements;
+        report.replacements = replacements;
+        report.changed = replacements > 0;
+        return Ok(report);
+    }
+
+    /// Hands `write` the destination for `path`: a temporary file that replaces
+    /// it with --inplace when `write` reports a change, or stdout.
+    fn write_streamed<F>(&self, path: &str, out: &mut dyn Write, write: F) -> Result<(), CliError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<bool, CliError>,
+    {
+        if !self.opts.inplace {
+            match self.print_output() {
+                true => {
+                    self.print_file_header(path, out)?;
+                    write(out)?
+                }
+                false => write(&mut std::io::sink())?,
+            };
+            return Ok(());
+        }
+        let target = self.inplace_target(path)?;
+        let times = self.saved_times(target.as_str())?;
+        let changed = with_write_access(target.as_str(), || {
+            let changed = self.write_replacing(path, &target, write)?;
+            if changed {
+                self.restore_times(target.as_str(), times)?;
+            }
+            return Ok(changed);
+        })?;
+        match changed {
+            true => return Ok(()),
+            false => return self.touch(path),
+        }
+    }
+
+    fn write_replacing<F>(&self, path: &str, target: &str, write: F) -> Result<bool, CliError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<bool, CliError>,
+    {
+        let mut temporary = Path::new(target).as_os_str().to_owned();
+        temporary.push(".rp-tmp");
+        let temporary = PathBuf::from(temporary);
+        let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", path, error));
+        let gzip = match self.gzipped(path) {
+            true => Some(Gzip::of(path)?),
+            false => None,
+        };
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&temporary).map_err(wrap)?);
+        let written = match &gzip {
+            Some(gzip) => {
+                let mut encoder = gzip.encoder(&mut writer);
+                write(&mut encoder).and_then(|changed| {
+                    encoder.try_finish()?;
+                    return Ok(changed);
+                })
+            }
+            None => write(&mut writer),
+        };
+        let written = written.and_then(|changed| {
+            writer.flush()?;
+            drop(writer);
+            if !changed {
+                std::fs::remove_file(&temporary)?;
+                return Ok(false);
+            }
+            let permissions = std::fs::metadata(target)?.permissions();
+            std::fs::set_permissions(&temporary, permissions)?;
+            std::fs::rename(&temporary, target)?;
+            return Ok(true);
+        });
+        if written.is_err() {
+            std::fs::remove_file(&temporary).ok();
+        }
+        return written;
+    }
+
+    fn use_mmap(&self, path: &str) -> bool {
+        if !cfg!(any(unix, windows))
+            || self.opts.no_mmap
+            || !self.can_stream()
+            || self.journal.get().is_some()
+            || self.renaming()
+            || self.gzipped(path)
+        {
+            return false;
+        }
+        return self.opts.mmap
+            || (self.opts.match_timeout.is_none()
+                && std::fs::metadata(path).is_ok_and(|meta| meta.len() >= MMAP_THRESHOLD));
+    }
+
+    /// Replaces in `path` through a read-only memory map rather than reading
+    /// it into memory, or returns `None` when it cannot be mapped.
+    ///
+    /// The map is only safe while no other process writes to the file. That
+    /// cannot be prevented, but a change of length is caught before the
+    /// result is used.
+    #[cfg(any(unix, windows))]
+    fn process_mapped(
+        &self,
+        path: &str,
+        out: &mut dyn Write,
+    ) -> Result<Option<FileReport>, CliError> {
+        let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", path, error));
+        let file = std::fs::File::open(path).map_err(wrap)?;
+        let map = match unsafe { memmap2::Mmap::map(&file) } {
+            Ok(map) => map,
+            Err(error) => {
+                infoln!("{}: cannot be mapped ({}), reading it instead", path, error);
+                return Ok(None);
+            }
+        };
+        let text = match std::str::from_utf8(&map) {
+            Ok(text) if text.starts_with(BOM) => return Ok(None),
+            Ok(text) => text,
+            Err(_) if self.opts.invalid_utf8 != "error" => return Ok(None),
+            Err(error) => {
+                return Err(CliError::from(format!(
+                    "{}: invalid UTF-8 at byte {}",
+                    path,
+                    error.valid_up_to()
+                )))
+            }
+        };
+        infoln!("{}: mapped {} bytes", path, text.len());
+        let template = self.file_template(path)?;
+        let mut report = FileReport::new(path);
+        self.write_streamed(path, out, |writer| {
+            let mut expanded = String::new();
+            let mut last = 0;
+            for captures in self.parsed_opts.regex.captures_iter(text) {
+                let captures = captures?;
+                let found = captures.found();
+                expanded.clear();
+                self.expand(&template, &captures, &report, &mut expanded)?;
+                writer.write_all(&text.as_bytes()[last..found.start()])?;
+                writer.write_all(expanded.as_bytes())?;
+                last = found.end();
+                report.matches += 1;
+                report.replacements += 1;
+                report.changed |= expanded != found.as_str();
+            }
+            writer.write_all(&text.as_bytes()[last..])?;
+            if file.metadata().map_err(wrap)?.len() != map.len() as u64 {
+                return Err(CliError::from(format!(
+                    "{}: changed while it was being processed",
+                    path
+                )));
+            }
+            return Ok(report.changed);
+        })?;
+        return Ok(Some(report));
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn process_mapped(
+        &self,
+        _path: &str,
+        _out: &mut dyn Write,
+    ) -> Result<Option<FileReport>, CliError> {
+        return Ok(None);
+    }
+
+    /// Renders `path` on a worker thread and abandons it after `timeout`.
+    /// Nothing is written by the worker, so an abandoned file is left
+    /// untouched. The worker cannot be stopped, though, and keeps matching
+    /// until it is done or rp exits.
+    fn render_file_within(
+        &self,
+        path: &str,
+        timeout: Duration,
+    ) -> Result<(FileReport, Output), CliError> {
+        let worker = self.clone();
+        let owned = String::from(path);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let rendered = worker.render_file(owned.as_str());
+            sender.send((rendered, worker.counted.get())).ok();
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok((rendered, counted)) => {
+                self.counted.set(counted);
+                return rendered;
+            }
+            Err(_) => {
+                return Err(CliError::from(format!(
+                    "{}: matching did not finish within {:?}",
+                    path, timeout
+                )))
+            }
+        }
+    }
+
+    fn renaming(&self) -> bool {
+        return self.opts.rename || self.opts.rename_path;
+    }
+
+    fn rename_target(&self, path: &str) -> Option<&str> {
+        return self
+            .renames
+            .get()
+            .and_then(|renames| renames.get(path))
+            .map(|target| target.as_str());
+    }
+
+    fn rename_text(&self, text: &str, template: &Template) -> Result<String, CliError> {
+        let mut report = FileReport::new(text);
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for captures in self.parsed_opts.regex.captures_iter(text) {
+            let captures = captures?;
+            let found = captures.found();
+            result.push_str(&text[last..found.start()]);
+            self.expand(template, &captures, &report, &mut result)?;
+            report.replacements += 1;
+            last = found.end();
+        }
+        result.push_str(&text[last..]);
+        return Ok(result);
+    }
+
+    /// Works out every rename up front so that collisions are reported before
+    /// any file is touched.
+    fn rename_plan(&self) -> Result<Vec<(String, String)>, CliError> {
+        let mut plan: Vec<(String, String)> = Vec::new();
+        // Paths compare by component, so a/b and a\b are one target on Windows.
+        let mut sources: HashMap<PathBuf, String> = HashMap::new();
+        for path in self.opts.files.iter() {
+            let template = self.file_template(path.as_str())?;
+            let target = match self.opts.rename_path {
+                true => self.rename_text(path.as_str(), &template)?,
+                false => {
+                    let file = Path::new(path);
+                    let name = file.file_name().unwrap_or_default().to_string_lossy();
+                    let renamed = self.rename_text(name.as_ref(), &template)?;
+                    match renamed == name {
+                        true => path.clone(),
+                        false => file.with_file_name(renamed).to_string_lossy().into_owned(),
+                    }
+                }
+            };
+            if target == *path {
+                continue;
+            }
+            if target.is_empty() || target.ends_with(std::path::is_separator) {
+                return Err(CliError::from(format!(
+                    "{}: would be renamed to an empty name",
+                    path
+                )));
+            }
+            if let Some(other) = sources.insert(PathBuf::from(&target), path.clone()) {
+                return Err(CliError::from(format!(
+                    "{} and {} would both be renamed to {}",
+                    other, path, target
+                )));
+            }
+            if Path::new(&target).exists() {
+                return Err(CliError::from(format!(
+                    "{}: cannot rename to {} which already exists",
+                    path, target
+                )));
+            }
+            plan.push((path.clone(), target));
+        }
+        return Ok(plan);
+    }
+
+    fn rename_files(&self) -> Result<Summary, CliError> {
+        let plan = self.rename_plan()?;
+        let mut summary = self.summary();
+        if self.opts.dry_run {
+            let stdout = std::io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            for (source, target) in plan.iter() {
+                writeln!(out, "{} -> {}", source, target)?;
+            }
+            out.flush()?;
+            summary.changed = plan.len() as u64;
+            return Ok(summary);
+        }
+        self.renames.get_or_init(|| plan.iter().cloned().collect());
+        if self.opts.inplace {
+            return self.process_files();
+        }
+        let mut reporter = self.open_report()?;
+        for (source, target) in plan.iter() {
+            infoln!("Renaming: {} => {}", source, target);
+            let mut report = FileReport::new(source.as_str());
+            if let Err(error) = rename_file(source.as_str(), target.as_str()) {
+                errorln!("{}", error);
+                report = FileReport::failed(source.as_str(), &error);
+            }
+            report.changed = report.error.is_none();
+            summary.add(&report);
+            self.write_report(&mut reporter, &report)?;
+        }
+        self.write_summary(&mut reporter, &summary)?;
+        return Ok(summary);
+    }
+
+    fn process_files(&self) -> Result<Summary, CliError> {
+        let mut reporter = self.open_report()?;
+        let mut summary = self.summary();
+        if self.parsed_opts.max_filesize.is_some() {
+            summary.large_policy = Some(self.opts.large_files.clone());
+        }
+        let stdout = std::io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        let mut cache = self.open_cache();
+        for path in self.opts.files.iter() {
+            if interrupted() {
+                summary.interrupted = true;
+                break;
+            }
+            if let Some(cache) = &mut cache {
+                if cache.is_cache(path.as_str()) {
+                    debugln!("{}: skipped (the --cache file)", path);
+                    continue;
+                }
+                if cache.fresh(path.as_str()) {
+                    debugln!("{}: skipped (no matches when cached)", path);
+                    continue;
+                }
+            }
+            let processing = Instant::now();
+            let report = match self.process_file(path.as_str(), &mut out) {
+                Ok(mut report) => {
+                    report.elapsed = processing.elapsed();
+                    report
+                }
+                Err(error) if error.is_broken_pipe() => return Err(error),
+                Err(error) => {
+                    let error = error.in_file(path.as_str());
+                    errorln!("{}", error);
+                    FileReport::failed(path.as_str(), &error)
+                }
+            };
+            if let Some(cache) = &mut cache {
+                if report.matches == 0 && !report.changed && !report.large && report.error.is_none()
+                {
+                    cache.insert(path.as_str());
+                }
+            }
+            summary.add(&report);
+            self.write_report(&mut reporter, &report)?;
+        }
+        out.flush()?;
+        if let Some(cache) = &cache {
+            cache.save()?;
+            summary.cache = Some(CacheCounts {
+                hits: cache.hits,
+                misses: cache.misses,
+            });
+        }
+        self.write_summary(&mut reporter, &summary)?;
+        return Ok(summary);
+    }
+
+    fn cache_path(&self) -> Option<&str> {
+        match self.opts.no_cache {
+            true => return None,
+            false => return self.opts.cache.as_deref(),
+        }
+    }
+
+    /// Opens --cache for rules hashed from everything that may decide whether
+    /// a file matches: the version, the pattern and the options, leaving out
+    /// the files, the mode and those that only change what is printed.
+    fn open_cache(&self) -> Option<Cache> {
+        let path = self.cache_path()?;
+        let mut opts = self.opts.clone();
+        opts.files.clear();
+        opts.cache = None;
+        opts.newer_than = None;
+        opts.older_than = None;
+        opts.inplace = false;
+        opts.check = false;
+        opts.dry_run = false;
+        opts.journal = None;
+        opts.quiet = false;
+        opts.stats = false;
+        opts.timings = false;
+        opts.verbose = 0;
+        opts.log_timestamps = false;
+        opts.log_file = None;
+        opts.report = None;
+        opts.report_file = None;
+        opts.color = String::new();
+        opts.width = None;
+        let rules = format!(
+            "{}\n{}\n{:?}\n{:?}",
+            env!("CARGO_PKG_VERSION"),
+            self.parsed_opts.pattern,
+            self.parsed_opts.mapping,
+            opts
+        );
+        return Some(Cache::open(path, checksum(rules.as_bytes()).as_str()));
+    }
+
+    fn can_stream(&self) -> bool {
+        return (!self.opts.only_matching || self.opts.extract.is_some())
+            && !self.list_mode()
+            && self.opts.replace_cmd.is_none()
+            && self.parsed_opts.mapping.is_none()
+            && self.parsed_opts.line_ranges.is_empty()
+            && self.opts.per_line_limit.is_none()
+            && self.parsed_opts.within.is_none()
+            && self.parsed_opts.occurrence == Occurrence::all()
+            && !self.opts.delete_lines
+            && !self.insert_mode()
+            && self.parsed_opts.if_matches.is_none()
+            && self.parsed_opts.unless_matches.is_none()
+            && !self.opts.dry_run
+            && !self.opts.check
+            && !self.opts.anchored
+            && self
+                .opts
+                .output
+                .as_deref()
+                .is_none_or(|output| output == "-")
+            && self.opts.report.is_none()
+            && !self.opts.strip_bom
+            && !self.opts.add_bom
+            && self.parsed_opts.normalize.is_none()
+            && !self.opts.until_stable
+            && !self.opts.paragraph_mode
+            && !self.replaces_values()
+            && self.opts.max_change_ratio.is_none()
+            && !self.use_color();
+    }
+
+    /// Streams `reader` to `writer`, replacing matches or extracting a group
+    /// from them with --extract.
+    fn stream_through<R: Read, W: Write>(
+        &self,
+        template: &Template,
+        reader: R,
+        writer: W,
+    ) -> Result<u64, CliError> {
+        let engine = self.parsed_opts.regex.as_ref();
+        let window = self.parsed_opts.pump_limit;
+        let (bom, reader) = skip_bom(reader)?;
+        let mut writer = writer;
+        if self.keeps_bom(bom) && self.opts.extract.is_none() {
+            writer.write_all(BOM.to_string().as_bytes())?;
+        }
+        match &self.opts.extract {
+            Some(group) => {
+                return extract_stream(
+                    engine,
+                    &GroupRef::parse(group),
+                    self.separator(),
+                    reader,
+                    writer,
+                    window,
+                )
+            }
+            None => return replace_stream_template(engine, template, reader, writer, window),
+        }
+    }
+
+    fn stream_stdin(&self) -> Result<Summary, CliError> {
+        infoln!("Streaming stdin");
+        let stdout = std::io::stdout();
+        let writer: Box<dyn Write> = match self.opts.quiet {
+            true => Box::new(std::io::sink()),
+            false => Box::new(BufWriter::new(stdout.lock())),
+        };
+        let replacements = self
+            .stream_through(&self.stdin_template()?, std::io::stdin().lock(), writer)
+            .map_err(|error| match error.is_broken_pipe() {
+                true => error,
+                false => CliError::from(format!("stdin: {}", error)),
+            })?;
+        let mut summary = self.summary();
+        let mut report = FileReport::new("-");
+        report.matches = replacements;
+        report.replacements = replacements;
+        report.changed = replacements > 0;
+        summary.add(&report);
+        return Ok(summary);
+    }
+
+    fn line_buffered(&self) -> bool {
+        return self.opts.line_buffered
+            || (std::io::stdout().is_terminal()
+                && !self.opts.dry_run
+                && !self.opts.check
+                && !self.list_mode()
+                && self.parsed_opts.line_ranges.is_empty()
+                && self.parsed_opts.within.is_none()
+                && self.parsed_opts.if_matches.is_none()
+                && self.parsed_opts.unless_matches.is_none()
+                && !self.opts.until_stable
+                && !self.opts.split
+                && !self.opts.paragraph_mode
+                && !self.replaces_values()
+                && self.opts.report.is_none()
+                && !self.anchors_text());
+    }
+
+    /// Processes stdin as each batch of complete lines arrives, so the pattern
+    /// only sees the lines read together. A line longer than the pump limit is
+    /// processed in pieces.
+    fn stream_lines(&self) -> Result<Summary, CliError> {
+        infoln!("Streaming stdin by line");
+        let template = self.stdin_template()?;
+        let mut report = FileReport::new("-");
+        let stdin = std::io::stdin();
+        let mut reader = stdin.lock();
+        let stdout = std::io::stdout();
+        let mut writer = BufWriter::new(stdout.lock());
+        let mut pending: Vec<u8> = Vec::new();
+        let mut offset = 0;
+        loop {
+            let read = reader.fill_buf()?;
+            let eof = read.is_empty();
+            pending.extend_from_slice(read);
+            let len = read.len();
+            reader.consume(len);
+            let end = match pending.iter().rposition(|byte| *byte == b'\n') {
+                Some(newline) => newline + 1,
+                None if eof => pending.len(),
+                // A character cut by the limit waits for the rest of its
+                // bytes.
+                None if pending.len() >= self.parsed_opts.pump_limit => {
+                    match std::str::from_utf8(&pending) {
+                        Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                        _ => pending.len(),
+                    }
+                }
+                None => continue,
+            };
+            if end == 0 && !eof {
+                continue;
+            }
+            traceln!("processing {} of {} buffered bytes", end, pending.len());
+            let start = match offset {
+                0 => self.take_bom(&pending[..end], &mut writer)?,
+                _ => 0,
+            };
+            let result = match self.decode("stdin", &pending[start..end], offset + start)? {
+                Some(text) => {
+                    let text = self.normalize_output(&text);
+                    match self.opts.only_matching {
+                        true => Some(self.match_text(&text, &mut report)?),
+                        false => Some(self.process_text(&text, &template, &mut report)?),
+                    }
+                }
+                None => None,
+            };
+            if !self.opts.quiet {
+                match &result {
+                    Some(result) => writer.write_all(result.as_bytes())?,
+                    None => writer.write_all(&pending[start..end])?,
+                }
+                writer.flush()?;
+            }
+            pending.drain(..end);
+            offset += end;
+            if eof {
+                break;
+            }
+        }
+        let mut summary = self.summary();
+        summary.add(&report);
+        return Ok(summary);
+    }
+
+    /// Processes stdin one NUL terminated record at a time with --null-data.
+    fn stream_records(&self) -> Result<Summary, CliError> {
+        infoln!("Streaming stdin by record");
+        let template = self.stdin_template()?;
+        let mut report = FileReport::new("-");
+        let stdin = std::io::stdin();
+        let mut reader = stdin.lock();
+        let stdout = std::io::stdout();
+        let mut writer = BufWriter::new(stdout.lock());
+        let mut record: Vec<u8> = Vec::new();
+        let mut offset = 0;
+        loop {
+            record.clear();
+            if reader.read_until(b'\0', &mut record)? == 0 {
+                break;
+            }
+            traceln!("processing a record of {} bytes", record.len());
+            let start = match offset {
+                0 => self.take_bom(&record, &mut writer)?,
+                _ => 0,
+            };
+            let result = match self.decode("stdin", &record[start..], offset + start)? {
+                Some(text) => {
+                    let text = self.normalize_output(&text);
+                    match self.opts.only_matching {
+                        true => Some(self.match_text(&text, &mut report)?),
+                        false => Some(self.process_text(&text, &template, &mut report)?),
+                    }
+                }
+                None => None,
+            };
+            if !self.opts.quiet {
+                match &result {
+                    Some(result) => writer.write_all(result.as_bytes())?,
+                    None => writer.write_all(&record[start..])?,
+                }
+                writer.flush()?;
+            }
+            offset += record.len();
+        }
+        let mut summary = self.summary();
+        summary.add(&report);
+        return Ok(summary);
+    }
+
+    /// Whether stdin read in full can be written out as it is replaced
+    /// rather than after.
+    fn writes_through(&self) -> bool {
+        return !self.opts.dry_run
+            && !self.opts.check
+            && !self.opts.only_matching
+            && !self.list_mode()
+            && !self.opts.until_stable
+            && !self.opts.null_data
+            && !self.opts.paragraph_mode
+            && !self.opts.delete_lines
+            && !self.insert_mode()
+            && !self.replaces_values();
+    }
+
+    /// Replaces within `text` read from stdin, writing the result to --output
+    /// or `out` as each match is replaced.
+    fn write_stdin(
+        &self,
+        text: &str,
+        bom: bool,
+        template: &Template,
+        report: &mut FileReport,
+        out: &mut dyn Write,
+    ) -> Result<(), CliError> {
+        let mut file = match self.output_path("-")? {
+            Some(target) => Some(BufWriter::new(self.create_output(&target)?)),
+            None => None,
+        };
+        let mut sink = std::io::sink();
+        let writer: &mut dyn Write = match &mut file {
+            Some(file) => file,
+            None if self.print_output() => out,
+            None => &mut sink,
+        };
+        if self.keeps_bom(bom) {
+            writer.write_all(BOM.to_string().as_bytes())?;
+        }
+        self.write_record(text, template, report, writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    fn process_stdin(&self) -> Result<Summary, CliError> {
+        if self.opts.line_mode {
+            infoln!("Reading stdin by line");
+            let template = self.stdin_template()?;
+            let mut report = FileReport::new("-");
+            let stdout = std::io::stdout();
+            let mut writer: Box<dyn Write> = match self.opts.quiet {
+                true => Box::new(std::io::sink()),
+                false => Box::new(BufWriter::new(stdout.lock())),
+            };
+            let stdin = std::io::stdin();
+            self.process_lines("-", &template, stdin.lock(), writer.as_mut(), &mut report)?;
+            let mut summary = self.summary();
+            summary.add(&report);
+            return Ok(summary);
+        }
+        if self.opts.null_data && (self.line_buffered() || self.can_stream()) {
+            return self.stream_records();
+        }
+        if self.line_buffered() {
+            return self.stream_lines();
+        }
+        if self.can_stream() && self.opts.invalid_utf8 == "error" {
+            return self.stream_stdin();
+        }
+        infoln!("Reading stdin");
+        let mut bytes = Vec::new();
+        let mut reporter = self.open_report()?;
+        let mut summary = self.summary();
+        let stdout = std::io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+
+        match std::io::stdin().read_to_end(&mut bytes) {
+            Ok(_) => {
+                let mut report = FileReport::new("-");
+                let mut text = match self.decode("stdin", &bytes, 0)? {
+                    Some(text) => text.into_owned(),
+                    None => {
+                        if self.output_path("-")?.is_none() && self.print_output() {
+                            out.write_all(&bytes)?;
+                            out.flush()?;
+                        }
+                        summary.add(&report);
+                        self.write_report(&mut reporter, &report)?;
+                        self.write_summary(&mut reporter, &summary)?;
+                        return Ok(summary);
+                    }
+                };
+                let bom = text.starts_with(BOM);
+                if bom {
+                    text.replace_range(..BOM.len_utf8(), "");
+                }
+                if let Cow::Owned(normalized) = self.normalize_output(text.as_str()) {
+                    text = normalized;
+                }
+                if !self.file_passes_guards(text.as_str()) {
+                    let output = match self.skip_guarded("-", text.as_str()) {
+                        Output::Emit(text) => Output::Emit(self.with_bom(bom, text)),
+                        output => output,
+                    };
+                    self.deliver("-", output, &mut out)?;
+                    out.flush()?;
+                    summary.add(&report);
+                    self.write_report(&mut reporter, &report)?;
+                    self.write_summary(&mut reporter, &summary)?;
+                    return Ok(summary);
+                }
+                self.check_anchored("-", text.as_str())?;
+                let template = self.stdin_template()?;
+                if self.writes_through() {
+                    self.write_stdin(text.as_str(), bom, &template, &mut report, &mut out)?;
+                    summary.add(&report);
+                    self.write_report(&mut reporter, &report)?;
+                    self.write_summary(&mut reporter, &summary)?;
+                    return Ok(summary);
+                }
+                let result = match (self.opts.only_matching, self.list_mode()) {
+                    _ if self.opts.dry_run => {
+                        self.preview_text(text.as_str(), &template, &mut report)
+                    }
+                    _ if self.opts.check => self.check_text(text.as_str(), &template, &mut report),
+                    (true, _) => self.match_text(text.as_str(), &mut report),
+                    (_, true) => self.list_text(text.as_str(), &mut report),
+                    _ => self
+                        .process_text(text.as_str(), &template, &mut report)
+                        .map(|result| self.with_bom(bom, result)),
+                };
+                let result = result?;
+                match self.output_path("-")? {
+                    Some(target) => self.write_output(&target, result.as_bytes())?,
+                    None => {
+                        if self.print_output() {
+                            out.write_all(result.as_bytes())?;
+                            out.flush()?;
+                        }
+                    }
+                }
+                summary.add(&report);
+                self.write_report(&mut reporter, &report)?;
+            }
+            Err(error) => return Err(CliError::from(error)),
+        }
+        self.write_summary(&mut reporter, &summary)?;
+        return Ok(summary);
+    }
+
+    /// Returns the references in `groups` to groups the pattern does not
+    /// have, formatted with `reference`.
+    fn missing_groups<F>(&self, groups: Vec<&GroupRef>, reference: F) -> Vec<String>
+    where
+        F: Fn(String) -> String,
+    {
+        let names = self.parsed_opts.regex.group_names();
+        return missing_groups(&names, groups)
+            .into_iter()
+            .map(reference)
+            .collect();
+    }
+
+    /// Finds unbraced references like `$1foo` that name a missing group while
+    /// starting with one that exists, and suggests the braced form.
+    fn check_ambiguous_references(&self) -> Result<(), CliError> {
+        if self.opts.no_expand_groups {
+            return self.check_literal_replacement();
+        }
+        let names: Vec<String> = self
+            .parsed_opts
+            .regex
+            .group_names()
+            .into_iter()
+            .flatten()
+            .collect();
+        for reference in unbraced_references(self.parsed_opts.replacement.as_str()) {
+            if names.iter().any(|name| name == reference) {
+                continue;
+            }
+            if let Some((_, braced)) = braced_suggestion(reference, &names) {
+                let message = format!(
+                    "${} refers to a group named {}; did you mean {}?",
+                    reference, reference, braced
+                );
+                match self.opts.strict_replacement {
+                    true => return Err(CliError::from(message)),
+                    false if self.opts.quiet => {}
+                    false => errorln!("warning: {}", message),
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Warns when a replacement written as it is looks like it meant to refer
+    /// to a group.
+    fn check_literal_replacement(&self) -> Result<(), CliError> {
+        if self.parsed_opts.replacement.contains("${") && !self.opts.quiet {
+            errorln!("warning: the replacement contains ${{, which --no-expand-groups writes as it is instead of expanding");
+        }
+        return Ok(());
+    }
+
+    fn watch_paths(&self) -> Vec<String> {
+        match self.opts.recursive {
+            true => {
+                return walk(
+                    &self.parsed_opts.roots,
+                    self.opts.hidden,
+                    self.opts.no_ignore,
+                    self.opts.follow_links,
+                    &self.parsed_opts.types,
+                )
+                .0
+            }
+            false => return self.parsed_opts.roots.clone(),
+        }
+    }
+
+    fn watch(&self) -> Result<(), CliError> {
+        install_handler()?;
+        let mut poller = Poller::new(&self.watch_paths());
+        while !interrupted() {
+            std::thread::sleep(WATCH_INTERVAL);
+            let mut changed = poller.changed(&self.watch_paths());
+            if changed.is_empty() {
+                continue;
+            }
+            loop {
+                std::thread::sleep(WATCH_DEBOUNCE);
+                let more = poller.changed(&self.watch_paths());
+                if more.is_empty() {
+                    break;
+                }
+                changed.extend(more);
+            }
+            changed.sort();
+            changed.dedup();
+            infoln!("re-running on {} changed files", changed.len());
+            let stdout = std::io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            for path in changed.iter() {
+                match self.process_file(path.as_str(), &mut out) {
+                    Err(error) if error.is_broken_pipe() => return Err(error),
+                    Err(error) => errorln!("{}", error.in_file(path.as_str())),
+                    Ok(_) => {}
+                }
+                poller.touch(path.as_str());
+            }
+            out.flush()?;
+        }
+        return Ok(());
+    }
+
+    /// Checks that the pattern, the replacement and the options go together
+    /// before any input is read.
+    fn validate(&self) -> Result<(), CliError> {
+        self.validate_group()?;
+        self.check_ambiguous_references()?;
+        self.check_empty_match();
+        self.check_line_mode()?;
+        self.check_per_line_limit()?;
+        self.check_line_buffered();
+        self.file_template("-")?;
+        if self.opts.watch && self.parsed_opts.roots.is_empty() {
+            return Err(CliError::from("--watch needs files to watch"));
+        }
+        if self.cache_path().is_some()
+            && !(self.opts.inplace || self.opts.check || self.opts.dry_run)
+        {
+            return Err(CliError::from(
+                "--cache needs --inplace, --check or --dry-run, since other modes print files without matches",
+            ));
+        }
+        return Ok(());
+    }
+
+    fn process_pattern(&self) -> Result<i32, CliError> {
+        self.validate()
+            .map_err(|error| error.or_class(ErrorClass::Usage))?;
+        if self.opts.inplace {
+            install_handler()?;
+        }
+        if let Some(path) = &self.opts.journal {
+            let command = std::env::args().collect::<Vec<String>>().join(" ");
+            let journal = Journal::create(path.as_str(), command.as_str())?;
+            self.journal.get_or_init(|| journal);
+        }
+        let summary = match !self.opts.files.is_empty() || self.opts.recursive {
+            true if self.renaming() => self.rename_files(),
+            true => self.process_files(),
+            false if self.renaming() => {
+                Err(CliError::from("--rename needs files to rename").with_class(ErrorClass::Usage))
+            }
+            false => self.process_stdin(),
+        };
+        if let Ok(summary) = &summary {
+            if (self.opts.stats || self.opts.verbose > 0) && !self.opts.quiet {
+                errorln!("{}", summary.to_line(self.started.elapsed()));
+            }
+            if let Some(timings) = summary.timings.as_ref().filter(|_| !self.opts.quiet) {
+                crate::error!("{}", timings.to_lines());
+            }
+        }
+        if self.opts.check {
+            match summary {
+                Ok(summary) if summary.errors > 0 => return Ok(self.error_status(&summary)),
+                Ok(summary) if summary.changed > 0 => return Ok(1),
+                Ok(_) => return Ok(0),
+                Err(error) => {
+                    errorln!("{}", error);
+                    return Ok(error.exit_code());
+                }
+            }
+        }
+        let summary = summary?;
+        if summary.interrupted {
+            errorln!(
+                "interrupted: {} of {} files completed",
+                summary.files,
+                self.opts.files.len()
+            );
+            return Ok(130);
+        }
+        if self.opts.watch {
+            self.watch()?;
+        }
+        if summary.errors > 0 {
+            return Ok(self.error_status(&summary));
+        }
+        let listed = match self.opts.files_without_match {
+            true => summary.files - summary.matches - summary.errors,
+            false => summary.matches,
+        };
+        match (self.opts.only_matching || self.list_mode() || self.opts.exit_status) && listed == 0
+        {
+            true => return Ok(EXIT_NO_MATCH),
+            false => return Ok(0),
+        }
+    }
+
+    /// The exit status of a run in which some files failed.
+    fn error_status(&self, summary: &Summary) -> i32 {
+        match summary.errors < summary.files {
+            true => return EXIT_PARTIAL,
+            false => return EXIT_IO,
+        }
+    }
+
+    pub fn run(&self) -> Result<i32, CliError> {
+        if self.opts.type_list {
+            let list = type_list(&self.opts.type_add)?;
+            std::io::stdout().lock().write_all(list.as_bytes())?;
+            return Ok(0);
+        }
+        if self.opts.escape {
+            self.escape_pattern()?;
+            return Ok(0);
+        }
+        if self.opts.escape_replacement {
+            self.escape_replacement_text()?;
+            return Ok(0);
+        }
+        if let Some(path) = &self.opts.undo {
+            undo(path.as_str(), self.opts.force)?;
+            return Ok(0);
+        }
+        if self.opts.explain {
+            let explained = explain(
+                self.parsed_opts.pattern.as_str(),
+                self.parsed_opts.flags.as_str(),
+            )?;
+            std::io::stdout().lock().write_all(explained.as_bytes())?;
+            return Ok(0);
+        }
+        if self.opts.test {
+            return self.test_sample();
         }
+        return self.process_pattern();
     }
 }
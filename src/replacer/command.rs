@@ -0,0 +1,104 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::{Engine, Groups};
+use super::error::*;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `command` for a single match and returns its stdout with one trailing
+/// newline removed. The match is written to the command's stdin and exposed,
+/// along with every participating group, through `RP_MATCH`, `RP_GROUP_<N>`
+/// and `RP_GROUP_<name>`. Without `shell` the command is split on whitespace.
+pub fn run_replacement(
+    command: &str,
+    shell: bool,
+    engine: &dyn Engine,
+    captures: &Groups,
+) -> Result<String, CliError> {
+    let mut process = match shell {
+        true => {
+            let mut process = Command::new("sh");
+            process.arg("-c").arg(command);
+            process
+        }
+        false => {
+            let mut words = command.split_whitespace();
+            let program = match words.next() {
+                Some(program) => program,
+                None => return Err(CliError::from("empty replacement command")),
+            };
+            let mut process = Command::new(program);
+            process.args(words);
+            process
+        }
+    };
+    let found = captures.get(0).unwrap().as_str();
+    process.env("RP_MATCH", found);
+    for (index, name) in engine.group_names().into_iter().enumerate() {
+        if let Some(group) = captures.get(index) {
+            process.env(format!("RP_GROUP_{}", index), group.as_str());
+            if let Some(name) = name {
+                process.env(format!("RP_GROUP_{}", name), group.as_str());
+            }
+        }
+    }
+
+    let mut child = process
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|error| CliError::from(format!("{}: {}", command, error)))?;
+    let input = String::from(found);
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    if let Ok(Err(error)) = writer.join() {
+        if error.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(CliError::from(error));
+        }
+    }
+    if !output.status.success() {
+        return Err(CliError::from(format!(
+            "{}: exited with {}",
+            command, output.status
+        )));
+    }
+
+    let mut replacement = match String::from_utf8(output.stdout) {
+        Ok(replacement) => replacement,
+        Err(_) => {
+            return Err(CliError::from(format!(
+                "{}: output is not valid UTF-8",
+                command
+            )))
+        }
+    };
+    if replacement.ends_with('\n') {
+        replacement.pop();
+        if replacement.ends_with('\r') {
+            replacement.pop();
+        }
+    }
+    return Ok(replacement);
+}
@@ -0,0 +1,231 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::util::read_file;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_CONFIG: &str = ".rp.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+/// A `key = value` line of a config file, with where it came from for messages.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub path: String,
+    pub line: usize,
+}
+
+impl Entry {
+    pub fn invalid(&self, expected: &str) -> CliError {
+        return CliError::from(format!(
+            "{}:{}: {} must be {}",
+            self.path, self.line, self.key, expected
+        ));
+    }
+
+    pub fn string(&self) -> Result<String, CliError> {
+        match &self.value {
+            Value::String(value) => return Ok(value.clone()),
+            Value::Integer(value) => return Ok(value.to_string()),
+            _ => return Err(self.invalid("a string")),
+        }
+    }
+
+    pub fn integer(&self) -> Result<i64, CliError> {
+        match &self.value {
+            Value::Integer(value) => return Ok(*value),
+            _ => return Err(self.invalid("an integer")),
+        }
+    }
+
+    pub fn bool(&self) -> Result<bool, CliError> {
+        match &self.value {
+            Value::Bool(value) => return Ok(*value),
+            _ => return Err(self.invalid("true or false")),
+        }
+    }
+
+    pub fn strings(&self) -> Result<Vec<String>, CliError> {
+        match &self.value {
+            Value::Array(values) => return Ok(values.clone()),
+            Value::String(value) => return Ok(vec![value.clone()]),
+            _ => return Err(self.invalid("a string or an array of strings")),
+        }
+    }
+}
+
+/// Default options read from the user and project config files. Later files
+/// take precedence over earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub entries: Vec<Entry>,
+}
+
+impl Config {
+    /// Loads `~/.config/rp/config.toml` (or under `$XDG_CONFIG_HOME`) and the
+    /// nearest `.rp.toml` in the current directory or its ancestors.
+    pub fn discover() -> Result<Config, CliError> {
+        let mut config = Config::default();
+        if let Some(path) = user_config() {
+            if path.is_file() {
+                config.load(&path)?;
+            }
+        }
+        if let Some(path) = project_config() {
+            config.load(&path)?;
+        }
+        return Ok(config);
+    }
+
+    pub fn load(&mut self, path: &Path) -> Result<(), CliError> {
+        let text = read_file(path.to_string_lossy().as_ref())
+            .map_err(|error| CliError::from(format!("{}: {}", path.display(), error)))?;
+        let name = path.display().to_string();
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid =
+                |reason: &str| CliError::from(format!("{}:{}: {}", name, index + 1, reason));
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(invalid("expected key = value")),
+            };
+            if key.is_empty() {
+                return Err(invalid("missing key"));
+            }
+            let value = parse_value(value).ok_or_else(|| invalid("invalid value"))?;
+            let entry = Entry {
+                key: String::from(key.trim_matches('"')),
+                value,
+                path: name.clone(),
+                line: index + 1,
+            };
+            self.entries.retain(|other| other.key != entry.key);
+            self.entries.push(entry);
+        }
+        return Ok(());
+    }
+}
+
+fn user_config() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(base) if !base.is_empty() => PathBuf::from(base),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    return Some(base.join("rp").join("config.toml"));
+}
+
+fn project_config() -> Option<PathBuf> {
+    let current = std::env::current_dir().ok()?;
+    return current
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.is_file());
+}
+
+/// Removes a `#` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('"'), '\\') => escaped = true,
+            (Some(open), c) if c == open => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    return line;
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    match value {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items.strip_suffix(']')?.trim();
+        let mut values = Vec::new();
+        let mut rest = items;
+        while !rest.is_empty() {
+            let (item, len) = parse_string(rest)?;
+            values.push(item);
+            rest = rest[len..].trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(next) => next.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return None,
+            };
+        }
+        return Some(Value::Array(values));
+    }
+    if value.starts_with('"') || value.starts_with('\'') {
+        let (item, len) = parse_string(value)?;
+        return match len == value.len() {
+            true => Some(Value::String(item)),
+            false => None,
+        };
+    }
+    return value
+        .replace('_', "")
+        .parse::<i64>()
+        .ok()
+        .map(Value::Integer);
+}
+
+/// Parses a basic `"..."` string with backslash escapes or a literal `'...'`
+/// string, returning it and the length consumed.
+fn parse_string(text: &str) -> Option<(String, usize)> {
+    let quote = text.chars().next()?;
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            c if c == quote => return Some((value, index + 1)),
+            '\\' if quote == '"' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                other => value.push(other),
+            },
+            c => value.push(c),
+        }
+    }
+    return None;
+}
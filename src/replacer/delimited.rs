@@ -0,0 +1,157 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use std::borrow::Cow;
+
+const QUOTE: char = '"';
+
+/// How records of delimited data are written: the character between fields
+/// and, with `csv`, whether fields may be quoted to hold delimiters, quotes
+/// and newlines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub csv: bool,
+}
+
+/// A field of a record as a byte span of the text, including its quotes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Field {
+    pub start: usize,
+    pub end: usize,
+    pub quoted: bool,
+}
+
+/// The fields of one record. Its line terminator belongs to no field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub fields: Vec<Field>,
+}
+
+impl Dialect {
+    /// Splits `text` into records ending at newlines outside of quotes. A
+    /// `\r` before the newline is left out of the last field.
+    pub fn records(&self, text: &str) -> Result<Vec<Record>, CliError> {
+        let mut records = Vec::new();
+        let mut fields = Vec::new();
+        let mut chars = text.char_indices().peekable();
+        let mut start = 0;
+        let mut quoted = false;
+        loop {
+            let next = chars.next();
+            match next {
+                Some((index, QUOTE)) if self.csv && index == start => {
+                    let mut closed = false;
+                    while let Some((_, c)) = chars.next() {
+                        if c != QUOTE {
+                            continue;
+                        }
+                        match chars.peek() {
+                            Some((_, QUOTE)) => {
+                                chars.next();
+                            }
+                            _ => {
+                                closed = true;
+                                break;
+                            }
+                        }
+                    }
+                    if !closed {
+                        return Err(CliError::from(format!(
+                            "unterminated quoted field at byte {}",
+                            index
+                        )));
+                    }
+                    match chars.peek() {
+                        Some((_, c)) if *c != self.delimiter && *c != '\n' && *c != '\r' => {
+                            return Err(CliError::from(format!(
+                                "unexpected {:?} after quoted field at byte {}",
+                                c, index
+                            )))
+                        }
+                        _ => quoted = true,
+                    }
+                }
+                Some((index, c)) if c == self.delimiter => {
+                    fields.push(Field {
+                        start,
+                        end: index,
+                        quoted,
+                    });
+                    start = index + c.len_utf8();
+                    quoted = false;
+                }
+                Some((index, '\n')) => {
+                    let end = match text[..index].ends_with('\r') && index > start {
+                        true => index - 1,
+                        false => index,
+                    };
+                    fields.push(Field { start, end, quoted });
+                    records.push(Record {
+                        fields: std::mem::take(&mut fields),
+                    });
+                    start = index + 1;
+                    quoted = false;
+                }
+                Some(_) => {}
+                None => {
+                    if start < text.len() || !fields.is_empty() {
+                        fields.push(Field {
+                            start,
+                            end: text.len(),
+                            quoted,
+                        });
+                        records.push(Record { fields });
+                    }
+                    return Ok(records);
+                }
+            }
+        }
+    }
+
+    /// The value of `field` in `text`, without quotes and with doubled quotes
+    /// made single.
+    pub fn value<'t>(&self, text: &'t str, field: &Field) -> Cow<'t, str> {
+        let raw = &text[field.start..field.end];
+        match field.quoted {
+            true => {
+                let inner = &raw[1..raw.len() - 1];
+                match inner.contains(QUOTE) {
+                    true => return Cow::Owned(inner.replace("\"\"", "\"")),
+                    false => return Cow::Borrowed(inner),
+                }
+            }
+            false => return Cow::Borrowed(raw),
+        }
+    }
+
+    /// Writes `value` back as a field, quoted when it was before or, with
+    /// `csv`, when it now holds a delimiter, quote or newline.
+    pub fn encode<'v>(&self, value: &'v str, quoted: bool) -> Cow<'v, str> {
+        let needs_quotes = self.csv && value.contains([self.delimiter, QUOTE, '\n', '\r']);
+        match quoted || needs_quotes {
+            true => return Cow::Owned(format!("\"{}\"", value.replace('"', "\"\""))),
+            false => return Cow::Borrowed(value),
+        }
+    }
+}
@@ -0,0 +1,558 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::normalize::{Form, Normalized};
+use regex::RegexBuilder;
+use std::sync::{Arc, Mutex};
+
+/// A match or capture group found by any engine.
+#[derive(Debug, Clone, Copy)]
+pub struct Group<'t> {
+    start: usize,
+    end: usize,
+    value: &'t str,
+}
+
+impl<'t> Group<'t> {
+    pub fn start(&self) -> usize {
+        return self.start;
+    }
+
+    pub fn end(&self) -> usize {
+        return self.end;
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        return self.value;
+    }
+}
+
+impl<'t> From<regex::Match<'t>> for Group<'t> {
+    fn from(found: regex::Match<'t>) -> Self {
+        return Group {
+            start: found.start(),
+            end: found.end(),
+            value: found.as_str(),
+        };
+    }
+}
+
+#[cfg(feature = "fancy")]
+impl<'t> From<fancy_regex::Match<'t>> for Group<'t> {
+    fn from(found: fancy_regex::Match<'t>) -> Self {
+        return Group {
+            start: found.start(),
+            end: found.end(),
+            value: found.as_str(),
+        };
+    }
+}
+
+/// The capture groups of a single match, whichever engine found it.
+#[derive(Debug)]
+pub enum Groups<'t> {
+    Regex(regex::Captures<'t>),
+    #[cfg(feature = "fancy")]
+    Fancy(fancy_regex::Captures<'t, str>),
+    /// Byte spans of each group, for engines without a borrowed captures type.
+    Spans {
+        text: &'t str,
+        spans: Vec<Option<(usize, usize)>>,
+        names: Arc<Vec<Option<String>>>,
+    },
+}
+
+impl<'t> Groups<'t> {
+    pub fn get(&self, index: usize) -> Option<Group<'t>> {
+        match self {
+            Groups::Regex(captures) => return captures.get(index).map(Group::from),
+            #[cfg(feature = "fancy")]
+            Groups::Fancy(captures) => return captures.get(index).map(Group::from),
+            Groups::Spans { text, spans, .. } => {
+                return spans
+                    .get(index)
+                    .copied()
+                    .flatten()
+                    .map(|(start, end)| Group {
+                        start,
+                        end,
+                        value: &text[start..end],
+                    })
+            }
+        }
+    }
+
+    pub fn name(&self, name: &str) -> Option<Group<'t>> {
+        match self {
+            Groups::Regex(captures) => return captures.name(name).map(Group::from),
+            #[cfg(feature = "fancy")]
+            Groups::Fancy(captures) => return captures.name(name).map(Group::from),
+            Groups::Spans { names, .. } => {
+                match names
+                    .iter()
+                    .position(|other| other.as_deref() == Some(name))
+                {
+                    Some(index) => return self.get(index),
+                    None => return None,
+                }
+            }
+        }
+    }
+
+    /// Returns the whole match.
+    pub fn found(&self) -> Group<'t> {
+        return self.get(0).unwrap();
+    }
+}
+
+/// The operations the file and stream plumbing needs from a regex engine.
+pub trait Engine: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the first match at or after `start`, with the text before
+    /// `start` still visible to anchors and lookaround.
+    fn captures_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Groups<'t>>, CliError>;
+
+    /// Returns the name of every group, indexed by group number.
+    fn group_names(&self) -> Vec<Option<String>>;
+
+    fn is_match(&self, text: &str) -> Result<bool, CliError> {
+        return Ok(self.captures_at(text, 0)?.is_some());
+    }
+
+    /// Returns the engine itself when it matches a normalized copy of the
+    /// text, so that iteration normalizes the text only once.
+    fn normalizing(&self) -> Option<&Normalizing> {
+        return None;
+    }
+}
+
+impl<'e> dyn Engine + 'e {
+    pub fn captures_iter<'t>(&'e self, text: &'t str) -> CapturesIter<'e, 't> {
+        return CapturesIter::new(self, text);
+    }
+}
+
+impl Engine for regex::Regex {
+    fn name(&self) -> &'static str {
+        return "regex";
+    }
+
+    fn captures_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Groups<'t>>, CliError> {
+        return Ok(regex::Regex::captures_at(self, text, start).map(Groups::Regex));
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        return self
+            .capture_names()
+            .map(|name| name.map(String::from))
+            .collect();
+    }
+
+    fn is_match(&self, text: &str) -> Result<bool, CliError> {
+        return Ok(regex::Regex::is_match(self, text));
+    }
+}
+
+#[cfg(feature = "fancy")]
+impl Engine for fancy_regex::Regex {
+    fn name(&self) -> &'static str {
+        return "fancy";
+    }
+
+    fn captures_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Groups<'t>>, CliError> {
+        return self
+            .captures_from_pos(text, start)
+            .map(|captures| captures.map(Groups::Fancy))
+            .map_err(|error| CliError::from(format!("fancy engine: {}", error)));
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        return self
+            .capture_names()
+            .map(|name| name.map(String::from))
+            .collect();
+    }
+}
+
+/// PCRE2 only matches bytes, so the group names are kept alongside it to build
+/// `Groups::Spans`.
+#[cfg(feature = "pcre2")]
+#[derive(Debug)]
+pub struct Pcre2 {
+    regex: pcre2::bytes::Regex,
+    names: Arc<Vec<Option<String>>>,
+}
+
+#[cfg(feature = "pcre2")]
+impl Engine for Pcre2 {
+    fn name(&self) -> &'static str {
+        return "pcre2";
+    }
+
+    fn captures_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Groups<'t>>, CliError> {
+        let mut locations = self.regex.capture_locations();
+        let found = self
+            .regex
+            .captures_read_at(&mut locations, text.as_bytes(), start)
+            .map_err(|error| CliError::from(format!("pcre2 engine: {}", error)))?;
+        if found.is_none() {
+            return Ok(None);
+        }
+        let spans = (0..locations.len())
+            .map(|index| locations.get(index))
+            .collect();
+        return Ok(Some(Groups::Spans {
+            text,
+            spans,
+            names: self.names.clone(),
+        }));
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        return self.names.as_ref().clone();
+    }
+}
+
+/// A match with the span it has in the text that was searched, which differs
+/// from its groups when the engine matched a normalized copy.
+type Located<'t> = (Groups<'t>, (usize, usize));
+
+/// Matches a Unicode normalized copy of the text with another engine and
+/// reports the spans of the original text that the matches came from.
+#[derive(Debug)]
+pub struct Normalizing {
+    engine: Arc<dyn Engine>,
+    form: Form,
+    names: Arc<Vec<Option<String>>>,
+    /// The last text `captures_at` was called with and its normalized copy,
+    /// since callers step through one text match by match.
+    last: Mutex<Option<Arc<(String, Normalized)>>>,
+}
+
+impl Normalizing {
+    pub fn new(engine: Arc<dyn Engine>, form: Form) -> Normalizing {
+        let names = Arc::new(engine.group_names());
+        return Normalizing {
+            engine,
+            form,
+            names,
+            last: Mutex::new(None),
+        };
+    }
+
+    /// Returns the normalized copy of `text`, reusing the last one when the
+    /// text is the same.
+    fn normalized(&self, text: &str) -> Arc<(String, Normalized)> {
+        let mut last = self.last.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(cached) = last.as_ref() {
+            if cached.0 == text {
+                return cached.clone();
+            }
+        }
+        let normalized = Arc::new((String::from(text), Normalized::new(text, self.form)));
+        *last = Some(normalized.clone());
+        return normalized;
+    }
+
+    /// Returns the first match at or after `start` of the normalized text,
+    /// with its groups mapped onto `text`, and the span of the match in the
+    /// normalized text.
+    fn captures_mapped<'t>(
+        &self,
+        text: &'t str,
+        normalized: &Normalized,
+        start: usize,
+    ) -> Result<Option<Located<'t>>, CliError> {
+        let captures = match self.engine.captures_at(normalized.text.as_str(), start)? {
+            Some(captures) => captures,
+            None => return Ok(None),
+        };
+        let spans = (0..self.names.len())
+            .map(|index| {
+                captures
+                    .get(index)
+                    .map(|group| normalized.original_span(group.start(), group.end()))
+            })
+            .collect();
+        let found = captures.found();
+        let groups = Groups::Spans {
+            text,
+            spans,
+            names: self.names.clone(),
+        };
+        return Ok(Some((groups, (found.start(), found.end()))));
+    }
+}
+
+impl Engine for Normalizing {
+    fn name(&self) -> &'static str {
+        return self.engine.name();
+    }
+
+    fn captures_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Groups<'t>>, CliError> {
+        let cached = self.normalized(text);
+        let normalized = &cached.1;
+        let start = normalized.normalized_offset(start);
+        let found = self.captures_mapped(text, normalized, start)?;
+        return Ok(found.map(|(captures, _)| captures));
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        return self.names.as_ref().clone();
+    }
+
+    fn is_match(&self, text: &str) -> Result<bool, CliError> {
+        return self.engine.is_match(self.form.normalize(text).as_ref());
+    }
+
+    fn normalizing(&self) -> Option<&Normalizing> {
+        return Some(self);
+    }
+}
+
+/// Iterates over successive non-overlapping matches. Like the regex crate, an
+/// empty match directly after the previous match is skipped.
+pub struct CapturesIter<'e, 't> {
+    engine: &'e dyn Engine,
+    text: &'t str,
+    /// The normalized text that is matched instead, for `Normalizing`. `at`
+    /// and `last` are offsets in it then.
+    normalized: Option<(&'e Normalizing, Normalized)>,
+    at: usize,
+    last: Option<usize>,
+}
+
+impl<'e, 't> CapturesIter<'e, 't> {
+    pub fn new(engine: &'e dyn Engine, text: &'t str) -> CapturesIter<'e, 't> {
+        let normalized = engine
+            .normalizing()
+            .map(|engine| (engine, Normalized::new(text, engine.form)));
+        return CapturesIter {
+            engine,
+            text,
+            normalized,
+            at: 0,
+            last: None,
+        };
+    }
+
+    fn next_match(&self) -> Result<Option<Located<'t>>, CliError> {
+        match &self.normalized {
+            Some((engine, normalized)) => {
+                return engine.captures_mapped(self.text, normalized, self.at)
+            }
+            None => {
+                let captures = self.engine.captures_at(self.text, self.at)?;
+                return Ok(captures.map(|captures| {
+                    let found = captures.found();
+                    return (captures, (found.start(), found.end()));
+                }));
+            }
+        }
+    }
+}
+
+impl<'e, 't> Iterator for CapturesIter<'e, 't> {
+    type Item = Result<Groups<'t>, CliError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let haystack = match &self.normalized {
+            Some((_, normalized)) => normalized.text.as_str(),
+            None => self.text,
+        };
+        while self.at <= haystack.len() {
+            let (captures, (start, end)) = match self.next_match() {
+                Ok(Some(found)) => found,
+                Ok(None) => break,
+                Err(error) => {
+                    self.at = haystack.len() + 1;
+                    return Some(Err(error));
+                }
+            };
+            let empty = start == end;
+            self.at = match empty {
+                true => next_char_boundary(haystack, end),
+                false => end,
+            };
+            if empty && self.last == Some(end) {
+                continue;
+            }
+            self.last = Some(end);
+            return Some(Ok(captures));
+        }
+        self.at = haystack.len() + 1;
+        return None;
+    }
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index + 1;
+    while index < text.len() && !text.is_char_boundary(index) {
+        index += 1;
+    }
+    return index;
+}
+
+#[cfg(any(not(feature = "fancy"), not(feature = "pcre2")))]
+fn unavailable(engine: &str) -> CliError {
+    return CliError::from(format!(
+        "the {} engine is not available; rebuild rp with --features {}",
+        engine, engine
+    ))
+    .with_class(ErrorClass::Usage);
+}
+
+/// PCRE2's builder has no swap-greed option, so `U` is applied inline.
+#[cfg(feature = "pcre2")]
+fn ungreedy(pattern: &str, flags: &str) -> String {
+    match flags.contains('U') {
+        true => return format!("(?U){}", pattern),
+        false => return String::from(pattern),
+    }
+}
+
+/// Bounds on the memory used by a compiled pattern, in bytes. Only the regex
+/// engine and the parts fancy delegates to it honor them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeLimits {
+    pub regex: usize,
+    pub dfa: usize,
+}
+
+impl Default for SizeLimits {
+    /// The defaults of --regex-size-limit and --dfa-size-limit.
+    fn default() -> SizeLimits {
+        return SizeLimits {
+            regex: 10 << 20,
+            dfa: 2 << 20,
+        };
+    }
+}
+
+/// Compiles `pattern` with the named engine, applying `[imsxU]` flags. Without
+/// `unicode`, classes like `\w` and case folding only cover ASCII.
+pub fn compile(
+    engine: &str,
+    pattern: &str,
+    flags: &str,
+    unicode: bool,
+    limits: SizeLimits,
+) -> Result<Arc<dyn Engine>, CliError> {
+    let rejected = |error: &dyn std::fmt::Display| {
+        CliError::from(format!(
+            "the {} engine rejected the pattern: {}",
+            engine, error
+        ))
+        .with_class(ErrorClass::Pattern)
+    };
+    match engine {
+        "regex" => {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(flags.contains('i'))
+                .multi_line(flags.contains('m'))
+                .dot_matches_new_line(flags.contains('s'))
+                .ignore_whitespace(flags.contains('x'))
+                .swap_greed(flags.contains('U'))
+                .unicode(unicode)
+                .size_limit(limits.regex)
+                .dfa_size_limit(limits.dfa)
+                .build()
+                .map_err(|error| match error {
+                    regex::Error::CompiledTooBig(limit) => CliError::from(format!(
+                        "the compiled pattern exceeds {} bytes, raise --regex-size-limit if it is meant to be this large",
+                        limit
+                    ))
+                    .with_class(ErrorClass::Pattern),
+                    error => rejected(&error),
+                })?;
+            return Ok(Arc::new(regex));
+        }
+        #[cfg(feature = "fancy")]
+        "fancy" => {
+            if flags.contains('U') {
+                return Err(rejected(&"the U flag is not supported"));
+            }
+            let regex = fancy_regex::RegexBuilder::new(pattern)
+                .case_insensitive(flags.contains('i'))
+                .multi_line(flags.contains('m'))
+                .dot_matches_new_line(flags.contains('s'))
+                .ignore_whitespace(flags.contains('x'))
+                .unicode_mode(unicode)
+                .delegate_size_limit(limits.regex)
+                .delegate_dfa_size_limit(limits.dfa)
+                .build()
+                .map_err(|error| rejected(&error))?;
+            return Ok(Arc::new(regex));
+        }
+        #[cfg(feature = "pcre2")]
+        "pcre2" => {
+            let regex = pcre2::bytes::RegexBuilder::new()
+                .utf(true)
+                .ucp(unicode)
+                .jit_if_available(true)
+                .caseless(flags.contains('i'))
+                .multi_line(flags.contains('m'))
+                .dotall(flags.contains('s'))
+                .extended(flags.contains('x'))
+                .build(ungreedy(pattern, flags).as_str())
+                .map_err(|error| rejected(&error))?;
+            let names = Arc::new(regex.capture_names().to_vec());
+            return Ok(Arc::new(Pcre2 { regex, names }));
+        }
+        #[cfg(not(feature = "fancy"))]
+        "fancy" => return Err(unavailable(engine)),
+        #[cfg(not(feature = "pcre2"))]
+        "pcre2" => return Err(unavailable(engine)),
+        _ => {
+            return Err(
+                CliError::from(format!("unknown engine: {}", engine)).with_class(ErrorClass::Usage)
+            )
+        }
+    }
+}
+
+#[cfg(all(test, feature = "normalize"))]
+mod tests {
+    use super::*;
+
+    fn spans(engine: &dyn Engine, text: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut at = 0;
+        while let Some(captures) = engine.captures_at(text, at).unwrap() {
+            let found = captures.found();
+            spans.push((found.start(), found.end()));
+            at = found.end();
+        }
+        return spans;
+    }
+
+    #[test]
+    fn captures_at_follows_a_changed_text() {
+        let regex = compile("regex", "\u{e9}", "", true, SizeLimits::default()).unwrap();
+        let engine = Normalizing::new(regex, Form::Nfc);
+        assert_eq!(spans(&engine, "e\u{301}x\u{e9}"), vec![(0, 3), (4, 6)]);
+        assert_eq!(spans(&engine, "\u{e9}e\u{301}"), vec![(0, 2), (2, 5)]);
+        assert_eq!(spans(&engine, "e\u{301}x\u{e9}"), vec![(0, 3), (4, 6)]);
+    }
+}
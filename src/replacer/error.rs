@@ -20,48 +20,117 @@
 *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 *   SOFTWARE.
 */
-use std::sync::atomic::{AtomicBool, Ordering};
-
-static DEBUG: AtomicBool = AtomicBool::new(false);
-
-pub fn set_debug(debug: bool) {
-    DEBUG.store(debug, Ordering::SeqCst);
-}
-
-pub fn get_debug() -> bool {
-    return DEBUG.load(Ordering::SeqCst);
+/// The exit statuses of rp besides 0 for success.
+pub const EXIT_NO_MATCH: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_PATTERN: i32 = 3;
+pub const EXIT_IO: i32 = 4;
+pub const EXIT_PARTIAL: i32 = 5;
+
+/// What kind of failure an error is, which decides the exit status.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    /// Options that are wrong or do not go together.
+    Usage,
+    /// A pattern the engine cannot compile.
+    Pattern,
+    /// Reading or writing an input or output failed.
+    Io,
 }
 
 #[derive(Debug, Clone)]
 pub struct CliError {
     msg: String,
+    kind: Option<std::io::ErrorKind>,
+    class: Option<ErrorClass>,
+    /// Whether `in_file` has put a path in front of the message.
+    in_file: bool,
+}
+
+impl CliError {
+    /// Whether the error is a write to a closed pipe, e.g. `rp ... | head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        return self.kind == Some(std::io::ErrorKind::BrokenPipe);
+    }
+
+    pub fn with_class(mut self, class: ErrorClass) -> CliError {
+        self.class = Some(class);
+        return self;
+    }
+
+    /// Sets the class unless the error already has one.
+    pub fn or_class(mut self, class: ErrorClass) -> CliError {
+        self.class = self.class.or(Some(class));
+        return self;
+    }
+
+    /// Names `path` at the start of the message unless it is there already,
+    /// so that errors about one of many files can be told apart.
+    pub fn in_file(mut self, path: &str) -> CliError {
+        if !self.in_file && !self.msg.starts_with(&format!("{}: ", path)) {
+            self.msg = format!("{}: {}", path, self.msg);
+        }
+        self.in_file = true;
+        return self;
+    }
+
+    /// The exit status for the error. Errors without a class happened while
+    /// processing an input and count as IO errors.
+    pub fn exit_code(&self) -> i32 {
+        match self.class {
+            Some(ErrorClass::Usage) => return EXIT_USAGE,
+            Some(ErrorClass::Pattern) => return EXIT_PATTERN,
+            Some(ErrorClass::Io) | None => return EXIT_IO,
+        }
+    }
 }
 
 impl From<std::io::Error> for CliError {
     fn from(error: std::io::Error) -> CliError {
-        let msg = String::from(format!("{}", error));
-        return CliError { msg };
+        let msg = format!("{}", error);
+        let kind = Some(error.kind());
+        return CliError {
+            msg,
+            kind,
+            class: Some(ErrorClass::Io),
+            in_file: false,
+        };
     }
 }
 
 impl From<String> for CliError {
     fn from(error: String) -> CliError {
-        let msg = String::from(format!("{}", error));
-        return CliError { msg };
+        let msg = error;
+        return CliError {
+            msg,
+            kind: None,
+            class: None,
+            in_file: false,
+        };
     }
 }
 
 impl From<&str> for CliError {
     fn from(error: &str) -> CliError {
-        let msg = String::from(format!("{}", error));
-        return CliError { msg };
+        let msg = String::from(error);
+        return CliError {
+            msg,
+            kind: None,
+            class: None,
+            in_file: false,
+        };
     }
 }
 
 impl From<regex::Error> for CliError {
     fn from(error: regex::Error) -> CliError {
-        let msg = String::from(format!("{}", error));
-        return CliError { msg };
+        let msg = format!("{}", error);
+        return CliError {
+            msg,
+            kind: None,
+            class: Some(ErrorClass::Pattern),
+            in_file: false,
+        };
     }
 }
 
@@ -78,17 +147,22 @@ macro_rules! error {
 
 #[macro_export]
 macro_rules! errorln {
-    () => (crate::error!("\n"));
-    ($($arg:tt)*) => (crate::error!("{}\n", format!($($arg)*)));
+    () => ($crate::error!("\n"));
+    ($($arg:tt)*) => ($crate::error!("{}\n", format!($($arg)*)));
 }
 
-#[macro_export]
-macro_rules! debug {
-    ($($arg:tt)*) => (if super::error::get_debug() { crate::error!($($arg)*);})
-}
-
-#[macro_export]
-macro_rules! debugln {
-    () => (crate::debug!("\n"));
-    ($($arg:tt)*) => (crate::debug!("{}\n", format!($($arg)*)));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_file_names_the_path_once() {
+        let error = CliError::from("a file has no newline").in_file("a");
+        assert_eq!(error.to_string(), "a: a file has no newline");
+        assert_eq!(error.in_file("a").to_string(), "a: a file has no newline");
+        let error = CliError::from("a: is a directory").in_file("a");
+        assert_eq!(error.to_string(), "a: is a directory");
+        let error = CliError::from("b.txt: missing").in_file("b");
+        assert_eq!(error.to_string(), "b: b.txt: missing");
+    }
 }
@@ -0,0 +1,228 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use regex_syntax::hir::literal::Extractor;
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use regex_syntax::ParserBuilder;
+
+/// Classes with more ranges than this are cut short in the tree.
+const MAX_RANGES: usize = 8;
+
+/// Describes how the regex engine reads `pattern` with `[imsxU]` flags: its
+/// syntax tree, groups, whether it can match nothing, its anchors and the
+/// literals every match starts with.
+pub fn explain(pattern: &str, flags: &str) -> Result<String, CliError> {
+    let hir = parse(pattern, flags)?;
+    let mut out = String::new();
+    out.push_str(&format!("pattern: {}\n", pattern));
+    out.push_str("tree:\n");
+    write_tree(&hir, 1, &mut out);
+    out.push_str("groups:\n");
+    let mut groups = Vec::new();
+    collect_groups(&hir, &mut groups);
+    groups.sort();
+    match groups.is_empty() {
+        true => out.push_str("  none\n"),
+        false => {
+            for (index, name) in groups {
+                match name {
+                    Some(name) => out.push_str(&format!("  {} ({})\n", index, name)),
+                    None => out.push_str(&format!("  {}\n", index)),
+                }
+            }
+        }
+    }
+    let properties = hir.properties();
+    let yes_no = |value: bool| match value {
+        true => "yes",
+        false => "no",
+    };
+    out.push_str(&format!(
+        "matches empty: {}\n",
+        yes_no(properties.minimum_len() == Some(0))
+    ));
+    out.push_str(&format!(
+        "anchored at start: {}\n",
+        yes_no(properties.look_set_prefix().contains(Look::Start))
+    ));
+    out.push_str(&format!(
+        "anchored at end: {}\n",
+        yes_no(properties.look_set_suffix().contains(Look::End))
+    ));
+    let prefixes = Extractor::new().extract(&hir);
+    match prefixes.literals() {
+        Some(literals) if !literals.is_empty() && literals.iter().all(|l| !l.is_empty()) => {
+            out.push_str("literal prefixes:\n");
+            for literal in literals {
+                let kind = match literal.is_exact() {
+                    true => " (whole match)",
+                    false => "",
+                };
+                out.push_str(&format!("  {}{}\n", quote(literal.as_bytes()), kind));
+            }
+        }
+        _ => out.push_str("literal prefixes: none\n"),
+    }
+    return Ok(out);
+}
+
+/// Whether `pattern` can match the empty string anywhere, e.g. `a*` or
+/// `a|\b`, or `None` when it is not in the regex engine's syntax.
+pub fn matches_empty(pattern: &str, flags: &str) -> Option<bool> {
+    return parse(pattern, flags)
+        .ok()
+        .map(|hir| hir.properties().minimum_len() == Some(0));
+}
+
+/// Whether `pattern` spells out a newline as a literal, e.g. `a\nb`, or `None`
+/// when it is not in the regex engine's syntax. Classes that happen to include
+/// a newline, like `\s` or `[^a]`, do not count.
+pub fn mentions_newline(pattern: &str, flags: &str) -> Option<bool> {
+    return parse(pattern, flags).ok().map(|hir| has_newline(&hir));
+}
+
+/// Whether `pattern` asserts the start or end of the whole text, as `\A`,
+/// `\z`, or `^` and `$` without the `m` flag do, or `None` when it is not in
+/// the regex engine's syntax.
+pub fn anchors_text(pattern: &str, flags: &str) -> Option<bool> {
+    return parse(pattern, flags).ok().map(|hir| {
+        let looks = hir.properties().look_set();
+        return looks.contains(Look::Start) || looks.contains(Look::End);
+    });
+}
+
+fn has_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Literal(literal) => return literal.0.contains(&b'\n'),
+        HirKind::Repetition(repetition) => return has_newline(&repetition.sub),
+        HirKind::Capture(capture) => return has_newline(&capture.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => return subs.iter().any(has_newline),
+        _ => return false,
+    }
+}
+
+fn parse(pattern: &str, flags: &str) -> Result<Hir, CliError> {
+    return ParserBuilder::new()
+        .case_insensitive(flags.contains('i'))
+        .multi_line(flags.contains('m'))
+        .dot_matches_new_line(flags.contains('s'))
+        .ignore_whitespace(flags.contains('x'))
+        .swap_greed(flags.contains('U'))
+        .build()
+        .parse(pattern)
+        .map_err(|error| CliError::from(format!("{}", error)).with_class(ErrorClass::Pattern));
+}
+
+fn write_tree(hir: &Hir, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match hir.kind() {
+        HirKind::Empty => out.push_str(&format!("{}empty\n", indent)),
+        HirKind::Literal(literal) => {
+            out.push_str(&format!("{}literal {}\n", indent, quote(&literal.0)))
+        }
+        HirKind::Class(class) => {
+            out.push_str(&format!("{}class {}\n", indent, describe_class(class)))
+        }
+        HirKind::Look(look) => out.push_str(&format!("{}look {:?}\n", indent, look)),
+        HirKind::Repetition(repetition) => {
+            let max = match repetition.max {
+                Some(max) => max.to_string(),
+                None => String::new(),
+            };
+            let greed = match repetition.greedy {
+                true => "greedy",
+                false => "lazy",
+            };
+            out.push_str(&format!(
+                "{}repeat {}..{} {}\n",
+                indent, repetition.min, max, greed
+            ));
+            write_tree(&repetition.sub, depth + 1, out);
+        }
+        HirKind::Capture(capture) => {
+            match &capture.name {
+                Some(name) => {
+                    out.push_str(&format!("{}group {} ({})\n", indent, capture.index, name))
+                }
+                None => out.push_str(&format!("{}group {}\n", indent, capture.index)),
+            }
+            write_tree(&capture.sub, depth + 1, out);
+        }
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            let name = match hir.kind() {
+                HirKind::Concat(_) => "concat",
+                _ => "alternation",
+            };
+            out.push_str(&format!("{}{}\n", indent, name));
+            for sub in subs {
+                write_tree(sub, depth + 1, out);
+            }
+        }
+    }
+}
+
+fn collect_groups(hir: &Hir, groups: &mut Vec<(u32, Option<String>)>) {
+    match hir.kind() {
+        HirKind::Capture(capture) => {
+            groups.push((capture.index, capture.name.as_deref().map(String::from)));
+            collect_groups(&capture.sub, groups);
+        }
+        HirKind::Repetition(repetition) => collect_groups(&repetition.sub, groups),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            for sub in subs {
+                collect_groups(sub, groups);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn describe_class(class: &Class) -> String {
+    let ranges: Vec<String> = match class {
+        Class::Unicode(class) => class
+            .iter()
+            .map(|range| describe_range(range.start(), range.end()))
+            .collect(),
+        Class::Bytes(class) => class
+            .iter()
+            .map(|range| describe_range(char::from(range.start()), char::from(range.end())))
+            .collect(),
+    };
+    let shown = ranges.len().min(MAX_RANGES);
+    let rest = match ranges.len() > shown {
+        true => format!(", ... {} ranges in all", ranges.len()),
+        false => String::new(),
+    };
+    return format!("[{}{}]", ranges[..shown].join(", "), rest);
+}
+
+fn describe_range(start: char, end: char) -> String {
+    match start == end {
+        true => return start.escape_debug().to_string(),
+        false => return format!("{}-{}", start.escape_debug(), end.escape_debug()),
+    }
+}
+
+fn quote(bytes: &[u8]) -> String {
+    return format!("{:?}", String::from_utf8_lossy(bytes));
+}
@@ -0,0 +1,171 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use ignore::types::{Types, TypesBuilder};
+
+/// The file types selected by `--type`, from the built-in table extended with
+/// the `--type-add` definitions in `added`. Nothing is filtered when no type
+/// is selected.
+pub fn file_types(selected: &[String], added: &[String]) -> Result<Types, CliError> {
+    if selected.is_empty() {
+        return Ok(Types::empty());
+    }
+    let mut builder = table(added)?;
+    for name in selected {
+        builder.select(name.as_str());
+    }
+    return builder
+        .build()
+        .map_err(|error| CliError::from(format!("--type: {}", error)));
+}
+
+/// Lists every type of the table as `NAME: GLOB, GLOB`, one per line.
+pub fn type_list(added: &[String]) -> Result<String, CliError> {
+    let mut out = String::new();
+    for definition in table(added)?.definitions() {
+        out.push_str(&format!(
+            "{}: {}\n",
+            definition.name(),
+            definition.globs().join(", ")
+        ));
+    }
+    return Ok(out);
+}
+
+fn table(added: &[String]) -> Result<TypesBuilder, CliError> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for definition in added {
+        let (name, globs) = parse_definition(definition.as_str())?;
+        for glob in globs {
+            builder
+                .add(name, glob)
+                .map_err(|error| CliError::from(format!("--type-add {}: {}", definition, error)))?;
+        }
+    }
+    return Ok(builder);
+}
+
+/// Splits a `--type-add` definition, `NAME:GLOB,GLOB...`, into its name and
+/// globs. Names are letters and digits.
+fn parse_definition(definition: &str) -> Result<(&str, Vec<&str>), CliError> {
+    let invalid = || {
+        CliError::from(format!(
+            "invalid --type-add {:?}, expected NAME:GLOB,GLOB...",
+            definition
+        ))
+    };
+    let (name, globs) = definition.split_once(':').ok_or_else(invalid)?;
+    if name.is_empty() || name == "all" || !name.chars().all(char::is_alphanumeric) {
+        return Err(invalid());
+    }
+    let globs: Vec<&str> = globs.split(',').map(str::trim).collect();
+    if globs.iter().any(|glob| glob.is_empty()) {
+        return Err(invalid());
+    }
+    return Ok((name, globs));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selects(selected: &[&str], added: &[&str], path: &str) -> bool {
+        let selected: Vec<String> = selected.iter().map(|name| name.to_string()).collect();
+        let added: Vec<String> = added.iter().map(|name| name.to_string()).collect();
+        let types = file_types(&selected, &added).unwrap();
+        return types.matched(path, false).is_whitelist();
+    }
+
+    #[test]
+    fn table_selects_by_extension() {
+        let cases = [
+            (&["rust"][..], "main.rs", true),
+            (&["rust"][..], "main.py", false),
+            (&["py"][..], "setup.py", true),
+            (&["py"][..], "Cargo.toml", false),
+            (&["toml"][..], "Cargo.toml", true),
+            (&["rust", "py"][..], "main.rs", true),
+            (&["rust", "py"][..], "setup.py", true),
+            (&["rust", "py"][..], "index.html", false),
+        ];
+        for (selected, path, expected) in cases.iter() {
+            assert_eq!(
+                selects(selected, &[], path),
+                *expected,
+                "{:?} {}",
+                selected,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_selected_filters_nothing() {
+        let types = file_types(&[], &[]).unwrap();
+        assert!(types.matched("anything.xyz", false).is_none());
+    }
+
+    #[test]
+    fn unknown_type_is_an_error() {
+        assert!(file_types(&["nosuchtype".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn added_types_extend_the_table() {
+        let web = ["web:*.html,*.css, *.js"];
+        assert!(selects(&["web"], &web, "index.html"));
+        assert!(selects(&["web"], &web, "site.css"));
+        assert!(selects(&["web"], &web, "app.js"));
+        assert!(!selects(&["web"], &web, "main.rs"));
+        assert!(selects(&["rust"], &["rust:*.rs.in"], "build.rs.in"));
+        assert!(selects(&["rust"], &["rust:*.rs.in"], "main.rs"));
+        assert!(type_list(&[web[0].to_string()])
+            .unwrap()
+            .lines()
+            .any(|line| line == "web: *.css, *.html, *.js"));
+    }
+
+    #[test]
+    fn parse_definition_splits_name_and_globs() {
+        assert_eq!(
+            parse_definition("web:*.html,*.css").unwrap(),
+            ("web", vec!["*.html", "*.css"])
+        );
+        assert_eq!(parse_definition("c2: *.c2 ").unwrap(), ("c2", vec!["*.c2"]));
+        for invalid in [
+            "web",
+            ":*.html",
+            "web:",
+            "web:*.html,",
+            "web:,*.html",
+            "all:*.x",
+            "my-type:*.x",
+            "my type:*.x",
+        ]
+        .iter()
+        {
+            assert!(parse_definition(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::Groups;
+use super::error::*;
+use super::report::json_string;
+use super::template::GroupRef;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Literal(String),
+    Group(GroupRef),
+}
+
+/// A record template for `--format`. `{name}` and `{1}` are replaced by the
+/// text of the group, or nothing when it did not participate in the match.
+/// `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    fields: Vec<Field>,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Format, CliError> {
+        let mut fields = Vec::new();
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                literal.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+            match c {
+                '{' => {
+                    let end = match rest.find('}') {
+                        Some(end) if end > 1 => end,
+                        _ => {
+                            return Err(CliError::from(format!(
+                                "invalid format field in {:?}",
+                                format
+                            )))
+                        }
+                    };
+                    if !literal.is_empty() {
+                        fields.push(Field::Literal(std::mem::take(&mut literal)));
+                    }
+                    fields.push(Field::Group(GroupRef::parse(&rest[1..end])));
+                    rest = &rest[end + 1..];
+                }
+                '}' => {
+                    return Err(CliError::from(format!(
+                        "unmatched }} in format {:?}",
+                        format
+                    )))
+                }
+                _ => {
+                    literal.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        if !literal.is_empty() {
+            fields.push(Field::Literal(literal));
+        }
+        return Ok(Format { fields });
+    }
+
+    pub fn groups(&self) -> Vec<&GroupRef> {
+        return self
+            .fields
+            .iter()
+            .filter_map(|field| match field {
+                Field::Group(group) => Some(group),
+                Field::Literal(_) => None,
+            })
+            .collect();
+    }
+
+    pub fn render(&self, captures: &Groups, dst: &mut String) {
+        for field in self.fields.iter() {
+            match field {
+                Field::Literal(text) => dst.push_str(text),
+                Field::Group(group) => {
+                    if let Some(found) = group.get(captures) {
+                        dst.push_str(found.as_str());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders a match as a JSON object keyed by group name, or by index for
+/// unnamed groups. The whole match (group 0) is left out and groups that did
+/// not participate are `null`.
+pub fn render_json(names: &[Option<String>], captures: &Groups, dst: &mut String) {
+    dst.push('{');
+    for (index, name) in names.iter().enumerate().skip(1) {
+        if index > 1 {
+            dst.push_str(", ");
+        }
+        let key = match name {
+            Some(name) => name.clone(),
+            None => index.to_string(),
+        };
+        dst.push_str(&json_string(&key));
+        dst.push_str(": ");
+        match captures.get(index) {
+            Some(found) => dst.push_str(&json_string(found.as_str())),
+            None => dst.push_str("null"),
+        }
+    }
+    dst.push('}');
+}
@@ -0,0 +1,109 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::{Compression, GzBuilder};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+
+/// The first two bytes of every gzip file.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The header of a gzip file, kept to compress its new content the same way.
+#[derive(Debug, Clone)]
+pub struct Gzip {
+    mtime: u32,
+    operating_system: u8,
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+    level: Compression,
+}
+
+impl Gzip {
+    /// Reads the header of the gzip file at `path`.
+    pub fn of(path: &str) -> Result<Gzip, CliError> {
+        let invalid = || CliError::from(format!("{}: not a valid gzip file", path));
+        let mut file = File::open(path).map_err(|error| wrap(path, error))?;
+        let mut fixed = [0u8; 10];
+        file.read_exact(&mut fixed).map_err(|_| invalid())?;
+        // The extra flags only record whether the fastest or the best
+        // compression was used; anything else is taken as the default.
+        let level = match fixed[8] {
+            2 => Compression::best(),
+            4 => Compression::fast(),
+            _ => Compression::default(),
+        };
+        let file = File::open(path).map_err(|error| wrap(path, error))?;
+        let decoder = flate2::bufread::GzDecoder::new(BufReader::new(file));
+        let header = decoder.header().ok_or_else(invalid)?;
+        return Ok(Gzip {
+            mtime: header.mtime(),
+            operating_system: header.operating_system(),
+            filename: header.filename().map(Vec::from),
+            comment: header.comment().map(Vec::from),
+            level,
+        });
+    }
+
+    /// Wraps `writer` so that what is written to it is compressed with this
+    /// header. The encoder has to be finished with `try_finish`.
+    pub fn encoder<W: Write>(&self, writer: W) -> GzEncoder<W> {
+        let mut builder = GzBuilder::new()
+            .mtime(self.mtime)
+            .operating_system(self.operating_system);
+        if let Some(filename) = &self.filename {
+            builder = builder.filename(filename.clone());
+        }
+        if let Some(comment) = &self.comment {
+            builder = builder.comment(comment.clone());
+        }
+        return builder.write(writer, self.level);
+    }
+
+    pub fn compress(&self, path: &str, content: &[u8]) -> Result<Vec<u8>, CliError> {
+        let mut encoder = self.encoder(Vec::with_capacity(content.len() / 2));
+        encoder
+            .write_all(content)
+            .map_err(|error| wrap(path, error))?;
+        return encoder.finish().map_err(|error| wrap(path, error));
+    }
+}
+
+/// Returns a reader of the decompressed content of `reader`, following any
+/// further members concatenated to the first.
+pub fn decoder<R: Read>(reader: R) -> MultiGzDecoder<R> {
+    return MultiGzDecoder::new(reader);
+}
+
+pub fn decompress(path: &str, content: &[u8]) -> Result<Vec<u8>, CliError> {
+    let mut decompressed = Vec::with_capacity(content.len() * 4);
+    decoder(content)
+        .read_to_end(&mut decompressed)
+        .map_err(|error| CliError::from(format!("{}: invalid gzip data: {}", path, error)))?;
+    return Ok(decompressed);
+}
+
+fn wrap(path: &str, error: std::io::Error) -> CliError {
+    return CliError::from(format!("{}: {}", path, error));
+}
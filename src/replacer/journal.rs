@@ -0,0 +1,184 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::util::{checksum, create_parent};
+use crate::infoln;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_VERSION: &str = "rp-journal 1";
+
+/// Records the original content of every file changed by an in-place run so
+/// that `rp --undo` can put it back.
+///
+/// The journal is a version line followed by fields written as a
+/// `<name> <length>` line, the raw bytes and a newline. Each entry starts with
+/// a `file` field and records the path written to (`target`), a checksum of
+/// the content written and the `original` content.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default)]
+struct JournalEntry {
+    file: String,
+    target: String,
+    checksum: String,
+    original: Vec<u8>,
+}
+
+impl Journal {
+    pub fn create(path: &str, command: &str) -> Result<Journal, CliError> {
+        let journal = Journal {
+            path: PathBuf::from(path),
+        };
+        let mut file = File::create(&journal.path).map_err(|error| journal.error(error))?;
+        let mut header = format!("{}\n", JOURNAL_VERSION).into_bytes();
+        push_field(&mut header, "command", command.as_bytes());
+        file.write_all(&header)
+            .and_then(|_| file.sync_all())
+            .map_err(|error| journal.error(error))?;
+        return Ok(journal);
+    }
+
+    /// Appends an entry for `file` and flushes it to disk. Must be called
+    /// before `file` is modified.
+    pub fn record(&self, file: &str, target: &str, content: &[u8]) -> Result<(), CliError> {
+        let original = std::fs::read(file)?;
+        let mut entry = Vec::with_capacity(original.len() + 128);
+        push_field(&mut entry, "file", file.as_bytes());
+        push_field(&mut entry, "target", target.as_bytes());
+        push_field(&mut entry, "checksum", checksum(content).as_bytes());
+        push_field(&mut entry, "original", &original);
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|error| self.error(error))?;
+        return journal
+            .write_all(&entry)
+            .and_then(|_| journal.sync_all())
+            .map_err(|error| self.error(error));
+    }
+
+    fn error(&self, error: std::io::Error) -> CliError {
+        return CliError::from(format!("{}: {}", self.path.display(), error));
+    }
+}
+
+/// Restores the files recorded in the journal at `path`. Every file must
+/// still hold what the run wrote unless `force` is given.
+pub fn undo(path: &str, force: bool) -> Result<(), CliError> {
+    let data =
+        std::fs::read(path).map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+    let entries = parse(path, &data)?;
+
+    // A file changed several times (e.g. with --watch) only has to match the
+    // last recorded state, the earlier entries are undone in reverse order.
+    let mut seen = HashSet::new();
+    let mut changed = Vec::new();
+    for entry in entries.iter().rev() {
+        if !seen.insert(entry.target.as_str()) {
+            continue;
+        }
+        let current = std::fs::read(&entry.target).ok();
+        if current.map(|current| checksum(&current)).as_ref() != Some(&entry.checksum) {
+            changed.push(entry.target.as_str());
+        }
+    }
+    if !changed.is_empty() && !force {
+        return Err(CliError::from(format!(
+            "{} changed since the journal was written, use --force to restore anyway: {}",
+            match changed.len() {
+                1 => "a file has",
+                _ => "files have",
+            },
+            changed.join(", ")
+        )));
+    }
+
+    for entry in entries.iter().rev() {
+        infoln!("Restoring: {}", entry.file);
+        let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", entry.file, error));
+        create_parent(Path::new(&entry.file))?;
+        std::fs::write(&entry.file, &entry.original).map_err(wrap)?;
+        if entry.target != entry.file && Path::new(&entry.target).exists() {
+            std::fs::remove_file(&entry.target)
+                .map_err(|error| CliError::from(format!("{}: {}", entry.target, error)))?;
+        }
+    }
+    return Ok(());
+}
+
+fn parse(path: &str, data: &[u8]) -> Result<Vec<JournalEntry>, CliError> {
+    let invalid =
+        |reason: &str| CliError::from(format!("{}: not a valid journal: {}", path, reason));
+    let header = format!("{}\n", JOURNAL_VERSION);
+    if !data.starts_with(header.as_bytes()) {
+        return Err(invalid("unknown version"));
+    }
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    let mut rest = &data[header.len()..];
+    while !rest.is_empty() {
+        let line_end = match rest.iter().position(|byte| *byte == b'\n') {
+            Some(line_end) => line_end,
+            None => return Err(invalid("truncated field")),
+        };
+        let line = String::from_utf8_lossy(&rest[..line_end]).into_owned();
+        let (name, length) = match line
+            .split_once(' ')
+            .map(|(name, length)| (name, length.parse::<usize>()))
+        {
+            Some((name, Ok(length))) => (name, length),
+            _ => return Err(invalid(format!("bad field {:?}", line).as_str())),
+        };
+        let start = line_end + 1;
+        if rest.len() < start + length + 1 || rest[start + length] != b'\n' {
+            return Err(invalid("truncated field"));
+        }
+        let value = &rest[start..start + length];
+        rest = &rest[start + length + 1..];
+        let text = || String::from_utf8_lossy(value).into_owned();
+        match (name, entries.last_mut()) {
+            ("command", _) => {}
+            ("file", _) => entries.push(JournalEntry {
+                file: text(),
+                target: text(),
+                ..JournalEntry::default()
+            }),
+            ("target", Some(entry)) => entry.target = text(),
+            ("checksum", Some(entry)) => entry.checksum = text(),
+            ("original", Some(entry)) => entry.original = value.to_vec(),
+            _ => return Err(invalid(format!("unexpected field {:?}", name).as_str())),
+        }
+    }
+    return Ok(entries);
+}
+
+fn push_field(buffer: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buffer.extend_from_slice(format!("{} {}\n", name, value.len()).as_bytes());
+    buffer.extend_from_slice(value);
+    buffer.push(b'\n');
+}
@@ -0,0 +1,395 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+
+/// Documents nested deeper than this are refused rather than overflowing the
+/// stack.
+const MAX_DEPTH: usize = 512;
+
+/// A step from a JSON value to one inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// A string value of a JSON document as a byte span of the text, including
+/// its quotes, and the path leading to it. Keys are not values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonString {
+    pub start: usize,
+    pub end: usize,
+    pub path: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Key(String),
+    Index(usize),
+    Any,
+}
+
+/// A path like `$.items[*].description` or `$['a key'][0]`. `*` stands for
+/// any key or index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    selectors: Vec<Selector>,
+}
+
+impl JsonPath {
+    pub fn parse(val: &str) -> Result<JsonPath, CliError> {
+        let invalid = || CliError::from(format!("invalid JSON path: {}", val));
+        let mut rest = val.strip_prefix('$').ok_or_else(invalid)?;
+        let mut selectors = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let (name, tail) = after.split_at(end);
+                match name {
+                    "" => return Err(invalid()),
+                    "*" => selectors.push(Selector::Any),
+                    name => selectors.push(Selector::Key(String::from(name))),
+                }
+                rest = tail;
+                continue;
+            }
+            let after = rest.strip_prefix('[').ok_or_else(invalid)?;
+            let quote = after.chars().next().filter(|c| *c == '\'' || *c == '"');
+            let (selector, tail) = match quote {
+                Some(quote) => {
+                    let end = after[1..].find(quote).ok_or_else(invalid)? + 1;
+                    let tail = after[end + 1..].strip_prefix(']').ok_or_else(invalid)?;
+                    (Selector::Key(String::from(&after[1..end])), tail)
+                }
+                None => {
+                    let (inside, tail) = after.split_once(']').ok_or_else(invalid)?;
+                    match inside.trim() {
+                        "*" => (Selector::Any, tail),
+                        index => match index.parse::<usize>() {
+                            Ok(index) => (Selector::Index(index), tail),
+                            Err(_) => return Err(invalid()),
+                        },
+                    }
+                }
+            };
+            selectors.push(selector);
+            rest = tail;
+        }
+        return Ok(JsonPath { selectors });
+    }
+
+    /// Returns whether `path` leads to a value the path selects or to one
+    /// inside it.
+    pub fn selects(&self, path: &[Step]) -> bool {
+        if path.len() < self.selectors.len() {
+            return false;
+        }
+        return self
+            .selectors
+            .iter()
+            .zip(path.iter())
+            .all(|(selector, step)| match (selector, step) {
+                (Selector::Any, _) => true,
+                (Selector::Key(name), Step::Key(key)) => name == key,
+                (Selector::Index(index), Step::Index(other)) => index == other,
+                _ => false,
+            });
+    }
+}
+
+/// Finds the string values of the JSON document in `text`, failing with the
+/// line and column of the first syntax error.
+pub fn string_values(text: &str) -> Result<Vec<JsonString>, CliError> {
+    let mut scanner = Scanner {
+        text,
+        bytes: text.as_bytes(),
+        position: 0,
+        path: Vec::new(),
+        strings: Vec::new(),
+    };
+    scanner.skip_whitespace();
+    scanner.value(0)?;
+    scanner.skip_whitespace();
+    if scanner.position < scanner.bytes.len() {
+        return Err(scanner.error("unexpected data after the JSON value"));
+    }
+    return Ok(scanner.strings);
+}
+
+/// Decodes a JSON string literal, quotes included, or returns `None` when it
+/// holds a lone surrogate that has no `char`.
+pub fn decode_string(literal: &str) -> Option<String> {
+    let inner = &literal[1..literal.len() - 1];
+    if !inner.contains('\\') {
+        return Some(String::from(inner));
+    }
+    let mut decoded = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'b' => decoded.push('\u{8}'),
+            'f' => decoded.push('\u{c}'),
+            'n' => decoded.push('\n'),
+            'r' => decoded.push('\r'),
+            't' => decoded.push('\t'),
+            'u' => {
+                let high = hex_unit(&mut chars)?;
+                let code = match high {
+                    0xd800..=0xdbff => {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return None;
+                        }
+                        match hex_unit(&mut chars)? {
+                            low @ 0xdc00..=0xdfff => {
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            }
+                            _ => return None,
+                        }
+                    }
+                    code => code,
+                };
+                decoded.push(char::from_u32(code)?);
+            }
+            c => decoded.push(c),
+        }
+    }
+    return Some(decoded);
+}
+
+fn hex_unit(chars: &mut std::str::Chars) -> Option<u32> {
+    let digits: String = chars.take(4).collect();
+    return u32::from_str_radix(digits.as_str(), 16).ok();
+}
+
+struct Scanner<'t> {
+    text: &'t str,
+    bytes: &'t [u8],
+    position: usize,
+    path: Vec<Step>,
+    strings: Vec<JsonString>,
+}
+
+impl Scanner<'_> {
+    fn error(&self, message: &str) -> CliError {
+        let before = &self.text[..self.position];
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(newline) => before[newline + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        return CliError::from(format!(
+            "invalid JSON at line {}, column {}: {}",
+            line, column, message
+        ));
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.bytes.get(self.position).copied();
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &str) -> Result<(), CliError> {
+        match self.peek() == Some(byte) {
+            true => {
+                self.position += 1;
+                return Ok(());
+            }
+            false => return Err(self.error(message)),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<(), CliError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.peek() {
+            Some(b'{') => return self.object(depth),
+            Some(b'[') => return self.array(depth),
+            Some(b'"') => {
+                let start = self.position;
+                self.string()?;
+                self.strings.push(JsonString {
+                    start,
+                    end: self.position,
+                    path: self.path.clone(),
+                });
+                return Ok(());
+            }
+            Some(b't') => return self.keyword("true"),
+            Some(b'f') => return self.keyword("false"),
+            Some(b'n') => return self.keyword("null"),
+            Some(b'-' | b'0'..=b'9') => return self.number(),
+            Some(_) => return Err(self.error("expected a value")),
+            None => return Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<(), CliError> {
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let start = self.position;
+            self.string()?;
+            let key = decode_string(&self.text[start..self.position])
+                .unwrap_or_else(|| String::from(&self.text[start + 1..self.position - 1]));
+            self.skip_whitespace();
+            self.expect(b':', "expected ':' after the key")?;
+            self.skip_whitespace();
+            self.path.push(Step::Key(key));
+            self.value(depth + 1)?;
+            self.path.pop();
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<(), CliError> {
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(());
+        }
+        let mut index = 0;
+        loop {
+            self.skip_whitespace();
+            self.path.push(Step::Index(index));
+            self.value(depth + 1)?;
+            self.path.pop();
+            index += 1;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), CliError> {
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                            self.position += 1
+                        }
+                        Some(b'u') => {
+                            self.position += 1;
+                            for _ in 0..4 {
+                                match self.peek() {
+                                    Some(byte) if byte.is_ascii_hexdigit() => self.position += 1,
+                                    _ => return Err(self.error("invalid \\u escape")),
+                                }
+                            }
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                Some(byte) if byte < 0x20 => {
+                    return Err(self.error("control character in a string"))
+                }
+                Some(_) => self.position += 1,
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), CliError> {
+        match self.text[self.position..].starts_with(keyword) {
+            true => {
+                self.position += keyword.len();
+                return Ok(());
+            }
+            false => return Err(self.error("expected a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<(), CliError> {
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.error("invalid number"));
+            }
+            self.digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                return Err(self.error("invalid number"));
+            }
+            self.digits();
+        }
+        return Ok(());
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+    }
+}
@@ -0,0 +1,224 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+
+/// An inclusive, 1-based range of lines written as `START:END`, where either
+/// side may be left open (`100:`, `:20`) and a single `N` means just line N.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl LineRange {
+    pub fn parse(val: &str) -> Result<LineRange, CliError> {
+        let invalid = || CliError::from(format!("invalid line range: {}", val));
+        let number = |text: &str| -> Result<Option<usize>, CliError> {
+            match text.trim() {
+                "" => return Ok(None),
+                text => match text.parse::<usize>() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(line) => return Ok(Some(line)),
+                },
+            }
+        };
+        let (start, end) = match val.split_once(':') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => match number(val)? {
+                Some(line) => (Some(line), Some(line)),
+                None => return Err(invalid()),
+            },
+        };
+        let start = start.unwrap_or(1);
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid());
+        }
+        return Ok(LineRange { start, end });
+    }
+
+    /// Returns whether the 1-based `line` falls inside the range.
+    pub fn contains(&self, line: usize) -> bool {
+        return line >= self.start && self.end.is_none_or(|end| line <= end);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let mut starts = vec![0];
+        for (index, _) in text.match_indices('\n') {
+            if index + 1 < text.len() {
+                starts.push(index + 1);
+            }
+        }
+        return LineIndex {
+            starts,
+            len: text.len(),
+        };
+    }
+
+    pub fn line_count(&self) -> usize {
+        return self.starts.len();
+    }
+
+    /// Returns the 0-based line containing the byte at `offset`.
+    pub fn line_of(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(line) => return line,
+            Err(line) => return line - 1,
+        }
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        return self.starts[line];
+    }
+
+    /// Returns the offset just past the line's terminator.
+    pub fn line_end(&self, line: usize) -> usize {
+        match self.starts.get(line + 1) {
+            Some(end) => return *end,
+            None => return self.len,
+        }
+    }
+
+    /// Returns the 1-based line and column, counted in characters, of the
+    /// byte at `offset` in `text`.
+    pub fn position(&self, text: &str, offset: usize) -> (usize, usize) {
+        let line = self.line_of(offset);
+        let column = text[self.starts[line]..offset].chars().count() + 1;
+        return (line + 1, column);
+    }
+
+    /// Returns the 0-based first and last lines touched by the span.
+    pub fn span_lines(&self, start: usize, end: usize) -> (usize, usize) {
+        let first = self.line_of(start);
+        match end > start {
+            true => return (first, self.line_of(end - 1)),
+            false => return (first, first),
+        }
+    }
+}
+
+/// Whether `offset` is the end of a text that ends with a newline, which is
+/// after the last line rather than on it.
+pub fn past_last_line(text: &str, offset: usize) -> bool {
+    return offset == text.len() && text.ends_with('\n');
+}
+
+/// Splits `text` into paragraphs separated by blank lines, which hold nothing
+/// but whitespace. Each paragraph comes with the separator after it: the
+/// newline ending its last line and the blank lines that follow. Leading blank
+/// lines come after an empty paragraph. The pieces joined give back `text`.
+pub fn paragraphs(text: &str) -> Vec<(&str, &str)> {
+    let bytes = text.as_bytes();
+    let line_end = |start: usize, position: usize| -> usize {
+        let mut end = position;
+        if end > start && bytes[end - 1] == b'\n' {
+            end -= 1;
+            if end > start && bytes[end - 1] == b'\r' {
+                end -= 1;
+            }
+        }
+        return end;
+    };
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut end: Option<usize> = None;
+    let mut position = 0;
+    for line in text.split_inclusive('\n') {
+        match (line.trim().is_empty(), end) {
+            (true, None) => end = Some(line_end(start, position)),
+            (false, Some(paragraph_end)) => {
+                result.push((&text[start..paragraph_end], &text[paragraph_end..position]));
+                start = position;
+                end = None;
+            }
+            _ => {}
+        }
+        position += line.len();
+    }
+    let end = end.unwrap_or_else(|| line_end(start, text.len()));
+    result.push((&text[start..end], &text[end..]));
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paragraphs_keep_separators() {
+        assert_eq!(
+            paragraphs("a\nb\n\n\nc\n"),
+            vec![("a\nb", "\n\n\n"), ("c", "\n")]
+        );
+        assert_eq!(
+            paragraphs("a\r\n \t\r\nb"),
+            vec![("a", "\r\n \t\r\n"), ("b", "")]
+        );
+        assert_eq!(paragraphs("\n\na"), vec![("", "\n\n"), ("a", "")]);
+        assert_eq!(paragraphs(""), vec![("", "")]);
+        assert_eq!(paragraphs("a"), vec![("a", "")]);
+    }
+
+    #[test]
+    fn paragraphs_hold_no_blank_lines() {
+        let text = "one\n two\n\nthree\n  \n\tfour\n";
+        for (paragraph, separator) in paragraphs(text).iter() {
+            assert!(paragraph.lines().all(|line| !line.trim().is_empty()));
+            assert!(separator.trim().is_empty());
+        }
+    }
+
+    #[test]
+    fn paragraphs_rejoin_to_input() {
+        // A xorshift generator over pieces that make up lines, blank lines and
+        // both line endings, so every mix of them is tried.
+        let pieces = ["a", "b c", " ", "\t", "\n", "\r\n", "\r", "é"];
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| -> usize {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            return (seed % bound as u64) as usize;
+        };
+        for _ in 0..20000 {
+            let length = next(24);
+            let text: String = (0..length).map(|_| pieces[next(pieces.len())]).collect();
+            let split = paragraphs(&text);
+            let joined: String = split
+                .iter()
+                .map(|(paragraph, separator)| format!("{}{}", paragraph, separator))
+                .collect();
+            assert_eq!(joined, text);
+            for (paragraph, _) in split.iter().skip(1) {
+                assert!(!paragraph.is_empty(), "{:?}", text);
+            }
+        }
+    }
+}
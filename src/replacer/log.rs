@@ -0,0 +1,114 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::timestamp::time_of_day;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off = 0,
+    Info = 1,
+    Debug = 2,
+    Trace = 3,
+}
+
+impl Level {
+    pub fn from_verbosity(verbosity: u64) -> Level {
+        match verbosity {
+            0 => return Level::Off,
+            1 => return Level::Info,
+            2 => return Level::Debug,
+            _ => return Level::Trace,
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Level::Off => return "",
+            Level::Info => return "info",
+            Level::Debug => return "debug",
+            Level::Trace => return "trace",
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Off as u8);
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+/// Where log lines go instead of stderr. Also serializes writers so that
+/// lines from different threads never interleave.
+static SINK: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+pub fn enabled(level: Level) -> bool {
+    return level != Level::Off && LEVEL.load(Ordering::SeqCst) >= level as u8;
+}
+
+pub fn set_timestamps(timestamps: bool) {
+    TIMESTAMPS.store(timestamps, Ordering::SeqCst);
+}
+
+pub fn set_log_file(path: &str) -> Result<(), CliError> {
+    let file =
+        File::create(path).map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+    *SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(file);
+    return Ok(());
+}
+
+/// Writes one complete line tagged with `level`.
+pub fn write(level: Level, message: &str) {
+    let line = match TIMESTAMPS.load(Ordering::SeqCst) {
+        true => format!("{} [{}] {}\n", time_of_day(), level.tag(), message),
+        false => format!("[{}] {}\n", level.tag(), message),
+    };
+    let mut sink = SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match sink.as_mut() {
+        Some(file) => file.write_all(line.as_bytes()).ok(),
+        None => std::io::stderr().write_all(line.as_bytes()).ok(),
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => (if $crate::replacer::log::enabled($level) { $crate::replacer::log::write($level, &format!($($arg)*)); })
+}
+
+#[macro_export]
+macro_rules! infoln {
+    ($($arg:tt)*) => ($crate::log!($crate::replacer::log::Level::Info, $($arg)*))
+}
+
+#[macro_export]
+macro_rules! debugln {
+    ($($arg:tt)*) => ($crate::log!($crate::replacer::log::Level::Debug, $($arg)*))
+}
+
+#[macro_export]
+macro_rules! traceln {
+    ($($arg:tt)*) => ($crate::log!($crate::replacer::log::Level::Trace, $($arg)*))
+}
@@ -0,0 +1,93 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::util::read_file;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pairs: HashMap<String, String>,
+}
+
+impl Mapping {
+    /// Loads `key<delimiter>value` pairs, one per line. Empty lines are skipped
+    /// and duplicate keys are an error naming both lines.
+    pub fn load(path: &str, delimiter: char) -> Result<Mapping, CliError> {
+        let mut pairs = HashMap::new();
+        let mut lines: HashMap<String, usize> = HashMap::new();
+        for (index, line) in read_file(path)?.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.split_once(delimiter) {
+                Some(pair) => pair,
+                None => {
+                    return Err(CliError::from(format!(
+                        "{}:{}: missing delimiter {:?}",
+                        path,
+                        index + 1,
+                        delimiter
+                    )))
+                }
+            };
+            if key.is_empty() {
+                return Err(CliError::from(format!("{}:{}: empty key", path, index + 1)));
+            }
+            if let Some(previous) = lines.insert(String::from(key), index + 1) {
+                return Err(CliError::from(format!(
+                    "{}:{}: duplicate key {:?} (first defined on line {})",
+                    path,
+                    index + 1,
+                    key,
+                    previous
+                )));
+            }
+            pairs.insert(String::from(key), String::from(value));
+        }
+        if pairs.is_empty() {
+            return Err(CliError::from(format!("{}: no mappings found", path)));
+        }
+        return Ok(Mapping { pairs });
+    }
+
+    /// Builds a single alternation of the escaped keys, longest first so that a
+    /// key is never shadowed by one of its prefixes.
+    pub fn pattern(&self, word_boundaries: bool) -> String {
+        let mut keys: Vec<&String> = self.pairs.keys().collect();
+        keys.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let alternation = keys
+            .iter()
+            .map(|key| regex::escape(key))
+            .collect::<Vec<String>>()
+            .join("|");
+        match word_boundaries {
+            true => return format!(r"\b(?:{})\b", alternation),
+            false => return format!("(?:{})", alternation),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        return self.pairs.get(key).map(|value| value.as_str());
+    }
+}
@@ -20,7 +20,38 @@
 *   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 *   SOFTWARE.
 */
+pub mod builder;
+pub mod cache;
 pub mod cli;
+pub mod command;
+pub mod config;
+pub mod delimited;
+pub mod engine;
 pub mod error;
+pub mod explain;
+pub mod filetype;
+pub mod format;
+pub mod gzip;
+pub mod interrupt;
+pub mod journal;
+pub mod json;
+pub mod lines;
+pub mod log;
+pub mod mapping;
+pub mod normalize;
+pub mod pattern;
+pub mod preview;
+pub mod region;
+pub mod replace;
+pub mod report;
+pub mod scan;
+pub mod sed;
+pub mod split;
+pub mod stream;
+pub mod template;
+pub mod terminal;
+pub mod timestamp;
 pub mod util;
 pub mod validators;
+pub mod walk;
+pub mod watch;
@@ -0,0 +1,185 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use std::borrow::Cow;
+
+/// A Unicode normalization form for --normalize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Form {
+    Nfc,
+    Nfd,
+}
+
+impl Form {
+    /// Parses `nfc`, `nfd` or `none`, the latter being `None`.
+    pub fn parse(name: &str) -> Result<Option<Form>, CliError> {
+        let form = match name {
+            "none" => return Ok(None),
+            "nfc" => Form::Nfc,
+            "nfd" => Form::Nfd,
+            _ => {
+                return Err(CliError::from(format!(
+                    "unknown normalization form: {}",
+                    name
+                )))
+            }
+        };
+        match cfg!(feature = "normalize") {
+            true => return Ok(Some(form)),
+            false => {
+                return Err(CliError::from(
+                    "--normalize is not available; rebuild rp with --features normalize",
+                ))
+            }
+        }
+    }
+
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.is_normalized(text) {
+            true => return Cow::Borrowed(text),
+            false => {
+                let mut normalized = String::with_capacity(text.len());
+                self.normalize_into(text, &mut normalized);
+                return Cow::Owned(normalized);
+            }
+        }
+    }
+
+    #[cfg(feature = "normalize")]
+    fn is_normalized(&self, text: &str) -> bool {
+        use unicode_normalization::{is_nfc_quick, is_nfd_quick, IsNormalized};
+        let quick = match self {
+            Form::Nfc => is_nfc_quick(text.chars()),
+            Form::Nfd => is_nfd_quick(text.chars()),
+        };
+        return quick == IsNormalized::Yes;
+    }
+
+    #[cfg(feature = "normalize")]
+    fn normalize_into(&self, text: &str, dst: &mut String) {
+        use unicode_normalization::UnicodeNormalization;
+        match self {
+            Form::Nfc => dst.extend(text.nfc()),
+            Form::Nfd => dst.extend(text.nfd()),
+        }
+    }
+
+    /// Whether normalizing the text before and after `c` separately gives the
+    /// same result as normalizing them together: `c` has to be a starter that
+    /// does not compose with `previous`.
+    #[cfg(feature = "normalize")]
+    fn splits_before(&self, previous: char, c: char) -> bool {
+        use unicode_normalization::char::{canonical_combining_class, compose};
+        if c.is_ascii() {
+            return true;
+        }
+        return canonical_combining_class(c) == 0
+            && (*self == Form::Nfd || compose(previous, c).is_none());
+    }
+
+    #[cfg(not(feature = "normalize"))]
+    fn is_normalized(&self, _text: &str) -> bool {
+        return true;
+    }
+
+    #[cfg(not(feature = "normalize"))]
+    fn normalize_into(&self, text: &str, dst: &mut String) {
+        dst.push_str(text);
+    }
+
+    #[cfg(not(feature = "normalize"))]
+    fn splits_before(&self, _previous: char, _c: char) -> bool {
+        return true;
+    }
+}
+
+/// A normalized copy of a text that remembers where each of its pieces came
+/// from, so that spans found in it can be mapped back to the original.
+#[derive(Debug)]
+pub struct Normalized {
+    pub text: String,
+    /// Offsets in the normalized and the original text where both agree,
+    /// empty when the text was already normalized.
+    boundaries: Vec<(usize, usize)>,
+}
+
+impl Normalized {
+    pub fn new(text: &str, form: Form) -> Normalized {
+        if form.is_normalized(text) {
+            return Normalized {
+                text: String::from(text),
+                boundaries: Vec::new(),
+            };
+        }
+        let mut normalized = String::with_capacity(text.len());
+        let mut boundaries = vec![(0, 0)];
+        let mut start = 0;
+        let mut previous: Option<char> = None;
+        for (index, c) in text.char_indices() {
+            if previous.is_some_and(|previous| form.splits_before(previous, c)) {
+                form.normalize_into(&text[start..index], &mut normalized);
+                boundaries.push((normalized.len(), index));
+                start = index;
+            }
+            previous = Some(c);
+        }
+        form.normalize_into(&text[start..], &mut normalized);
+        boundaries.push((normalized.len(), text.len()));
+        return Normalized {
+            text: normalized,
+            boundaries,
+        };
+    }
+
+    /// Returns the span of the original text that `start..end` of the
+    /// normalized text came from, widened to whole pieces when it cuts one.
+    /// Empty spans inside a piece move to its start.
+    pub fn original_span(&self, start: usize, end: usize) -> (usize, usize) {
+        if self.boundaries.is_empty() {
+            return (start, end);
+        }
+        let first = match self.boundaries.binary_search_by_key(&start, |b| b.0) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        if start == end {
+            return (self.boundaries[first].1, self.boundaries[first].1);
+        }
+        let last = match self.boundaries.binary_search_by_key(&end, |b| b.0) {
+            Ok(index) | Err(index) => index,
+        };
+        return (self.boundaries[first].1, self.boundaries[last].1);
+    }
+
+    /// Returns the offset in the normalized text of the piece holding
+    /// `offset` of the original.
+    pub fn normalized_offset(&self, offset: usize) -> usize {
+        if self.boundaries.is_empty() {
+            return offset;
+        }
+        match self.boundaries.binary_search_by_key(&offset, |b| b.1) {
+            Ok(index) => return self.boundaries[index].0,
+            Err(index) => return self.boundaries[index - 1].0,
+        }
+    }
+}
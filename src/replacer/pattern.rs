@@ -0,0 +1,307 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use super::util::{read_file_or_stdin, strip_trailing_newline};
+use crate::infoln;
+use std::collections::HashSet;
+
+/// Splits a Perl-style `/pattern/flags` pattern into the pattern and its
+/// `[imsxU]` flags. Returns `None` when `pattern` is not in that form, which
+/// includes an empty pattern, so that `//` still matches two slashes.
+pub fn split_flags(pattern: &str) -> Option<(&str, &str)> {
+    let inner = pattern.strip_prefix('/')?;
+    let end = inner.rfind('/')?;
+    let flags = &inner[end + 1..];
+    match end > 0 && flags.chars().all(|flag| "imsxU".contains(flag)) {
+        true => return Some((&inner[..end], flags)),
+        false => return None,
+    }
+}
+
+/// Requires `pattern` to match the whole input.
+pub fn anchored_pattern(pattern: &str) -> String {
+    return format!(r"\A(?:{})\z", pattern);
+}
+
+/// Restricts `pattern` to whole words: the match may not be preceded or
+/// followed by a word character. The half boundaries are used instead of `\b`
+/// so that patterns starting or ending with a non-word character still behave.
+pub fn word_pattern(pattern: &str) -> String {
+    return format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern);
+}
+
+/// Turns the literal `text` into a pattern that matches it however it is
+/// spaced: whitespace between two word characters becomes `\s+`, and any
+/// other boundary between tokens, spaced or not, becomes `\s*`. So
+/// `foo ( bar )` matches `foo(bar)` and `foo (\n  bar\n)`.
+pub fn loosen_literal(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len() * 2);
+    let mut previous: Option<char> = None;
+    let mut spaced = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            spaced = true;
+            continue;
+        }
+        if let Some(previous) = previous {
+            match (is_word(previous) && is_word(c), spaced) {
+                (true, true) => pattern.push_str(r"\s+"),
+                (true, false) => {}
+                (false, _) => pattern.push_str(r"\s*"),
+            }
+        }
+        pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+        previous = Some(c);
+        spaced = false;
+    }
+    return pattern;
+}
+
+/// Turns each run of whitespace in the regex `pattern` into `\s+` between
+/// two word characters and `\s*` otherwise. Escaped whitespace and
+/// whitespace inside character classes are kept, and nothing is added where
+/// the pattern has no whitespace.
+pub fn loosen_pattern(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut loosened = String::with_capacity(pattern.len() * 2);
+    let mut depth = 0;
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '\\' {
+            loosened.push(c);
+            if let Some(next) = chars.get(index + 1) {
+                loosened.push(*next);
+            }
+            index += 2;
+            continue;
+        }
+        if depth > 0 {
+            match c {
+                '[' => depth += 1,
+                ']' if !class_start(&chars, index) => depth -= 1,
+                _ => {}
+            }
+            loosened.push(c);
+            index += 1;
+            continue;
+        }
+        if c == '[' {
+            depth = 1;
+            loosened.push(c);
+            index += 1;
+            continue;
+        }
+        if !c.is_whitespace() {
+            loosened.push(c);
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < chars.len() && chars[index].is_whitespace() {
+            index += 1;
+        }
+        let before = start.checked_sub(1).map(|before| chars[before]);
+        let after = chars.get(index).copied();
+        match (before, after) {
+            (Some(before), Some(after)) if is_word(before) && is_word(after) => {
+                loosened.push_str(r"\s+")
+            }
+            _ => loosened.push_str(r"\s*"),
+        }
+    }
+    return loosened;
+}
+
+/// Whether the `]` at `index` is taken literally as the first character of a
+/// class, as in `[]a]` or `[^]a]`.
+fn class_start(chars: &[char], index: usize) -> bool {
+    match index.checked_sub(1).map(|before| chars[before]) {
+        Some('[') => return true,
+        Some('^') => return index >= 2 && chars[index - 2] == '[',
+        _ => return false,
+    }
+}
+
+fn is_word(c: char) -> bool {
+    return c.is_alphanumeric() || c == '_';
+}
+
+/// Joins the lines of the files at `paths` into one alternation, returned with
+/// the number of alternatives. Empty lines and lines starting with `#` are
+/// skipped and repeated lines are kept once. Lines are escaped and put longest
+/// first, so that an entry is never shadowed by one of its prefixes, unless
+/// `regex` is set, in which case each is grouped on its own and the order of
+/// the files is kept. Carriage returns at line ends are kept only when `raw`.
+pub fn line_alternation(
+    paths: &[String],
+    regex: bool,
+    raw: bool,
+) -> Result<(String, usize), CliError> {
+    let mut seen = HashSet::new();
+    let mut entries: Vec<String> = Vec::new();
+    for path in paths {
+        let text = read_pattern_text(path.as_str(), raw)?;
+        for line in text.split('\n') {
+            if line.is_empty() || line.starts_with('#') || !seen.insert(String::from(line)) {
+                continue;
+            }
+            entries.push(String::from(line));
+        }
+    }
+    if entries.is_empty() {
+        return Err(CliError::from(format!(
+            "{}: no patterns found",
+            paths.join(", ")
+        )));
+    }
+    let alternatives: Vec<String> = match regex {
+        true => entries
+            .iter()
+            .map(|entry| format!("(?:{})", entry))
+            .collect(),
+        false => {
+            entries.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+            entries.iter().map(|entry| regex::escape(entry)).collect()
+        }
+    };
+    return Ok((
+        format!("(?:{})", alternatives.join("|")),
+        alternatives.len(),
+    ));
+}
+
+/// Reads the pattern in the --pattern-file at `path`, or stdin for `-`, without its trailing
+/// newline unless `keep_newline`. Unless `raw`, the carriage returns of
+/// Windows line endings are dropped, since they would otherwise silently
+/// become part of the pattern.
+pub fn read_pattern_file(path: &str, keep_newline: bool, raw: bool) -> Result<String, CliError> {
+    let mut text = read_pattern_text(path, raw)?;
+    match (keep_newline, raw) {
+        (true, _) => return Ok(text),
+        (false, true) => {
+            if text.ends_with('\n') {
+                text.pop();
+            }
+            return Ok(text);
+        }
+        (false, false) => return Ok(strip_trailing_newline(text)),
+    }
+}
+
+fn read_pattern_text(path: &str, raw: bool) -> Result<String, CliError> {
+    let text =
+        read_file_or_stdin(path).map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+    if raw || (!text.contains("\r\n") && !text.ends_with('\r')) {
+        return Ok(text);
+    }
+    infoln!(
+        "{}: dropped the carriage returns of Windows line endings, use --pattern-file-raw to keep them",
+        path
+    );
+    let mut text = text.replace("\r\n", "\n");
+    if text.ends_with('\r') {
+        text.pop();
+    }
+    return Ok(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        let regex = Regex::new(&format!(r"\A(?:{})\z", pattern)).unwrap();
+        return regex.is_match(text);
+    }
+
+    #[test]
+    fn split_flags_takes_trailing_flags() {
+        assert_eq!(split_flags("/warn.*$/im"), Some(("warn.*$", "im")));
+        assert_eq!(split_flags("/a/b/sU"), Some(("a/b", "sU")));
+        assert_eq!(split_flags("/usr/"), Some(("usr", "")));
+        assert_eq!(split_flags("///"), Some(("/", "")));
+    }
+
+    #[test]
+    fn split_flags_leaves_other_patterns() {
+        for pattern in ["//", "//i", "/", "usr/", "/usr", "/a/z", "/a/ i", "a/b/i"].iter() {
+            assert_eq!(split_flags(pattern), None, "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn loosen_literal_matches_reformatted_snippets() {
+        for snippet in ["foo ( bar )", "foo(bar)", "foo (\n  bar\n)"].iter() {
+            let pattern = loosen_literal(snippet);
+            for text in [
+                "foo(bar)",
+                "foo ( bar )",
+                "foo (\n  bar\n)",
+                "foo\t(bar\r\n)",
+            ]
+            .iter()
+            {
+                assert!(matches(&pattern, text), "{:?} {:?}", snippet, text);
+            }
+            assert!(!matches(&pattern, "fo o(bar)"), "{:?}", snippet);
+            assert!(!matches(&pattern, "foo(baz)"), "{:?}", snippet);
+        }
+    }
+
+    #[test]
+    fn loosen_literal_keeps_words_apart() {
+        let pattern = loosen_literal("let  mut x = 1.5;");
+        assert_eq!(pattern, r"let\s+mut\s+x\s*=\s*1\s*\.\s*5\s*;");
+        assert!(matches(&pattern, "let mut\n    x=1.5;"));
+        assert!(!matches(&pattern, "letmut x = 1.5;"));
+    }
+
+    #[test]
+    fn loosen_pattern_matches_reformatted_snippets() {
+        let pattern = loosen_pattern(r"fn \w+ \( (\w+) : \s*u8 \)");
+        assert!(matches(&pattern, "fn f(x: u8)"));
+        assert!(matches(&pattern, "fn f (\n    x : u8\n)"));
+        assert!(!matches(&loosen_pattern("fn main ()"), "fnmain()"));
+        assert_eq!(
+            Regex::new(&pattern)
+                .unwrap()
+                .captures("fn f(\n  value:\n    u8)")
+                .unwrap()[1]
+                .to_string(),
+            "value"
+        );
+    }
+
+    #[test]
+    fn loosen_pattern_keeps_escapes_and_classes() {
+        assert_eq!(loosen_pattern(r"a\ b"), r"a\ b");
+        assert_eq!(loosen_pattern("[ a]b c"), r"[ a]b\s+c");
+        assert_eq!(loosen_pattern("[] ]x y"), r"[] ]x\s+y");
+        assert_eq!(loosen_pattern("[^] ] ("), r"[^] ]\s*(");
+        assert_eq!(loosen_pattern("[[:space:] ] x"), r"[[:space:] ]\s*x");
+        assert_eq!(loosen_pattern("abc"), "abc");
+        assert_eq!(loosen_pattern(" a "), r"\s*a\s*");
+    }
+}
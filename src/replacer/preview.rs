@@ -0,0 +1,436 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::lines::LineIndex;
+use super::terminal::fit;
+use std::borrow::Cow;
+use std::path::{Component, Path};
+
+pub const COLOR_DELETED: &str = "\x1b[31m";
+pub const COLOR_INSERTED: &str = "\x1b[32m";
+pub const COLOR_RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl Edit {
+    /// The single edit that turns `old` into `new`, covering everything
+    /// between their common prefix and suffix.
+    pub fn between(old: &str, new: &str) -> Option<Edit> {
+        if old == new {
+            return None;
+        }
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((index, _), _)| index)
+            .unwrap_or_else(|| old.len().min(new.len()));
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        return Some(Edit {
+            start: prefix,
+            end: old.len() - suffix,
+            replacement: String::from(&new[prefix..new.len() - suffix]),
+        });
+    }
+}
+
+struct Hunk<'e> {
+    first: usize,
+    last: usize,
+    edits: &'e [Edit],
+}
+
+/// Renders the edits to `text` as hunks of removed and added lines with
+/// `context` lines around them, each cut to `width` columns when given.
+pub fn render_hunks(
+    path: &str,
+    text: &str,
+    edits: &[Edit],
+    context: usize,
+    color: bool,
+    width: Option<usize>,
+) -> String {
+    let index = LineIndex::new(text);
+    let hunks = group_hunks(&index, edits);
+    let mut output = String::new();
+    let mut position = 0;
+    while position < hunks.len() {
+        // Hunks whose context would touch or overlap are shown as one, with
+        // the unchanged lines between them as context.
+        let mut end = position + 1;
+        while end < hunks.len() && hunks[end].first <= hunks[end - 1].last + 2 * context + 1 {
+            end += 1;
+        }
+        let group = &hunks[position..end];
+        if position > 0 {
+            output.push_str("--\n");
+        }
+        output.push_str(&format!("{}:{}\n", path, group[0].first + 1));
+
+        let before = group[0].first.saturating_sub(context);
+        let after = (group[group.len() - 1].last + context).min(index.line_count() - 1);
+        let mut line = before;
+        for hunk in group {
+            for context_line in line..hunk.first {
+                push_line(
+                    &mut output,
+                    " ",
+                    line_text(text, &index, context_line),
+                    None,
+                    width,
+                );
+            }
+            push_hunk(&mut output, text, &index, hunk, color, width);
+            line = hunk.last + 1;
+        }
+        for context_line in line..after + 1 {
+            push_line(
+                &mut output,
+                " ",
+                line_text(text, &index, context_line),
+                None,
+                width,
+            );
+        }
+        position = end;
+    }
+    return output;
+}
+
+/// Pushes the lines of the hunk as they were, then as the edits leave them.
+fn push_hunk(
+    output: &mut String,
+    text: &str,
+    index: &LineIndex,
+    hunk: &Hunk,
+    color: bool,
+    width: Option<usize>,
+) {
+    let start = index.line_start(hunk.first);
+    let end = index.line_end(hunk.last);
+    let mut replaced = String::new();
+    let mut last = start;
+    for edit in hunk.edits {
+        replaced.push_str(&text[last..edit.start]);
+        replaced.push_str(edit.replacement.as_str());
+        last = edit.end;
+    }
+    replaced.push_str(&text[last..end]);
+
+    let (deleted, inserted) = match color {
+        true => (Some(COLOR_DELETED), Some(COLOR_INSERTED)),
+        false => (None, None),
+    };
+    for line in text[start..end].split_inclusive('\n') {
+        push_line(output, "-", line, deleted, width);
+    }
+    for line in replaced.split_inclusive('\n') {
+        push_line(output, "+", line, inserted, width);
+    }
+}
+
+fn group_hunks<'e>(index: &LineIndex, edits: &'e [Edit]) -> Vec<Hunk<'e>> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut first_edit = 0;
+    for (position, edit) in edits.iter().enumerate() {
+        let (first, last) = index.span_lines(edit.start, edit.end);
+        match hunks.last_mut() {
+            Some(hunk) if first <= hunk.last => {
+                hunk.last = hunk.last.max(last);
+                hunk.edits = &edits[first_edit..position + 1];
+            }
+            _ => {
+                first_edit = position;
+                hunks.push(Hunk {
+                    first,
+                    last,
+                    edits: &edits[position..position + 1],
+                });
+            }
+        }
+    }
+    return hunks;
+}
+
+fn line_text<'t>(text: &'t str, index: &LineIndex, line: usize) -> &'t str {
+    return &text[index.line_start(line)..index.line_end(line)];
+}
+
+fn push_line(
+    output: &mut String,
+    marker: &str,
+    line: &str,
+    color: Option<&str>,
+    width: Option<usize>,
+) {
+    let content = line.strip_suffix('\n').unwrap_or(line);
+    let content = match width {
+        Some(width) => fit(content, width.saturating_sub(marker.len())),
+        None => Cow::Borrowed(content),
+    };
+    match color {
+        Some(color) => output.push_str(&format!("{}{}{}{}\n", color, marker, content, COLOR_RESET)),
+        None => output.push_str(&format!("{}{}\n", marker, content)),
+    }
+}
+
+/// `path` as a patch names it: relative to the current directory when below
+/// it, without `.` components and with `/` between the parts.
+pub fn patch_path(path: &str) -> String {
+    let path = Path::new(path);
+    let relative = match std::env::current_dir() {
+        Ok(current) if path.is_absolute() => path.strip_prefix(current).unwrap_or(path),
+        _ => path,
+    };
+    let parts: Vec<String> = relative
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    match relative.is_absolute() {
+        true => return format!("/{}", parts[1..].join("/")),
+        false => return parts.join("/"),
+    }
+}
+
+/// A run of changed lines: the 0-based lines of the old text it replaces and
+/// the text that takes their place.
+struct Change {
+    first: usize,
+    last: usize,
+    replaced: String,
+}
+
+/// Renders the edits to `text` as a unified diff of `path` that `git apply`
+/// reads, with `context` unchanged lines around each change. Nothing is
+/// rendered when the edits change nothing.
+pub fn render_patch(path: &str, text: &str, edits: &[Edit], context: usize) -> String {
+    let index = LineIndex::new(text);
+    let changes = group_changes(text, &index, edits);
+    let mut output = String::new();
+    if changes.is_empty() {
+        return output;
+    }
+    output.push_str(&format!("diff --git a/{} b/{}\n", path, path));
+    output.push_str(&format!("--- a/{}\n+++ b/{}\n", path, path));
+    let lines = match text.is_empty() {
+        true => 0,
+        false => index.line_count(),
+    };
+    let mut offset: isize = 0;
+    let mut position = 0;
+    while position < changes.len() {
+        // Changes whose context would touch or overlap share a hunk.
+        let mut end = position + 1;
+        while end < changes.len() && changes[end].first <= changes[end - 1].last + 2 * context + 1 {
+            end += 1;
+        }
+        let group = &changes[position..end];
+        let before = group[0].first.saturating_sub(context);
+        let after = (group[end - position - 1].last + context + 1).min(lines);
+        let mut body = String::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        let mut line = before;
+        for change in group {
+            for context_line in line..change.first {
+                push_patch_line(&mut body, " ", line_text(text, &index, context_line));
+            }
+            let start = index.line_start(change.first);
+            let end = index.line_end(change.last);
+            for old in text[start..end].split_inclusive('\n') {
+                push_patch_line(&mut body, "-", old);
+                old_count += 1;
+            }
+            for new in change.replaced.split_inclusive('\n') {
+                push_patch_line(&mut body, "+", new);
+                new_count += 1;
+            }
+            old_count += change.first - line;
+            new_count += change.first - line;
+            line = change.last + 1;
+        }
+        for context_line in line..after {
+            push_patch_line(&mut body, " ", line_text(text, &index, context_line));
+        }
+        old_count += after.saturating_sub(line);
+        new_count += after.saturating_sub(line);
+        let old_start = before + 1;
+        let new_start = (old_start as isize + offset) as usize;
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        output.push_str(&body);
+        offset += new_count as isize - old_count as isize;
+        position = end;
+    }
+    return output;
+}
+
+/// Groups the edits that change something into runs of whole lines. A run
+/// whose new text does not end in a newline takes in the following line, as
+/// the two are joined in the new text.
+fn group_changes(text: &str, index: &LineIndex, edits: &[Edit]) -> Vec<Change> {
+    let edits: Vec<&Edit> = edits
+        .iter()
+        .filter(|edit| text[edit.start..edit.end] != *edit.replacement)
+        .collect();
+    let mut changes = Vec::new();
+    let mut position = 0;
+    while position < edits.len() {
+        let (first, mut last) = index.span_lines(edits[position].start, edits[position].end);
+        let mut end = position + 1;
+        loop {
+            while end < edits.len() && index.span_lines(edits[end].start, edits[end].end).0 <= last
+            {
+                last = last.max(index.span_lines(edits[end].start, edits[end].end).1);
+                end += 1;
+            }
+            let start = index.line_start(first);
+            let stop = index.line_end(last);
+            let mut replaced = String::new();
+            let mut copied = start;
+            for edit in &edits[position..end] {
+                replaced.push_str(&text[copied..edit.start]);
+                replaced.push_str(edit.replacement.as_str());
+                copied = edit.end;
+            }
+            replaced.push_str(&text[copied..stop]);
+            if replaced.is_empty() || replaced.ends_with('\n') || stop >= text.len() {
+                changes.push(Change {
+                    first,
+                    last,
+                    replaced,
+                });
+                break;
+            }
+            last += 1;
+        }
+        position = end;
+    }
+    return changes;
+}
+
+/// Writes `line` with `marker`, noting when it is the last line of a text that
+/// does not end in a newline.
+fn push_patch_line(output: &mut String, marker: &str, line: &str) {
+    output.push_str(marker);
+    output.push_str(line);
+    if !line.ends_with('\n') {
+        output.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// The `START,COUNT` of a hunk side, where an empty side starts at the line
+/// before it.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => return format!("{},0", start - 1),
+        _ => return format!("{},{}", start, count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits(text: &str, words: &[&str]) -> Vec<Edit> {
+        return words
+            .iter()
+            .map(|word| {
+                let start = text.find(&format!("{}\n", word)).unwrap();
+                Edit {
+                    start,
+                    end: start + word.len(),
+                    replacement: word.to_uppercase(),
+                }
+            })
+            .collect();
+    }
+
+    #[test]
+    fn hunks_with_overlapping_context_are_merged() {
+        let text = "a\nb\nc\nd\ne\nf\n";
+        assert_eq!(
+            render_hunks("f", text, &edits(text, &["b", "d"]), 2, false, None),
+            "f:2\n a\n-b\n+B\n c\n-d\n+D\n e\n f\n"
+        );
+        assert_eq!(
+            render_hunks("f", text, &edits(text, &["b", "e"]), 1, false, None),
+            "f:2\n a\n-b\n+B\n c\n d\n-e\n+E\n f\n"
+        );
+    }
+
+    #[test]
+    fn distant_hunks_are_separated() {
+        let text = "a\nb\nc\nd\ne\nf\n";
+        assert_eq!(
+            render_hunks("f", text, &edits(text, &["b", "d"]), 0, false, None),
+            "f:2\n-b\n+B\n--\nf:4\n-d\n+D\n"
+        );
+        assert_eq!(
+            render_hunks("f", text, &edits(text, &["a", "f"]), 1, false, None),
+            "f:1\n-a\n+A\n b\n--\nf:6\n e\n-f\n+F\n"
+        );
+    }
+
+    #[test]
+    fn edits_on_one_line_share_a_hunk() {
+        let text = "x a a\ny\n";
+        let edits = vec![
+            Edit {
+                start: 2,
+                end: 3,
+                replacement: String::from("1"),
+            },
+            Edit {
+                start: 4,
+                end: 5,
+                replacement: String::from("2"),
+            },
+        ];
+        assert_eq!(
+            render_hunks("f", text, &edits, 1, false, None),
+            "f:1\n-x a a\n+x 1 2\n y\n"
+        );
+    }
+
+    #[test]
+    fn edit_between_finds_changed_span() {
+        let edit = Edit::between("hello world", "hello there world").unwrap();
+        assert_eq!((edit.start, edit.end), (6, 6));
+        assert_eq!(edit.replacement, "there ");
+        assert!(Edit::between("same", "same").is_none());
+    }
+}
@@ -0,0 +1,93 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use regex::Regex;
+
+/// A block of text delimited by a start and an end marker. The outer span
+/// includes both markers, the inner span only the text between them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub outer_start: usize,
+    pub inner_start: usize,
+    pub inner_end: usize,
+    pub outer_end: usize,
+}
+
+impl Region {
+    pub fn contains(&self, start: usize, end: usize) -> bool {
+        return start >= self.inner_start && end <= self.inner_end;
+    }
+
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        return (start < self.outer_end && end > self.outer_start)
+            || (start == end && start >= self.outer_start && start < self.outer_end);
+    }
+}
+
+/// Finds the non-overlapping regions opened by `start` and closed by the next
+/// `end` after it. A region left open at EOF extends to it when `extend` is
+/// set and is an error otherwise.
+pub fn find_regions(
+    text: &str,
+    start: &Regex,
+    end: &Regex,
+    extend: bool,
+) -> Result<Vec<Region>, CliError> {
+    let mut regions = Vec::new();
+    let mut at = 0;
+    while at <= text.len() {
+        let opened = match start.find_at(text, at) {
+            Some(opened) => opened,
+            None => break,
+        };
+        let region = match end.find_at(text, opened.end()) {
+            Some(closed) => Region {
+                outer_start: opened.start(),
+                inner_start: opened.end(),
+                inner_end: closed.start(),
+                outer_end: closed.end(),
+            },
+            None if extend => Region {
+                outer_start: opened.start(),
+                inner_start: opened.end(),
+                inner_end: text.len(),
+                outer_end: text.len(),
+            },
+            None => {
+                return Err(CliError::from(format!(
+                    "unterminated region starting at byte {}",
+                    opened.start()
+                )))
+            }
+        };
+        regions.push(region);
+        at = match region.outer_end > at {
+            true => region.outer_end,
+            false => match text[at..].chars().next() {
+                Some(c) => at + c.len_utf8(),
+                None => break,
+            },
+        };
+    }
+    return Ok(regions);
+}
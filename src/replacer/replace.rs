@@ -0,0 +1,170 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::{Engine, Groups};
+use super::error::*;
+use super::template::Template;
+use regex::Regex;
+use std::io::Write;
+
+/// One replacement: `start..end` is the match in the input, in bytes, and its
+/// replacement is `new_len` bytes of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplacementSpan {
+    pub start: usize,
+    pub end: usize,
+    pub new_len: usize,
+    /// The span of each capture group in the input, by group number.
+    pub groups: Vec<Option<(usize, usize)>>,
+}
+
+/// Where the replacements in a text were made, in the order they were made.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplaceReport {
+    pub replacements: Vec<ReplacementSpan>,
+    /// Whether any replacement differs from the text it replaced.
+    pub changed: bool,
+}
+
+impl ReplaceReport {
+    /// Pairs each replacement with its text in `output`, the text it was
+    /// reported for.
+    pub fn replaced<'a>(
+        &'a self,
+        output: &'a str,
+    ) -> impl Iterator<Item = (&'a ReplacementSpan, &'a str)> + 'a {
+        let mut shift: isize = 0;
+        return self.replacements.iter().map(move |span| {
+            let start = (span.start as isize + shift) as usize;
+            shift += span.new_len as isize - (span.end - span.start) as isize;
+            return (span, &output[start..start + span.new_len]);
+        });
+    }
+
+    /// The number of bytes of `input` rewritten to get `output`. A
+    /// replacement counts with the longer of the text it replaced and its own
+    /// length, and not at all when the two are the same.
+    pub fn changed_bytes(&self, input: &str, output: &str) -> usize {
+        return self
+            .replaced(output)
+            .filter(|(span, replacement)| *replacement != &input[span.start..span.end])
+            .map(|(span, _)| (span.end - span.start).max(span.new_len))
+            .sum();
+    }
+}
+
+/// Replaces every match of `regex` in `text` with `replacement`.
+pub fn replace_str(regex: &Regex, replacement: &str, text: &str) -> Result<String, CliError> {
+    return replace_str_with_report(regex, replacement, text).map(|(output, _)| output);
+}
+
+/// Like `replace_str`, also reporting where each replacement was made.
+pub fn replace_str_with_report(
+    regex: &Regex,
+    replacement: &str,
+    text: &str,
+) -> Result<(String, ReplaceReport), CliError> {
+    let template = Template::parse(replacement);
+    let mut index = 0;
+    return replace_with(regex as &dyn Engine, text, |captures, dst| {
+        template.expand(captures, index, dst)?;
+        index += 1;
+        return Ok(true);
+    });
+}
+
+/// Replaces the matches of `engine` in `text` with what `replace` appends to
+/// the output for each of them. A match is left as it is when `replace`
+/// returns false, in which case it must not have appended anything.
+pub fn replace_with<F>(
+    engine: &dyn Engine,
+    text: &str,
+    mut replace: F,
+) -> Result<(String, ReplaceReport), CliError>
+where
+    F: FnMut(&Groups, &mut String) -> Result<bool, CliError>,
+{
+    let groups = engine.group_names().len();
+    let mut output = String::with_capacity(text.len());
+    let mut report = ReplaceReport::default();
+    let mut last = 0;
+    for captures in engine.captures_iter(text) {
+        let captures = captures?;
+        let found = captures.found();
+        output.push_str(&text[last..found.start()]);
+        let start = output.len();
+        if !replace(&captures, &mut output)? {
+            output.push_str(found.as_str());
+            last = found.end();
+            continue;
+        }
+        report.changed |= output[start..] != *found.as_str();
+        report.replacements.push(ReplacementSpan {
+            start: found.start(),
+            end: found.end(),
+            new_len: output.len() - start,
+            groups: (0..groups)
+                .map(|index| {
+                    captures
+                        .get(index)
+                        .map(|group| (group.start(), group.end()))
+                })
+                .collect(),
+        });
+        last = found.end();
+    }
+    output.push_str(&text[last..]);
+    return Ok((output, report));
+}
+
+/// Like `replace_with`, but writes the output to `writer` as it is produced
+/// instead of building it. Returns the number of bytes rewritten, counted as
+/// `ReplaceReport::changed_bytes` does.
+pub fn write_with<F>(
+    engine: &dyn Engine,
+    text: &str,
+    writer: &mut dyn Write,
+    mut replace: F,
+) -> Result<usize, CliError>
+where
+    F: FnMut(&Groups, &mut String) -> Result<bool, CliError>,
+{
+    let mut replacement = String::new();
+    let mut changed = 0;
+    let mut last = 0;
+    for captures in engine.captures_iter(text) {
+        let captures = captures?;
+        let found = captures.found();
+        replacement.clear();
+        if !replace(&captures, &mut replacement)? {
+            continue;
+        }
+        writer.write_all(&text.as_bytes()[last..found.start()])?;
+        writer.write_all(replacement.as_bytes())?;
+        if replacement != found.as_str() {
+            changed += found.as_str().len().max(replacement.len());
+        }
+        last = found.end();
+    }
+    writer.write_all(&text.as_bytes()[last..])?;
+    return Ok(changed);
+}
@@ -0,0 +1,330 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: String,
+    pub matches: u64,
+    pub replacements: u64,
+    pub changed: bool,
+    pub large: bool,
+    pub error: Option<String>,
+    pub locations: Vec<MatchLocation>,
+    /// The bytes of text read, and how many of them the replacements rewrote.
+    pub size: u64,
+    pub changed_bytes: u64,
+    /// Time spent reading and writing the file, and on it altogether.
+    pub read: Duration,
+    pub write: Duration,
+    pub elapsed: Duration,
+}
+
+/// Where a replacement was made, with the matched text and what replaced it
+/// cut short for display.
+#[derive(Debug, Clone)]
+pub struct MatchLocation {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+    pub matched: String,
+    pub replacement: String,
+}
+
+impl MatchLocation {
+    fn to_json(&self) -> String {
+        return format!(
+            "{{\"line\": {}, \"column\": {}, \"offset\": {}, \"matched\": {}, \"replacement\": {}}}",
+            self.line,
+            self.column,
+            self.offset,
+            json_string(&self.matched),
+            json_string(&self.replacement)
+        );
+    }
+}
+
+impl FileReport {
+    pub fn new(path: &str) -> FileReport {
+        return FileReport {
+            path: String::from(path),
+            matches: 0,
+            replacements: 0,
+            changed: false,
+            large: false,
+            error: None,
+            locations: Vec::new(),
+            size: 0,
+            changed_bytes: 0,
+            read: Duration::ZERO,
+            write: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        };
+    }
+
+    pub fn failed(path: &str, error: &CliError) -> FileReport {
+        let mut report = FileReport::new(path);
+        report.error = Some(format!("{}", error));
+        return report;
+    }
+
+    pub fn to_json(&self) -> String {
+        let error = match &self.error {
+            Some(error) => json_string(error),
+            None => String::from("null"),
+        };
+        let locations = self
+            .locations
+            .iter()
+            .map(|location| location.to_json())
+            .collect::<Vec<String>>()
+            .join(", ");
+        return format!(
+            "{{\"path\": {}, \"matches\": {}, \"replacements\": {}, \"changed\": {}, \"error\": {}, \"locations\": [{}]}}",
+            json_string(&self.path),
+            self.matches,
+            self.replacements,
+            self.changed,
+            error,
+            locations
+        );
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub files: u64,
+    pub matches: u64,
+    pub replacements: u64,
+    pub changed: u64,
+    pub errors: u64,
+    pub large: u64,
+    /// Whether the run stopped early on Ctrl-C.
+    pub interrupted: bool,
+    /// The --large-files policy, when a --max-filesize is in effect.
+    pub large_policy: Option<String>,
+    /// Where the time went, with --timings.
+    pub timings: Option<Timings>,
+    /// The files left out by --newer-than or --older-than, when given.
+    pub mtime_skipped: Option<u64>,
+    /// How the files fared in --cache, when given.
+    pub cache: Option<CacheCounts>,
+}
+
+/// Files --cache skipped as unchanged (`hits`) and files it had to process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheCounts {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// How many of the slowest files --timings lists.
+const SLOWEST_FILES: usize = 10;
+
+/// Wall time spent compiling the pattern, reading files, matching and
+/// replacing, and writing, with the slowest files. Files that are streamed
+/// count all of their time as matching.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub compile: Duration,
+    pub read: Duration,
+    pub replace: Duration,
+    pub write: Duration,
+    pub slowest: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    pub fn new(compile: Duration) -> Timings {
+        return Timings {
+            compile,
+            ..Timings::default()
+        };
+    }
+
+    fn add(&mut self, report: &FileReport) {
+        self.read += report.read;
+        self.write += report.write;
+        self.replace += report.elapsed.saturating_sub(report.read + report.write);
+        let position = self
+            .slowest
+            .iter()
+            .position(|(_, elapsed)| *elapsed < report.elapsed)
+            .unwrap_or(self.slowest.len());
+        if position < SLOWEST_FILES && !report.elapsed.is_zero() {
+            self.slowest
+                .insert(position, (report.path.clone(), report.elapsed));
+            self.slowest.truncate(SLOWEST_FILES);
+        }
+    }
+
+    pub fn to_lines(&self) -> String {
+        let mut out = format!(
+            "timings: compile {:.3}s, read {:.3}s, replace {:.3}s, write {:.3}s\n",
+            self.compile.as_secs_f64(),
+            self.read.as_secs_f64(),
+            self.replace.as_secs_f64(),
+            self.write.as_secs_f64()
+        );
+        if !self.slowest.is_empty() {
+            out.push_str("slowest files:\n");
+        }
+        for (path, elapsed) in self.slowest.iter() {
+            out.push_str(&format!("  {:.3}s {}\n", elapsed.as_secs_f64(), path));
+        }
+        return out;
+    }
+
+    fn to_json(&self) -> String {
+        let slowest = self
+            .slowest
+            .iter()
+            .map(|(path, elapsed)| {
+                format!(
+                    "{{\"path\": {}, \"elapsed\": {:.6}}}",
+                    json_string(path),
+                    elapsed.as_secs_f64()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        return format!(
+            "{{\"compile\": {:.6}, \"read\": {:.6}, \"replace\": {:.6}, \"write\": {:.6}, \"slowest\": [{}]}}",
+            self.compile.as_secs_f64(),
+            self.read.as_secs_f64(),
+            self.replace.as_secs_f64(),
+            self.write.as_secs_f64(),
+            slowest
+        );
+    }
+}
+
+impl Summary {
+    pub fn add(&mut self, report: &FileReport) {
+        self.files += 1;
+        self.matches += report.matches;
+        self.replacements += report.replacements;
+        self.changed += report.changed as u64;
+        self.errors += report.error.is_some() as u64;
+        self.large += report.large as u64;
+        if let Some(timings) = &mut self.timings {
+            timings.add(report);
+        }
+    }
+
+    pub fn to_line(&self, elapsed: Duration) -> String {
+        let large = match self.large_policy.as_deref() {
+            Some(policy) => format!(
+                ", {} large files {}",
+                self.large,
+                match policy {
+                    "stream" => "streamed",
+                    "error" => "rejected",
+                    _ => "skipped",
+                }
+            ),
+            None => String::new(),
+        };
+        let mtime_skipped = match self.mtime_skipped {
+            Some(skipped) => format!(", {} skipped by modification time", skipped),
+            None => String::new(),
+        };
+        let cache = match self.cache {
+            Some(cache) => format!(", {} cache hits, {} misses", cache.hits, cache.misses),
+            None => String::new(),
+        };
+        return format!(
+            "{} files scanned, {} changed, {} replacements, {} skipped due to errors{}{}{} in {:.3}s",
+            self.files,
+            self.changed,
+            self.replacements,
+            self.errors,
+            large,
+            mtime_skipped,
+            cache,
+            elapsed.as_secs_f64()
+        );
+    }
+
+    pub fn to_json(&self, elapsed: Duration) -> String {
+        let policy = match &self.large_policy {
+            Some(policy) => json_string(policy),
+            None => String::from("null"),
+        };
+        let mtime_skipped = match self.mtime_skipped {
+            Some(skipped) => format!(", \"mtime_skipped\": {}", skipped),
+            None => String::new(),
+        };
+        let cache = match self.cache {
+            Some(cache) => format!(
+                ", \"cache\": {{\"hits\": {}, \"misses\": {}}}",
+                cache.hits, cache.misses
+            ),
+            None => String::new(),
+        };
+        let timings = match &self.timings {
+            Some(timings) => format!(", \"timings\": {}", timings.to_json()),
+            None => String::new(),
+        };
+        return format!(
+            "{{\"summary\": {{\"files\": {}, \"matches\": {}, \"replacements\": {}, \"changed\": {}, \"errors\": {}, \"large\": {}, \"large_policy\": {}, \"elapsed\": {:.6}{}{}{}}}}}",
+            self.files,
+            self.matches,
+            self.replacements,
+            self.changed,
+            self.errors,
+            self.large,
+            policy,
+            elapsed.as_secs_f64(),
+            mtime_skipped,
+            cache,
+            timings
+        );
+    }
+}
+
+/// Cuts `text` to at most `limit` characters, marking where it was cut.
+pub fn truncate_preview(text: &str, limit: usize) -> String {
+    match text.char_indices().nth(limit) {
+        Some((end, _)) => return format!("{}...", &text[..end]),
+        None => return String::from(text),
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    return escaped;
+}
@@ -0,0 +1,260 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use std::io::Read;
+use std::io::Write;
+
+pub struct ScanBuffer<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    scan_size: usize,
+    eof: bool,
+}
+
+impl<R: Read> ScanBuffer<R> {
+    pub fn new(reader: R, scan_size: usize) -> ScanBuffer<R> {
+        return ScanBuffer {
+            reader,
+            buffer: Vec::with_capacity(scan_size),
+            scan_size: scan_size.max(1),
+            eof: false,
+        };
+    }
+
+    /// Appends up to `scan_size` bytes from the reader, reading repeatedly until
+    /// that many have arrived or EOF is reached, and returns the number appended.
+    pub fn shift(&mut self) -> Result<usize, CliError> {
+        let start = self.buffer.len();
+        let target = start + self.scan_size;
+        self.buffer.resize(target, 0);
+        let mut filled = start;
+        while filled < target {
+            match self.reader.read(&mut self.buffer[filled..target]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(bytes_read) => filled += bytes_read,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.buffer.truncate(filled);
+                    return Err(CliError::from(error));
+                }
+            }
+        }
+        self.buffer.truncate(filled);
+        return Ok(filled - start);
+    }
+
+    pub fn is_eof(&self) -> bool {
+        return self.eof;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.buffer.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.buffer.is_empty();
+    }
+
+    pub fn remaining(&self) -> &[u8] {
+        return &self.buffer;
+    }
+
+    /// Drops the first `n` buffered bytes without reading more, keeping the
+    /// tail for the next window.
+    pub fn consume(&mut self, n: usize) {
+        let n = n.min(self.buffer.len());
+        self.buffer.drain(..n);
+    }
+
+    /// Writes the first `n` buffered bytes to `writer` and consumes them.
+    pub fn drain_to<W: Write>(&mut self, writer: &mut W, n: usize) -> Result<(), CliError> {
+        let n = n.min(self.buffer.len());
+        writer.write_all(&self.buffer[..n])?;
+        self.consume(n);
+        return Ok(());
+    }
+
+    pub fn process<F, T>(&mut self, f: F) -> T
+    where
+        F: FnOnce(&[u8], bool) -> T,
+    {
+        return f(&self.buffer, self.eof);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns one byte per read, failing with `Interrupted` on every
+    /// `interrupt_every`th call when that is not zero.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        calls: usize,
+        interrupt_every: usize,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            if self.interrupt_every != 0 && self.calls.is_multiple_of(self.interrupt_every) {
+                return Err(std::io::Error::from(std::io::ErrorKind::Interrupted));
+            }
+            let n = buf.len().min(self.data.len()).min(1);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            return Ok(n);
+        }
+    }
+
+    fn trickle(data: &[u8], interrupt_every: usize) -> Trickle<'_> {
+        return Trickle {
+            data,
+            calls: 0,
+            interrupt_every,
+        };
+    }
+
+    fn windows(input: &[u8], scan_size: usize) -> Vec<Vec<u8>> {
+        let mut scanner = ScanBuffer::new(input, scan_size);
+        let mut windows = Vec::new();
+        loop {
+            scanner.shift().unwrap();
+            windows.push(scanner.remaining().to_vec());
+            let length = scanner.len();
+            scanner.consume(length);
+            if scanner.is_eof() {
+                return windows;
+            }
+        }
+    }
+
+    #[test]
+    fn one_byte_windows() {
+        let expected: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), vec![]];
+        assert_eq!(windows(b"abc", 1), expected);
+    }
+
+    #[test]
+    fn zero_scan_size_reads_one_byte() {
+        assert_eq!(windows(b"ab", 0)[0], b"a");
+    }
+
+    #[test]
+    fn large_windows() {
+        let input: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+        let windows = windows(&input, 4096);
+        let lengths: Vec<usize> = windows.iter().map(|window| window.len()).collect();
+        assert_eq!(lengths, vec![4096, 4096, 1808]);
+        assert_eq!(windows.concat(), input);
+    }
+
+    #[test]
+    fn process_sees_eof() {
+        let mut scanner = ScanBuffer::new(&b"abc"[..], 8);
+        assert_eq!(scanner.shift().unwrap(), 3);
+        assert!(scanner.process(|buffer, eof| buffer == b"abc" && eof));
+    }
+
+    #[test]
+    fn shift_fills_window_from_one_byte_reads() {
+        let mut scanner = ScanBuffer::new(trickle(b"abcdefg", 0), 4);
+        assert_eq!(scanner.shift().unwrap(), 4);
+        assert_eq!(scanner.remaining(), b"abcd");
+        assert!(!scanner.is_eof());
+        assert_eq!(scanner.shift().unwrap(), 3);
+        assert_eq!(scanner.remaining(), b"abcdefg");
+        assert!(scanner.is_eof());
+    }
+
+    #[test]
+    fn shift_retries_interrupted_reads() {
+        let mut scanner = ScanBuffer::new(trickle(b"abcdefg", 2), 5);
+        assert_eq!(scanner.shift().unwrap(), 5);
+        assert_eq!(scanner.remaining(), b"abcde");
+        assert_eq!(scanner.shift().unwrap(), 2);
+        assert_eq!(scanner.remaining(), b"abcdefg");
+        assert!(scanner.is_eof());
+    }
+
+    #[test]
+    fn shift_keeps_bytes_read_before_an_error() {
+        struct Failing(bool);
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+                }
+                self.0 = true;
+                buf[0] = b'a';
+                return Ok(1);
+            }
+        }
+        let mut scanner = ScanBuffer::new(Failing(false), 4);
+        assert!(scanner.shift().is_err());
+        assert_eq!(scanner.remaining(), b"a");
+    }
+
+    #[test]
+    fn match_split_across_shifts() {
+        // "needle" arrives as "..nee" and "dle..", so the caller has to carry
+        // the partial match forward with `consume` rather than drain it.
+        let mut scanner = ScanBuffer::new(&b"..needle.."[..], 5);
+        let mut output = Vec::new();
+        let mut found = Vec::new();
+        loop {
+            scanner.shift().unwrap();
+            let text = scanner.remaining();
+            let keep_from = match text.windows(6).position(|w| w == b"needle") {
+                Some(start) => {
+                    found.push(start + output.len());
+                    start + 6
+                }
+                None if scanner.is_eof() => text.len(),
+                None => text.len().saturating_sub(5),
+            };
+            scanner.drain_to(&mut output, keep_from).unwrap();
+            if scanner.is_eof() && scanner.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(output, b"..needle..");
+        assert_eq!(found, vec![2]);
+    }
+
+    #[test]
+    fn consume_keeps_tail() {
+        let mut scanner = ScanBuffer::new(&b"abcdef"[..], 4);
+        scanner.shift().unwrap();
+        scanner.consume(3);
+        assert_eq!(scanner.remaining(), b"d");
+        assert_eq!(scanner.len(), 1);
+        scanner.shift().unwrap();
+        assert_eq!(scanner.remaining(), b"def");
+        scanner.consume(10);
+        assert!(scanner.is_empty());
+    }
+}
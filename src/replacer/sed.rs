@@ -0,0 +1,135 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+
+/// A sed-style `s/pattern/replacement/flags` expression. Any delimiter that is
+/// not alphanumeric, a backslash or whitespace may follow the leading `s`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SedExpression {
+    pub pattern: String,
+    pub replacement: String,
+    /// The `i`, `m` and `s` flags, to be applied as an inline `(?flags)` group.
+    pub flags: String,
+    pub occurrence: Occurrence,
+}
+
+/// Which of the matches in a file are replaced, counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Occurrence {
+    pub nth: u64,
+    pub global: bool,
+}
+
+impl Occurrence {
+    pub fn all() -> Occurrence {
+        return Occurrence {
+            nth: 1,
+            global: true,
+        };
+    }
+
+    pub fn accepts(&self, position: u64) -> bool {
+        match self.global {
+            true => return position >= self.nth,
+            false => return position == self.nth,
+        }
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    return !c.is_alphanumeric() && c != '\\' && !c.is_whitespace();
+}
+
+impl SedExpression {
+    /// Returns whether `val` starts like an expression, i.e. `s` and a valid
+    /// delimiter.
+    pub fn detect(val: &str) -> bool {
+        let mut chars = val.chars();
+        return chars.next() == Some('s') && chars.next().is_some_and(is_delimiter);
+    }
+
+    pub fn parse(val: &str) -> Result<SedExpression, CliError> {
+        let invalid =
+            |reason: &str| CliError::from(format!("invalid expression {}: {}", val, reason));
+        if !SedExpression::detect(val) {
+            return Err(invalid("expected s followed by a delimiter"));
+        }
+        let mut chars = val.chars().skip(1);
+        let delimiter = chars.next().unwrap();
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            if parts.len() == 3 {
+                parts[2].push(c);
+                continue;
+            }
+            let part = parts.last_mut().unwrap();
+            match c {
+                '\\' => match chars.next() {
+                    Some(next) if next == delimiter => part.push(next),
+                    Some(next) => {
+                        part.push(c);
+                        part.push(next);
+                    }
+                    None => return Err(invalid("trailing backslash")),
+                },
+                c if c == delimiter => parts.push(String::new()),
+                c => part.push(c),
+            }
+        }
+        if parts.len() < 3 {
+            return Err(invalid("missing delimiter"));
+        }
+        let flags = parts.pop().unwrap();
+        let replacement = parts.pop().unwrap();
+        let pattern = parts.pop().unwrap();
+
+        let mut inline = String::new();
+        let mut global = false;
+        let mut nth = String::new();
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' | 'm' | 's' => {
+                    if !inline.contains(flag) {
+                        inline.push(flag);
+                    }
+                }
+                '0'..='9' => nth.push(flag),
+                _ => return Err(invalid(&format!("unknown flag {}", flag))),
+            }
+        }
+        let nth = match nth.is_empty() {
+            true => 1,
+            false => match nth.parse::<u64>() {
+                Ok(0) | Err(_) => return Err(invalid("occurrence must be at least 1")),
+                Ok(nth) => nth,
+            },
+        };
+        return Ok(SedExpression {
+            pattern,
+            replacement,
+            flags: inline,
+            occurrence: Occurrence { nth, global },
+        });
+    }
+}
@@ -0,0 +1,99 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::Engine;
+use super::error::*;
+
+/// A list of 1-based fields written as `1,3-5`, where a range may be left open
+/// on either side (`4-`, `-2`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSelector {
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl FieldSelector {
+    pub fn parse(val: &str) -> Result<FieldSelector, CliError> {
+        let invalid = || CliError::from(format!("invalid field list: {}", val));
+        let number = |text: &str| -> Result<Option<usize>, CliError> {
+            match text.trim() {
+                "" => return Ok(None),
+                text => match text.parse::<usize>() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(field) => return Ok(Some(field)),
+                },
+            }
+        };
+        let mut ranges = Vec::new();
+        for item in val.split(',') {
+            let (start, end) = match item.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None => match number(item)? {
+                    Some(field) => (Some(field), Some(field)),
+                    None => return Err(invalid()),
+                },
+            };
+            let start = start.unwrap_or(1);
+            if end.is_some_and(|end| end < start) {
+                return Err(invalid());
+            }
+            ranges.push((start, end));
+        }
+        return Ok(FieldSelector { ranges });
+    }
+
+    /// Returns whether the 1-based `field` is selected.
+    pub fn contains(&self, field: usize) -> bool {
+        return self
+            .ranges
+            .iter()
+            .any(|(start, end)| field >= *start && end.is_none_or(|end| field <= end));
+    }
+}
+
+/// Splits `text` at every match of `engine`, like `Regex::split`. Empty
+/// fields at either end are dropped unless `keep_empty`.
+pub fn split<'t>(
+    engine: &dyn Engine,
+    text: &'t str,
+    keep_empty: bool,
+) -> Result<Vec<&'t str>, CliError> {
+    let mut fields = Vec::new();
+    let mut last = 0;
+    for captures in engine.captures_iter(text) {
+        let found = captures?.found();
+        fields.push(&text[last..found.start()]);
+        last = found.end();
+    }
+    fields.push(&text[last..]);
+    if !keep_empty {
+        let first = fields.iter().position(|field| !field.is_empty());
+        let last = fields.iter().rposition(|field| !field.is_empty());
+        match (first, last) {
+            (Some(first), Some(last)) => {
+                fields.truncate(last + 1);
+                fields.drain(..first);
+            }
+            _ => fields.clear(),
+        }
+    }
+    return Ok(fields);
+}
@@ -0,0 +1,310 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::{Engine, Groups};
+use super::error::*;
+use super::scan::ScanBuffer;
+use super::template::{GroupRef, Template};
+use crate::traceln;
+use regex::Regex;
+use std::io::Read;
+use std::io::Write;
+
+/// The least held back from the end of a window that is not the last, so that
+/// `\z`, and `$` before a final newline with pcre2, do not take the end of
+/// the window for the end of the input.
+const END_MARGIN: usize = 2;
+
+/// Replaces every match of `regex` read from `reader`, writing the result to
+/// `writer` as each window is processed, and returns the number of replacements.
+///
+/// Up to `window - 1` bytes are held back from the end of each window so that
+/// a match touching it can be completed by the next read. Matches that need
+/// more lookahead than that to be decided may differ from `replace_all`, and
+/// so may look-behind assertions, with the fancy and pcre2 engines, that
+/// reach back further than one character into the previous window. `\A` and
+/// `\z` match only at the start and end of the whole input.
+pub fn replace_stream<R: Read, W: Write>(
+    regex: &Regex,
+    replacement: &str,
+    reader: R,
+    writer: W,
+    window: usize,
+) -> Result<u64, CliError> {
+    let template = Template::parse(replacement);
+    return replace_stream_template(regex as &dyn Engine, &template, reader, writer, window);
+}
+
+/// Like `replace_stream` with an already parsed replacement template.
+pub fn replace_stream_template<R: Read, W: Write>(
+    engine: &dyn Engine,
+    template: &Template,
+    reader: R,
+    writer: W,
+    window: usize,
+) -> Result<u64, CliError> {
+    let mut expanded = String::new();
+    return scan_stream(
+        engine,
+        reader,
+        writer,
+        window,
+        true,
+        |captures, replacements, writer| {
+            expanded.clear();
+            template.expand(captures, replacements, &mut expanded)?;
+            writer.write_all(expanded.as_bytes())?;
+            return Ok(());
+        },
+    );
+}
+
+/// Writes only the text of `group` in each match of `engine` read from
+/// `reader`, each followed by `separator`, and returns the number of matches.
+/// Matches where the group did not participate are skipped.
+pub fn extract_stream<R: Read, W: Write>(
+    engine: &dyn Engine,
+    group: &GroupRef,
+    separator: &str,
+    reader: R,
+    writer: W,
+    window: usize,
+) -> Result<u64, CliError> {
+    return scan_stream(
+        engine,
+        reader,
+        writer,
+        window,
+        false,
+        |captures, _, writer| {
+            if let Some(found) = group.get(captures) {
+                writer.write_all(found.as_str().as_bytes())?;
+                writer.write_all(separator.as_bytes())?;
+            }
+            return Ok(());
+        },
+    );
+}
+
+/// Hands each match read from `reader` to `on_match` along with the number of
+/// matches before it, copying the text between matches to `writer` when
+/// `keep_unmatched`.
+fn scan_stream<R, W, F>(
+    engine: &dyn Engine,
+    reader: R,
+    mut writer: W,
+    window: usize,
+    keep_unmatched: bool,
+    mut on_match: F,
+) -> Result<u64, CliError>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&Groups, u64, &mut W) -> Result<(), CliError>,
+{
+    let window = window.max(1);
+    let mut scanner = ScanBuffer::new(reader, window);
+    let mut replacements: u64 = 0;
+    let mut context: usize = 0;
+    let mut after_match = false;
+    let mut offset: usize = 0;
+
+    loop {
+        scanner.shift()?;
+        let consumed = scanner.process(|buffer, eof| -> Result<Option<usize>, CliError> {
+            let text = match std::str::from_utf8(buffer) {
+                Ok(text) => text,
+                Err(error) if error.error_len().is_none() && !eof => {
+                    std::str::from_utf8(&buffer[..error.valid_up_to()]).unwrap()
+                }
+                Err(error) => {
+                    return Err(CliError::from(format!(
+                        "invalid UTF-8 at byte {}",
+                        offset + error.valid_up_to()
+                    )))
+                }
+            };
+            // Matches are only accepted up to `limit` until the end of the
+            // input. Every window after the first starts with the character
+            // before `context`, which gives `\b` something to look at and
+            // keeps `\A` from matching there.
+            let margin = (window - 1).max(END_MARGIN);
+            let limit = match eof {
+                true => text.len(),
+                false => floor_char_boundary(text, text.len().saturating_sub(margin)),
+            };
+            if limit <= context && !eof {
+                return Ok(Some(0));
+            }
+
+            let mut last = context;
+            let mut at = context;
+            let mut emit_to = limit;
+            while at <= text.len() {
+                let captures = match engine.captures_at(text, at)? {
+                    Some(captures) => captures,
+                    None => break,
+                };
+                let found = captures.get(0).unwrap();
+                if found.start() == found.end() && found.start() == last && after_match {
+                    at = next_char_boundary(text, at);
+                    continue;
+                }
+                if !eof && found.end() > limit {
+                    emit_to = found.start().min(limit);
+                    break;
+                }
+                if keep_unmatched {
+                    writer.write_all(&text.as_bytes()[last..found.start()])?;
+                }
+                on_match(&captures, replacements, &mut writer)?;
+                replacements += 1;
+                last = found.end();
+                after_match = true;
+                at = match found.start() == found.end() {
+                    true => next_char_boundary(text, found.end()),
+                    false => found.end(),
+                };
+            }
+            traceln!(
+                "stream window: {} bytes buffered, {} replaced so far, emitting {}..{}{}",
+                text.len(),
+                replacements,
+                context,
+                emit_to,
+                match eof {
+                    true => " at end of input",
+                    false => "",
+                }
+            );
+            if emit_to > last {
+                if keep_unmatched {
+                    writer.write_all(&text.as_bytes()[last..emit_to])?;
+                }
+                after_match = false;
+            }
+            if eof {
+                return Ok(None);
+            }
+
+            let keep = match text[..emit_to].char_indices().next_back() {
+                Some((index, _)) => index,
+                None => emit_to,
+            };
+            context = emit_to - keep;
+            return Ok(Some(keep));
+        })?;
+        match consumed {
+            Some(consumed) => {
+                scanner.consume(consumed);
+                offset += consumed;
+            }
+            None => break,
+        }
+    }
+    writer.flush()?;
+    return Ok(replacements);
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    return index;
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    match text[index..].chars().next() {
+        Some(c) => return index + c.len_utf8(),
+        None => return index + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, replacement: &str, input: &str, window: usize) -> (String, u64) {
+        let regex = Regex::new(pattern).unwrap();
+        let mut output = Vec::new();
+        let count =
+            replace_stream(&regex, replacement, input.as_bytes(), &mut output, window).unwrap();
+        return (String::from_utf8(output).unwrap(), count);
+    }
+
+    #[test]
+    fn one_byte_window() {
+        assert_eq!(replace("ab", "X", "xxabyyab", 1), ("xxXyyX".to_string(), 2));
+        assert_eq!(replace("é", "e", "café é", 1), ("cafe e".to_string(), 2));
+    }
+
+    #[test]
+    fn large_window() {
+        let input = "ab ".repeat(1000);
+        let (output, count) = replace("ab", "X", &input, 4096);
+        assert_eq!(output, "X ".repeat(1000));
+        assert_eq!(count, 1000);
+    }
+
+    #[test]
+    fn match_split_across_windows() {
+        // Every split point of both matches is covered once the window is at
+        // least as long as the match.
+        for window in 6..16 {
+            let (output, count) = replace("abcdef", "X", "..abcdef..abcdef", window);
+            assert_eq!(output, "..X..X", "window {}", window);
+            assert_eq!(count, 2, "window {}", window);
+        }
+    }
+
+    #[test]
+    fn anchors_match_whole_input() {
+        for window in 1..8 {
+            assert_eq!(
+                replace(r"\Aa", "X", "aaaa", window).0,
+                "Xaaa",
+                "window {}",
+                window
+            );
+            assert_eq!(
+                replace(r"a\z", "X", "aaaa", window).0,
+                "aaaX",
+                "window {}",
+                window
+            );
+            assert_eq!(
+                replace(r"\Aa\z", "X", "aaaa", window).0,
+                "aaaa",
+                "window {}",
+                window
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let regex = Regex::new("a").unwrap();
+        let mut output = Vec::new();
+        let input: &[u8] = b"aa\xffa";
+        assert!(replace_stream(&regex, "X", input, &mut output, 2).is_err());
+    }
+}
@@ -0,0 +1,620 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::engine::{Group, Groups};
+use super::error::*;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupRef {
+    Index(usize),
+    Name(String),
+}
+
+impl GroupRef {
+    /// A group index when `name` is a number, otherwise a group name.
+    pub fn parse(name: &str) -> GroupRef {
+        match name.parse::<usize>() {
+            Ok(index) => return GroupRef::Index(index),
+            Err(_) => return GroupRef::Name(String::from(name)),
+        }
+    }
+
+    pub fn get<'t>(&self, captures: &Groups<'t>) -> Option<Group<'t>> {
+        match self {
+            GroupRef::Index(index) => return captures.get(*index),
+            GroupRef::Name(name) => return captures.name(name.as_str()),
+        }
+    }
+
+    pub fn exists(&self, names: &[Option<String>]) -> bool {
+        match self {
+            GroupRef::Index(index) => return *index < names.len(),
+            GroupRef::Name(name) => {
+                return names
+                    .iter()
+                    .any(|other| other.as_deref() == Some(name.as_str()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Width {
+    width: usize,
+    zero: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Group(GroupRef),
+    Number(i128),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Expression {
+    operands: Vec<Operand>,
+    operators: Vec<char>,
+}
+
+/// The match with `prefix` and `suffix` around one of its groups.
+#[derive(Debug, Clone, PartialEq)]
+struct Wrap {
+    group: GroupRef,
+    prefix: String,
+    suffix: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Group(GroupRef),
+    Counter(Width),
+    Variable(String),
+    Arithmetic(Expression),
+    Start(Case),
+    End,
+    Next(Case),
+    Wrap(Wrap),
+}
+
+/// A parsed replacement string.
+///
+/// Group references use the regex crate's syntax (`$1`, `${1}`, `$name`,
+/// `${name}`, `$$` for a literal dollar). On top of that, `\U` and `\L`
+/// upper/lowercase everything up to the matching `\E` (they nest), `\u` and `\l`
+/// change only the next character, and `\\` is a literal backslash. Case
+/// escapes apply to expanded group text as well, so `\U${1}` uppercases the
+/// whole group and `\u\L${1}` capitalizes it. Any other backslash is kept as is.
+///
+/// `${rp:count}` expands to the counter value of the current match, optionally
+/// padded to a width as in `${rp:count:3}` or `${rp:count:03}`. Other `${rp:...}`
+/// variables are substituted by `resolve` before any matching takes place.
+///
+/// When parsed with `numeric`, a braced reference containing `+`, `-`, `*` or
+/// `/` is evaluated as integer arithmetic: `${1+1}`, `${size*512}`. The first
+/// operand is always a group; later ones are groups when written as `$N` or
+/// `$name` (or a non-numeric name) and integer literals otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    tokens: Vec<Token>,
+    counter_start: i64,
+    counter_step: i64,
+}
+
+impl Template {
+    pub fn parse(replacement: &str) -> Template {
+        return Template::parse_with(replacement, false);
+    }
+
+    pub fn parse_with(replacement: &str, numeric: bool) -> Template {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut rest = replacement;
+        while let Some(c) = rest.chars().next() {
+            let token = match c {
+                '$' => match parse_group(rest) {
+                    Some((GroupRef::Name(name), len)) if name.starts_with("rp:count") => {
+                        match parse_counter(&name["rp:count".len()..]) {
+                            Some(width) => {
+                                rest = &rest[len..];
+                                Some(Token::Counter(width))
+                            }
+                            None => {
+                                rest = &rest[len..];
+                                Some(Token::Variable(name))
+                            }
+                        }
+                    }
+                    Some((GroupRef::Name(name), len)) if name.starts_with("rp:") => {
+                        rest = &rest[len..];
+                        Some(Token::Variable(name))
+                    }
+                    Some((GroupRef::Name(name), len)) if numeric && is_expression(&name) => {
+                        match parse_expression(&name) {
+                            Some(expression) => {
+                                rest = &rest[len..];
+                                Some(Token::Arithmetic(expression))
+                            }
+                            None => {
+                                rest = &rest[len..];
+                                Some(Token::Group(GroupRef::Name(name)))
+                            }
+                        }
+                    }
+                    Some((group, len)) => {
+                        rest = &rest[len..];
+                        Some(Token::Group(group))
+                    }
+                    None => {
+                        let len = match rest.starts_with("$$") {
+                            true => 2,
+                            false => 1,
+                        };
+                        literal.push('$');
+                        rest = &rest[len..];
+                        None
+                    }
+                },
+                '\\' => {
+                    let token = match rest[1..].chars().next() {
+                        Some('U') => Some(Token::Start(Case::Upper)),
+                        Some('L') => Some(Token::Start(Case::Lower)),
+                        Some('E') => Some(Token::End),
+                        Some('u') => Some(Token::Next(Case::Upper)),
+                        Some('l') => Some(Token::Next(Case::Lower)),
+                        _ => None,
+                    };
+                    match (token, rest.starts_with("\\\\")) {
+                        (Some(token), _) => {
+                            rest = &rest[2..];
+                            Some(token)
+                        }
+                        (None, true) => {
+                            literal.push('\\');
+                            rest = &rest[2..];
+                            None
+                        }
+                        (None, false) => {
+                            literal.push('\\');
+                            rest = &rest[1..];
+                            None
+                        }
+                    }
+                }
+                c => {
+                    literal.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    None
+                }
+            };
+            if let Some(token) = token {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(token);
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        return Template {
+            tokens,
+            counter_start: 1,
+            counter_step: 1,
+        };
+    }
+
+    /// A template that writes `replacement` as it is, without groups, case
+    /// escapes or variables.
+    pub fn literal(replacement: &str) -> Template {
+        let tokens = match replacement.is_empty() {
+            true => Vec::new(),
+            false => vec![Token::Literal(String::from(replacement))],
+        };
+        return Template {
+            tokens,
+            counter_start: 1,
+            counter_step: 1,
+        };
+    }
+
+    /// A template that writes the match with `prefix` and `suffix` around
+    /// `group`, both taken literally. Group 0 wraps the whole match. Matches
+    /// where the group did not take part are written as they are.
+    pub fn wrap(group: GroupRef, prefix: &str, suffix: &str) -> Template {
+        return Template {
+            tokens: vec![Token::Wrap(Wrap {
+                group,
+                prefix: String::from(prefix),
+                suffix: String::from(suffix),
+            })],
+            counter_start: 1,
+            counter_step: 1,
+        };
+    }
+
+    pub fn with_counter(mut self, start: i64, step: i64) -> Template {
+        self.counter_start = start;
+        self.counter_step = step;
+        return self;
+    }
+
+    /// Returns a copy of the template with every `${rp:...}` variable replaced by
+    /// the value `lookup` gives for its name (without the `rp:` prefix).
+    pub fn resolve<F>(&self, lookup: F) -> Result<Template, CliError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut resolved = self.clone();
+        for token in resolved.tokens.iter_mut() {
+            if let Token::Variable(name) = token {
+                match lookup(&name["rp:".len()..]) {
+                    Some(value) => *token = Token::Literal(value),
+                    None => {
+                        return Err(CliError::from(format!(
+                            "unknown replacement variable: ${{{}}}",
+                            name
+                        )))
+                    }
+                }
+            }
+        }
+        return Ok(resolved);
+    }
+
+    /// Returns every group the template refers to, including arithmetic operands.
+    pub fn groups(&self) -> Vec<&GroupRef> {
+        let mut groups = Vec::new();
+        for token in self.tokens.iter() {
+            match token {
+                Token::Group(group) => groups.push(group),
+                Token::Wrap(wrap) => groups.push(&wrap.group),
+                Token::Arithmetic(expression) => {
+                    for operand in expression.operands.iter() {
+                        if let Operand::Group(group) = operand {
+                            groups.push(group);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        return groups;
+    }
+
+    /// Expands the template for the `index`th (0-based) match into `dst`.
+    pub fn expand(&self, captures: &Groups, index: u64, dst: &mut String) -> Result<(), CliError> {
+        let mut modes: Vec<Case> = Vec::new();
+        let mut next: Option<Case> = None;
+        for token in self.tokens.iter() {
+            match token {
+                Token::Literal(text) => push_cased(dst, text, modes.last(), &mut next),
+                Token::Group(group) => {
+                    if let Some(found) = group.get(captures) {
+                        push_cased(dst, found.as_str(), modes.last(), &mut next);
+                    }
+                }
+                Token::Counter(width) => {
+                    let value = self.counter_start + self.counter_step * index as i64;
+                    let text = match width.zero {
+                        true => format!("{:0width$}", value, width = width.width),
+                        false => format!("{:width$}", value, width = width.width),
+                    };
+                    push_cased(dst, text.as_str(), modes.last(), &mut next);
+                }
+                Token::Variable(name) => dst.push_str(&format!("${{{}}}", name)),
+                Token::Arithmetic(expression) => {
+                    let value = expression.evaluate(captures)?;
+                    push_cased(dst, value.to_string().as_str(), modes.last(), &mut next);
+                }
+                Token::Start(case) => modes.push(*case),
+                Token::End => {
+                    modes.pop();
+                }
+                Token::Next(case) => next = Some(*case),
+                Token::Wrap(wrap) => wrap.expand(captures, dst),
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl Wrap {
+    fn expand(&self, captures: &Groups, dst: &mut String) {
+        let whole = match captures.get(0) {
+            Some(whole) => whole,
+            None => return,
+        };
+        let text = whole.as_str();
+        match self.group.get(captures) {
+            // Groups in lookarounds or before \K can lie outside the match.
+            Some(group) if group.start() >= whole.start() && group.end() <= whole.end() => {
+                dst.push_str(&text[..group.start() - whole.start()]);
+                dst.push_str(self.prefix.as_str());
+                dst.push_str(group.as_str());
+                dst.push_str(self.suffix.as_str());
+                dst.push_str(&text[group.end() - whole.start()..]);
+            }
+            _ => dst.push_str(text),
+        }
+    }
+}
+
+fn parse_group(text: &str) -> Option<(GroupRef, usize)> {
+    let rest = &text[1..];
+    let (name, len) = match rest.strip_prefix('{') {
+        Some(braced) => match braced.find('}') {
+            Some(end) => (&braced[..end], end + 3),
+            None => return None,
+        },
+        None => {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], end + 1)
+        }
+    };
+    if name.is_empty() {
+        return None;
+    }
+    return Some((GroupRef::parse(name), len));
+}
+
+/// Returns the names of the unbraced `$name` references in `replacement`,
+/// skipping `$$` escapes.
+pub fn unbraced_references(replacement: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = replacement;
+    while let Some(start) = rest.find('$') {
+        rest = &rest[start + 1..];
+        if let Some(escaped) = rest.strip_prefix('$') {
+            rest = escaped;
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if end > 0 {
+            names.push(&rest[..end]);
+        }
+        rest = &rest[end..];
+    }
+    return names;
+}
+
+/// Looks up the file metadata variables (`path`, `filename`, `stem`, `dirname`
+/// and `ext`) for `path`.
+pub fn path_variable(path: &str, name: &str) -> Option<String> {
+    let file = Path::new(path);
+    let text = |value: Option<&std::ffi::OsStr>| match value {
+        Some(value) => value.to_string_lossy().into_owned(),
+        None => String::new(),
+    };
+    match name {
+        "path" => return Some(String::from(path)),
+        "filename" => return Some(text(file.file_name())),
+        "stem" => return Some(text(file.file_stem())),
+        "ext" => return Some(text(file.extension())),
+        "dirname" => match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                return Some(parent.to_string_lossy().into_owned())
+            }
+            _ => return Some(String::from(".")),
+        },
+        _ => return None,
+    }
+}
+
+/// Replaces `${env:NAME}` and `${env:NAME:-default}` with the value of the
+/// environment variable, escaped so that it is inserted literally. `$$` is
+/// skipped so an escaped reference is left alone.
+pub fn expand_env(replacement: &str) -> Result<String, CliError> {
+    let mut expanded = String::with_capacity(replacement.len());
+    let mut rest = replacement;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$$") {
+            expanded.push_str("$$");
+            rest = &rest[2..];
+            continue;
+        }
+        let end = match rest.strip_prefix("${env:").and_then(|body| body.find('}')) {
+            Some(end) => end + "${env:".len(),
+            None => {
+                expanded.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let body = &rest["${env:".len()..end];
+        let (name, default) = match body.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (body, None),
+        };
+        let value = match (std::env::var(name), default) {
+            (Ok(value), _) => value,
+            (Err(_), Some(default)) => String::from(default),
+            (Err(error), None) => {
+                return Err(CliError::from(format!(
+                    "environment variable {}: {}",
+                    name, error
+                )))
+            }
+        };
+        expanded.push_str(escape_replacement(value.as_str()).as_str());
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    return Ok(expanded);
+}
+
+impl Expression {
+    fn evaluate(&self, captures: &Groups) -> Result<i128, CliError> {
+        let mut values = Vec::with_capacity(self.operands.len());
+        for operand in self.operands.iter() {
+            values.push(operand.value(captures)?);
+        }
+        let mut terms = vec![values[0]];
+        let mut signs = Vec::new();
+        for (operator, value) in self.operators.iter().zip(values[1..].iter()) {
+            match operator {
+                '*' | '/' => {
+                    let term = terms.pop().unwrap();
+                    let result = match operator {
+                        '*' => term.checked_mul(*value),
+                        _ if *value == 0 => return Err(CliError::from("division by zero")),
+                        _ => term.checked_div(*value),
+                    };
+                    terms.push(result.ok_or_else(overflow)?);
+                }
+                _ => {
+                    signs.push(*operator);
+                    terms.push(*value);
+                }
+            }
+        }
+        let mut result = terms[0];
+        for (sign, term) in signs.iter().zip(terms[1..].iter()) {
+            let next = match sign {
+                '+' => result.checked_add(*term),
+                _ => result.checked_sub(*term),
+            };
+            result = next.ok_or_else(overflow)?;
+        }
+        return Ok(result);
+    }
+}
+
+impl Operand {
+    fn value(&self, captures: &Groups) -> Result<i128, CliError> {
+        let (group, found) = match self {
+            Operand::Number(number) => return Ok(*number),
+            Operand::Group(GroupRef::Index(index)) => (index.to_string(), captures.get(*index)),
+            Operand::Group(GroupRef::Name(name)) => (name.clone(), captures.name(name.as_str())),
+        };
+        match found {
+            Some(found) => match found.as_str().trim().parse::<i128>() {
+                Ok(value) => return Ok(value),
+                Err(_) => {
+                    return Err(CliError::from(format!(
+                        "group {} is not a number: \"{}\"",
+                        group,
+                        found.as_str()
+                    )))
+                }
+            },
+            None => return Err(CliError::from(format!("group {} did not match", group))),
+        }
+    }
+}
+
+fn overflow() -> CliError {
+    return CliError::from("arithmetic overflow");
+}
+
+fn is_expression(text: &str) -> bool {
+    return text.contains(['+', '-', '*', '/']);
+}
+
+fn parse_expression(text: &str) -> Option<Expression> {
+    let mut operands = Vec::new();
+    let mut operators = Vec::new();
+    let mut rest = text.trim();
+    loop {
+        let negative = !operands.is_empty() && rest.starts_with('-');
+        if negative {
+            rest = rest[1..].trim_start();
+        }
+        let explicit = rest.starts_with('$');
+        if explicit {
+            rest = &rest[1..];
+        }
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        if name.is_empty() {
+            return None;
+        }
+        let operand = match (operands.is_empty() || explicit, name.parse::<i128>()) {
+            (false, Ok(number)) if negative => Operand::Number(-number),
+            (false, Ok(number)) => Operand::Number(number),
+            (_, _) if negative => return None,
+            (_, Ok(index)) => Operand::Group(GroupRef::Index(index as usize)),
+            (_, Err(_)) => Operand::Group(GroupRef::Name(String::from(name))),
+        };
+        operands.push(operand);
+        rest = rest[end..].trim_start();
+        match rest.chars().next() {
+            Some(operator) if matches!(operator, '+' | '-' | '*' | '/') => {
+                operators.push(operator);
+                rest = rest[1..].trim_start();
+            }
+            Some(_) => return None,
+            None => break,
+        }
+    }
+    return Some(Expression {
+        operands,
+        operators,
+    });
+}
+
+fn parse_counter(spec: &str) -> Option<Width> {
+    let spec = match spec.strip_prefix(':') {
+        Some(spec) => spec,
+        None if spec.is_empty() => "0",
+        None => return None,
+    };
+    match spec.parse::<usize>() {
+        Ok(width) => {
+            return Some(Width {
+                width,
+                zero: spec.len() > 1 && spec.starts_with('0'),
+            })
+        }
+        Err(_) => return None,
+    }
+}
+
+fn push_cased(dst: &mut String, text: &str, mode: Option<&Case>, next: &mut Option<Case>) {
+    for c in text.chars() {
+        match next.take().or_else(|| mode.copied()) {
+            Some(Case::Upper) => dst.extend(c.to_uppercase()),
+            Some(Case::Lower) => dst.extend(c.to_lowercase()),
+            None => dst.push(c),
+        }
+    }
+}
+
+/// Escapes `text` so that a template expands it literally.
+pub fn escape_replacement(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('$', "$$");
+}
@@ -0,0 +1,92 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use std::borrow::Cow;
+use std::io::IsTerminal;
+
+/// The width assumed when the terminal cannot be asked.
+pub const DEFAULT_WIDTH: usize = 80;
+
+const ELLIPSIS: char = '…';
+
+/// Whether output is colored for the `--color` `setting`. With `auto`, a
+/// non-empty NO_COLOR turns color off, CLICOLOR_FORCE other than `0` turns it
+/// on, and otherwise it is on when stdout is a terminal.
+pub fn use_color(setting: &str) -> bool {
+    match setting {
+        "always" => return true,
+        "never" => return false,
+        _ => {}
+    }
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    match std::env::var("CLICOLOR_FORCE") {
+        Ok(value) if !value.is_empty() && value != "0" => return true,
+        _ => return std::io::stdout().is_terminal(),
+    }
+}
+
+/// The number of columns of the terminal on stdout, else of COLUMNS, else
+/// `DEFAULT_WIDTH`.
+pub fn width() -> usize {
+    if let Some(columns) = terminal_columns() {
+        return columns;
+    }
+    match std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+    {
+        Some(columns) if columns > 0 => return columns,
+        _ => return DEFAULT_WIDTH,
+    }
+}
+
+#[cfg(unix)]
+fn terminal_columns() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only writes to the winsize it is given.
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        match libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) {
+            0 if size.ws_col > 0 => return Some(size.ws_col as usize),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn terminal_columns() -> Option<usize> {
+    return None;
+}
+
+/// Cuts `line` to at most `width` characters, ending it with an ellipsis when
+/// anything was left out.
+pub fn fit(line: &str, width: usize) -> Cow<'_, str> {
+    match line.char_indices().nth(width) {
+        Some(_) if width == 0 => return Cow::Borrowed(""),
+        Some(_) => {
+            let (end, _) = line.char_indices().nth(width - 1).unwrap();
+            return Cow::Owned(format!("{}{}", &line[..end], ELLIPSIS));
+        }
+        None => return Cow::Borrowed(line),
+    }
+}
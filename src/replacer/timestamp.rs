@@ -0,0 +1,371 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// An instant in seconds since the Unix epoch, shown at `offset` seconds east
+/// of UTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamp {
+    seconds: i64,
+    offset: i32,
+}
+
+/// The calendar fields of a timestamp at its offset.
+struct Civil {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    weekday: usize,
+    yearday: u32,
+}
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(error) => -(error.duration().as_secs() as i64),
+        };
+        return Timestamp { seconds, offset: 0 };
+    }
+
+    /// Parses an RFC 3339 date and time such as `2024-05-01T12:00:00Z` or
+    /// `2024-05-01 14:00:00.5+02:00`. Fractions of a second are dropped.
+    pub fn parse(val: &str) -> Result<Timestamp, CliError> {
+        let invalid = || CliError::from(format!("invalid RFC 3339 timestamp: {}", val));
+        let bytes = val.as_bytes();
+        let number = |start: usize, len: usize| -> Result<i64, CliError> {
+            match val.get(start..start + len) {
+                Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                    return Ok(digits.parse::<i64>().unwrap())
+                }
+                _ => return Err(invalid()),
+            }
+        };
+        if bytes.len() < 20
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || !matches!(bytes[10], b'T' | b't' | b' ')
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+        {
+            return Err(invalid());
+        }
+        let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+        let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+        if !(1..=12).contains(&month)
+            || day < 1
+            || day > days_in_month(year, month as u32) as i64
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return Err(invalid());
+        }
+        let mut rest = &val[19..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(fraction.len());
+            if digits == 0 {
+                return Err(invalid());
+            }
+            rest = &fraction[digits..];
+        }
+        let offset = match rest {
+            "Z" | "z" => 0,
+            _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+                let sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return Err(invalid()),
+                };
+                let start = val.len() - 5;
+                let (hours, minutes) = (number(start, 2)?, number(start + 3, 2)?);
+                if hours > 23 || minutes > 59 {
+                    return Err(invalid());
+                }
+                sign * (hours * 3600 + minutes * 60)
+            }
+            _ => return Err(invalid()),
+        };
+        let days = days_from_civil(year, month as u32, day as u32);
+        let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+        return Ok(Timestamp {
+            seconds,
+            offset: offset as i32,
+        });
+    }
+
+    /// Reads a point in time given as an RFC 3339 timestamp, as `@SECONDS`
+    /// since the Unix epoch or as the path of a file whose modification time
+    /// is taken.
+    pub fn reference(val: &str) -> Result<SystemTime, CliError> {
+        if let Some(seconds) = val.strip_prefix('@') {
+            return match seconds.parse::<i64>() {
+                Ok(seconds) => Ok(Timestamp { seconds, offset: 0 }.system_time()),
+                Err(_) => Err(CliError::from(format!("invalid Unix time: {}", val))),
+            };
+        }
+        if let Ok(timestamp) = Timestamp::parse(val) {
+            return Ok(timestamp.system_time());
+        }
+        return std::fs::metadata(val)
+            .and_then(|meta| meta.modified())
+            .map_err(|error| {
+                CliError::from(format!(
+                    "{}: not an RFC 3339 time, @SECONDS or a readable file: {}",
+                    val, error
+                ))
+            });
+    }
+
+    pub fn system_time(&self) -> SystemTime {
+        match self.seconds < 0 {
+            true => return UNIX_EPOCH - Duration::from_secs(self.seconds.unsigned_abs()),
+            false => return UNIX_EPOCH + Duration::from_secs(self.seconds as u64),
+        }
+    }
+
+    /// The same instant shown in UTC.
+    pub fn utc(self) -> Timestamp {
+        return Timestamp {
+            seconds: self.seconds,
+            offset: 0,
+        };
+    }
+
+    /// The same instant shown in the local time zone.
+    pub fn local(self) -> Timestamp {
+        return Timestamp {
+            seconds: self.seconds,
+            offset: local_offset(self.seconds),
+        };
+    }
+
+    /// The value of the `${rp:...}` variable `name`: `date` and `datetime` in
+    /// ISO 8601 and RFC 3339 form, or either followed by `:FORMAT` in the
+    /// style of strftime.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "date" => return self.format("%Y-%m-%d"),
+            "datetime" => return Some(self.rfc3339()),
+            _ => match name.split_once(':') {
+                Some(("date", format)) | Some(("datetime", format)) => return self.format(format),
+                _ => return None,
+            },
+        }
+    }
+
+    pub fn rfc3339(&self) -> String {
+        let zone = match self.offset {
+            0 => String::from("Z"),
+            _ => self.zone(true),
+        };
+        return format!("{}{}", self.format("%Y-%m-%dT%H:%M:%S").unwrap(), zone);
+    }
+
+    /// Formats the timestamp with the strftime conversions `%Y %C %y %m %d %e
+    /// %j %H %I %M %S %p %a %A %b %h %B %u %w %z %:z %s %F %T %D %R %n %t %%`,
+    /// or `None` for any other.
+    pub fn format(&self, format: &str) -> Option<String> {
+        let civil = self.civil();
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let hour12 = match civil.hour % 12 {
+                0 => 12,
+                hour => hour,
+            };
+            match chars.next()? {
+                'Y' => out.push_str(&format!("{:04}", civil.year)),
+                'C' => out.push_str(&format!("{:02}", civil.year.div_euclid(100))),
+                'y' => out.push_str(&format!("{:02}", civil.year.rem_euclid(100))),
+                'm' => out.push_str(&format!("{:02}", civil.month)),
+                'd' => out.push_str(&format!("{:02}", civil.day)),
+                'e' => out.push_str(&format!("{:2}", civil.day)),
+                'j' => out.push_str(&format!("{:03}", civil.yearday)),
+                'H' => out.push_str(&format!("{:02}", civil.hour)),
+                'I' => out.push_str(&format!("{:02}", hour12)),
+                'M' => out.push_str(&format!("{:02}", civil.minute)),
+                'S' => out.push_str(&format!("{:02}", civil.second)),
+                'p' => match civil.hour < 12 {
+                    true => out.push_str("AM"),
+                    false => out.push_str("PM"),
+                },
+                'a' => out.push_str(&WEEKDAYS[civil.weekday][..3]),
+                'A' => out.push_str(WEEKDAYS[civil.weekday]),
+                'b' | 'h' => out.push_str(&MONTHS[civil.month as usize - 1][..3]),
+                'B' => out.push_str(MONTHS[civil.month as usize - 1]),
+                'u' => out.push_str(&format!("{}", (civil.weekday + 6) % 7 + 1)),
+                'w' => out.push_str(&format!("{}", civil.weekday)),
+                'z' => out.push_str(&self.zone(false)),
+                ':' => match chars.next()? {
+                    'z' => out.push_str(&self.zone(true)),
+                    _ => return None,
+                },
+                's' => out.push_str(&format!("{}", self.seconds)),
+                'F' => out.push_str(&self.format("%Y-%m-%d")?),
+                'T' => out.push_str(&self.format("%H:%M:%S")?),
+                'D' => out.push_str(&self.format("%m/%d/%y")?),
+                'R' => out.push_str(&self.format("%H:%M")?),
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                '%' => out.push('%'),
+                _ => return None,
+            }
+        }
+        return Some(out);
+    }
+
+    fn zone(&self, colon: bool) -> String {
+        let sign = match self.offset < 0 {
+            true => '-',
+            false => '+',
+        };
+        let minutes = self.offset.unsigned_abs() / 60;
+        match colon {
+            true => return format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60),
+            false => return format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60),
+        }
+    }
+
+    fn civil(&self) -> Civil {
+        let local = self.seconds + self.offset as i64;
+        let days = local.div_euclid(86400);
+        let time = local.rem_euclid(86400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        return Civil {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+            weekday: (days + 4).rem_euclid(7) as usize,
+            yearday: (days - days_from_civil(year, 1, 1)) as u32 + 1,
+        };
+    }
+}
+
+/// Formats the current UTC time of day as `HH:MM:SS`.
+pub fn time_of_day() -> String {
+    return Timestamp::now().format("%T").unwrap();
+}
+
+fn is_leap(year: i64) -> bool {
+    return year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap(year) => return 29,
+        2 => return 28,
+        4 | 6 | 9 | 11 => return 30,
+        _ => return 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = match month <= 2 {
+        true => year - 1,
+        false => year,
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = match month_from_march < 10 {
+        true => month_from_march + 3,
+        false => month_from_march - 9,
+    } as u32;
+    let year = year_of_era + era * 400;
+    match month <= 2 {
+        true => return (year + 1, month, day),
+        false => return (year, month, day),
+    }
+}
+
+#[cfg(unix)]
+fn local_offset(seconds: i64) -> i32 {
+    let time = seconds as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm it is given.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        match libc::localtime_r(&time, &mut tm).is_null() {
+            true => return 0,
+            false => return tm.tm_gmtoff as i32,
+        }
+    }
+}
+
+/// Without a way to ask for the zone, local time is UTC.
+#[cfg(not(unix))]
+fn local_offset(_seconds: i64) -> i32 {
+    return 0;
+}
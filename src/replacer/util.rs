@@ -21,26 +21,443 @@
 *   SOFTWARE.
 */
 use super::error::*;
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 pub fn read_file(path: &str) -> Result<String, CliError> {
     let mut buf = String::new();
-    match File::open(&path)?.read_to_string(&mut buf) {
+    match File::open(path)?.read_to_string(&mut buf) {
         Ok(_) => Ok(buf),
         Err(error) => Err(CliError::from(error)),
     }
 }
 
+/// Reads the file at `path`, or all of stdin when `path` is `-`.
+pub fn read_file_or_stdin(path: &str) -> Result<String, CliError> {
+    if path != "-" {
+        return read_file(path);
+    }
+    let mut buf = String::new();
+    std::io::stdin().read_to_string(&mut buf)?;
+    return Ok(buf);
+}
+
+/// Reads past a UTF-8 byte order mark at the start of `reader`. Returns
+/// whether there was one, and the rest of the input.
+pub fn skip_bom<R: Read>(mut reader: R) -> Result<(bool, impl Read), CliError> {
+    let mut head = Vec::with_capacity(3);
+    (&mut reader).take(3).read_to_end(&mut head)?;
+    let bom = head == "\u{feff}".as_bytes();
+    if bom {
+        head.clear();
+    }
+    return Ok((bom, std::io::Cursor::new(head).chain(reader)));
+}
+
+/// Strips exactly one trailing `\n` or `\r\n`.
+pub fn strip_trailing_newline(mut text: String) -> String {
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+    return text;
+}
+
+/// Interprets C-style escapes: `\n`, `\t`, `\r`, `\0`, `\xNN`, `\u{...}` and
+/// `\\`. With `template`, the case escapes of replacement templates are kept
+/// and decoded characters are escaped again so the template treats them
+/// literally.
+pub fn unescape(val: &str, template: bool) -> Result<String, CliError> {
+    let mut result = String::with_capacity(val.len());
+    let mut chars = val.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let invalid = |escape: &str| {
+            CliError::from(format!("invalid escape \\{} at byte {}", escape, position))
+        };
+        let decoded = match chars.next() {
+            None => {
+                return Err(CliError::from(format!(
+                    "trailing backslash at byte {}",
+                    position
+                )))
+            }
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '0')) => '\0',
+            Some((_, '\\')) => '\\',
+            Some((_, 'x')) => {
+                let digits: String = (0..2)
+                    .filter_map(|_| chars.next().map(|(_, c)| c))
+                    .collect();
+                match u8::from_str_radix(digits.as_str(), 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => char::from(byte),
+                    _ => return Err(invalid(format!("x{}", digits).as_str())),
+                }
+            }
+            Some((_, 'u')) if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                chars.next();
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => digits.push(c),
+                        None => return Err(invalid(format!("u{{{}", digits).as_str())),
+                    }
+                }
+                match u32::from_str_radix(digits.as_str(), 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(decoded) if digits.len() <= 6 => decoded,
+                    _ => return Err(invalid(format!("u{{{}}}", digits).as_str())),
+                }
+            }
+            Some((_, c)) if template && "ULEul".contains(c) => {
+                result.push('\\');
+                result.push(c);
+                continue;
+            }
+            Some((_, c)) => return Err(invalid(c.to_string().as_str())),
+        };
+        match (template, decoded) {
+            (true, '\\') => result.push_str("\\\\"),
+            (true, '$') => result.push_str("$$"),
+            _ => result.push(decoded),
+        }
+    }
+    return Ok(result);
+}
+
+/// The modification time and size of a file, to notice it being changed by
+/// someone else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &str) -> Result<FileStamp, CliError> {
+        let meta = std::fs::metadata(path)
+            .map_err(|error| CliError::from(format!("{}: {}", path, error)))?;
+        return Ok(FileStamp {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        });
+    }
+
+    pub fn unchanged(&self, path: &str) -> bool {
+        return FileStamp::of(path).is_ok_and(|stamp| stamp == *self);
+    }
+}
+
+/// The access and modification times of a file, to put back after it has been
+/// rewritten.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamps {
+    accessed: SystemTime,
+    modified: SystemTime,
+}
+
+impl Timestamps {
+    pub fn of(path: &str) -> Result<Timestamps, CliError> {
+        let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", path, error));
+        let meta = std::fs::metadata(path).map_err(wrap)?;
+        return Ok(Timestamps {
+            accessed: meta.accessed().map_err(wrap)?,
+            modified: meta.modified().map_err(wrap)?,
+        });
+    }
+
+    pub fn now() -> Timestamps {
+        let now = SystemTime::now();
+        return Timestamps {
+            accessed: now,
+            modified: now,
+        };
+    }
+
+    pub fn apply(&self, path: &str) -> Result<(), CliError> {
+        let times = std::fs::FileTimes::new()
+            .set_accessed(self.accessed)
+            .set_modified(self.modified);
+        return File::open(path)
+            .and_then(|file| file.set_times(times))
+            .map_err(|error| CliError::from(format!("{}: {}", path, error)));
+    }
+}
+
+/// 64 bit FNV-1a, which is stable across builds unlike the std hashers.
+pub fn checksum(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return format!("{:016x}", hash);
+}
+
 pub fn write_file(path: &str, content: String) -> Result<(), CliError> {
     match std::fs::OpenOptions::new()
         .write(true)
         .truncate(true)
-        .open(&path)?
+        .open(path)?
         .write_all(content.as_bytes())
     {
         Ok(_) => Ok(()),
         Err(error) => Err(CliError::from(error)),
     }
 }
+
+pub fn create_parent(path: &Path) -> Result<(), CliError> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            return Ok(std::fs::create_dir_all(parent)?)
+        }
+        _ => return Ok(()),
+    }
+}
+
+pub fn rename_file(source: &str, target: &str) -> Result<(), CliError> {
+    let wrap =
+        |error: std::io::Error| CliError::from(format!("{} -> {}: {}", source, target, error));
+    create_parent(Path::new(target))
+        .map_err(|error| CliError::from(format!("{}: {}", target, error)))?;
+    return std::fs::rename(source, target).map_err(wrap);
+}
+
+/// Replaces the content of `path` through a temporary file next to it, so that
+/// an interrupted write leaves the original in place.
+pub fn write_atomic(path: &str, content: &[u8]) -> Result<(), CliError> {
+    let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", path, error));
+    let mut temporary = Path::new(path).as_os_str().to_owned();
+    temporary.push(".rp-tmp");
+    let temporary = PathBuf::from(temporary);
+    let written = std::fs::metadata(path).and_then(|meta| {
+        std::fs::write(&temporary, content)?;
+        std::fs::set_permissions(&temporary, meta.permissions())?;
+        return std::fs::rename(&temporary, path);
+    });
+    if let Err(error) = written {
+        std::fs::remove_file(&temporary).ok();
+        return Err(wrap(error));
+    }
+    return Ok(());
+}
+
+/// Runs `write` with the read-only flag of `path` cleared and puts the
+/// original permissions back on whatever is at `path` afterwards.
+pub fn with_write_access<F, T>(path: &str, write: F) -> Result<T, CliError>
+where
+    F: FnOnce() -> Result<T, CliError>,
+{
+    let wrap = |error: std::io::Error| CliError::from(format!("{}: {}", path, error));
+    let original = std::fs::metadata(path).map_err(wrap)?.permissions();
+    if !original.readonly() {
+        return write();
+    }
+    let mut writable = original.clone();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        writable.set_mode(original.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    writable.set_readonly(false);
+    std::fs::set_permissions(path, writable).map_err(wrap)?;
+    let written = write();
+    std::fs::set_permissions(path, original).map_err(wrap)?;
+    return written;
+}
+
+/// Writes `content` to `target` through a temporary file next to it and only
+/// then removes `source`, so that the file is never seen half written.
+pub fn write_renamed(source: &str, target: &str, content: &[u8]) -> Result<(), CliError> {
+    let wrap =
+        |error: std::io::Error| CliError::from(format!("{} -> {}: {}", source, target, error));
+    let target_path = Path::new(target);
+    create_parent(target_path).map_err(|error| CliError::from(format!("{}: {}", target, error)))?;
+    let mut temporary = target_path.as_os_str().to_owned();
+    temporary.push(".rp-tmp");
+    let temporary = PathBuf::from(temporary);
+    std::fs::write(&temporary, content).map_err(wrap)?;
+    if let Ok(meta) = std::fs::metadata(source) {
+        std::fs::set_permissions(&temporary, meta.permissions()).map_err(wrap)?;
+    }
+    if let Err(error) = std::fs::rename(&temporary, target_path) {
+        std::fs::remove_file(&temporary).ok();
+        return Err(wrap(error));
+    }
+    return std::fs::remove_file(source).map_err(wrap);
+}
+
+pub fn parse_size(val: &str) -> Result<u64, CliError> {
+    let invalid = || CliError::from(format!("invalid size: {}", val));
+    let trimmed = val.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+    let multiplier: u128 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "ki" | "kib" => 1 << 10,
+        "m" | "mi" | "mib" => 1 << 20,
+        "g" | "gi" | "gib" => 1 << 30,
+        "t" | "ti" | "tib" => 1 << 40,
+        "p" | "pi" | "pib" => 1 << 50,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "pb" => 1_000_000_000_000_000,
+        _ => return Err(invalid()),
+    };
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (number, ""),
+    };
+    if (whole.is_empty() && fraction.is_empty()) || fraction.contains('.') || fraction.len() > 18 {
+        return Err(invalid());
+    }
+    let digits = format!("{}{}", whole, fraction);
+    let mantissa = match digits.parse::<u128>() {
+        Ok(mantissa) => mantissa,
+        Err(_) => return Err(CliError::from(format!("size out of range: {}", val))),
+    };
+    let size = mantissa
+        .checked_mul(multiplier)
+        .map(|size| size / 10u128.pow(fraction.len() as u32));
+    match size.map(u64::try_from) {
+        Some(Ok(size)) => return Ok(size),
+        _ => return Err(CliError::from(format!("size out of range: {}", val))),
+    }
+}
+
+/// Parses a positive, possibly fractional, number of seconds.
+pub fn parse_seconds(val: &str) -> Result<Duration, CliError> {
+    let seconds = val
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| *seconds > 0.0)
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+    match seconds {
+        Some(seconds) => return Ok(seconds),
+        None => {
+            return Err(CliError::from(format!(
+                "invalid number of seconds: {}",
+                val
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_bom_reads_past_it() {
+        for (input, bom, rest) in [
+            (&b"\xef\xbb\xbfhello"[..], true, &b"hello"[..]),
+            (b"\xef\xbb\xbf", true, b""),
+            (b"hello", false, b"hello"),
+            (b"\xef\xbb", false, b"\xef\xbb"),
+            (b"", false, b""),
+        ]
+        .iter()
+        {
+            let (found, mut reader) = skip_bom(*input).unwrap();
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!((found, read.as_slice()), (*bom, *rest), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_size_accepts_spellings() {
+        let cases: &[(&str, u64)] = &[
+            ("0", 0),
+            ("12", 12),
+            ("12B", 12),
+            ("12b", 12),
+            (" 12 ", 12),
+            ("1k", 1 << 10),
+            ("1K", 1 << 10),
+            ("1ki", 1 << 10),
+            ("1kib", 1 << 10),
+            ("1KiB", 1 << 10),
+            ("1KIB", 1 << 10),
+            ("64k", 64 << 10),
+            ("1m", 1 << 20),
+            ("1MiB", 1 << 20),
+            ("1.5MiB", 3 << 19),
+            ("2 GiB", 2 << 30),
+            ("1t", 1 << 40),
+            ("1PiB", 1 << 50),
+            (".5k", 512),
+            ("0.25k", 256),
+            ("1.", 1),
+            ("1kb", 1_000),
+            ("128KB", 128_000),
+            ("1MB", 1_000_000),
+            ("1.5mb", 1_500_000),
+            ("3GB", 3_000_000_000),
+            ("1TB", 1_000_000_000_000),
+            ("1PB", 1_000_000_000_000_000),
+            ("0.0001k", 0),
+            ("16383PiB", 16383 << 50),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_size(input).unwrap(), *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid() {
+        let cases = [
+            "",
+            " ",
+            "k",
+            ".",
+            ".k",
+            "-1",
+            "+1",
+            "1.2.3k",
+            "12XB",
+            "1 k b",
+            "1kibb",
+            "1e3",
+            "0x10",
+            "1EiB",
+            "99999999999999999999999",
+            "16384PiB",
+            "20000000PiB",
+            "18446744073709551616",
+            "1.0000000000000000001",
+        ];
+        for input in cases.iter() {
+            assert!(parse_size(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_seconds_takes_positive_durations() {
+        assert_eq!(parse_seconds("2").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_seconds("0.5").unwrap(), Duration::from_millis(500));
+        for invalid in ["0", "-1", "", "x", "inf", "NaN", "1e300"] {
+            assert!(parse_seconds(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
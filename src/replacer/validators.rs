@@ -21,28 +21,50 @@
 *   SOFTWARE.
 */
 use super::error::*;
-use regex::Regex;
-use std::fs::File;
-use std::io::Read;
-
-pub fn validate_regex(val: &str) -> Result<String, CliError> {
-    match Regex::new(val) {
-        Ok(_) => return Ok(String::from(val)),
-        Err(error) => return Err(CliError::from(error)),
+use super::lines::LineRange;
+use super::util::{parse_seconds, parse_size};
+use std::convert::TryFrom;
+
+pub fn validate_size(val: &str) -> Result<String, CliError> {
+    match parse_size(val) {
+        Ok(size) if size > 0 && usize::try_from(size).is_ok() => return Ok(String::from(val)),
+        Ok(_) => return Err(CliError::from(format!("size out of range: {}", val))),
+        Err(error) => return Err(error),
+    }
+}
+
+pub fn validate_seconds(val: &str) -> Result<String, CliError> {
+    parse_seconds(val)?;
+    return Ok(String::from(val));
+}
+
+pub fn validate_ratio(val: &str) -> Result<String, CliError> {
+    match val.parse::<f64>() {
+        Ok(ratio) if ratio > 0.0 && ratio <= 1.0 => return Ok(String::from(val)),
+        _ => {
+            return Err(CliError::from(format!(
+                "invalid ratio: {}, expected a number above 0 and at most 1",
+                val
+            )))
+        }
     }
 }
 
-pub fn validate_regex_file(val: &str) -> Result<String, CliError> {
-    let mut buffer = String::new();
-    let file = File::open(val);
-    match file {
-        Ok(mut file) => match file.read_to_string(&mut buffer) {
-            Ok(_) => match validate_regex(buffer.as_str()) {
-                Ok(_) => return Ok(String::from(val)),
-                Err(error) => return Err(CliError::from(error)),
-            },
-            Err(error) => return Err(CliError::from(error)),
-        },
-        Err(error) => return Err(CliError::from(error)),
+pub fn validate_passes(val: &str) -> Result<String, CliError> {
+    match val.parse::<usize>() {
+        Ok(passes) if passes > 0 => return Ok(String::from(val)),
+        _ => return Err(CliError::from(format!("invalid number of passes: {}", val))),
     }
 }
+
+pub fn validate_per_line_limit(val: &str) -> Result<String, CliError> {
+    match val.parse::<u64>() {
+        Ok(limit) if limit > 0 => return Ok(String::from(val)),
+        _ => return Err(CliError::from(format!("invalid per-line limit: {}", val))),
+    }
+}
+
+pub fn validate_line_range(val: &str) -> Result<String, CliError> {
+    LineRange::parse(val)?;
+    return Ok(String::from(val));
+}
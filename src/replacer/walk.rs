@@ -0,0 +1,88 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use super::error::*;
+use crate::infoln;
+use ignore::types::Types;
+use ignore::WalkBuilder;
+
+/// Expands `paths` into the files below them. Unless `no_ignore` is set,
+/// `.gitignore`, `.ignore` and the global git excludes are honored, and hidden
+/// entries are skipped unless `hidden` is set. Files named directly are always
+/// kept and `.git` directories are never entered. Below them only regular
+/// files and symlinks to them are kept. Errors for individual entries are
+/// returned alongside the files.
+///
+/// Symlinked directories are only entered with `follow_links`, in which case
+/// loops are reported as errors instead of being followed. Below the paths,
+/// only files of the selected `types` are kept when any are selected.
+pub fn walk(
+    paths: &[String],
+    hidden: bool,
+    no_ignore: bool,
+    follow_links: bool,
+    types: &Types,
+) -> (Vec<String>, Vec<CliError>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let (first, rest) = match paths.split_first() {
+        Some(split) => split,
+        None => return (files, errors),
+    };
+    let mut builder = WalkBuilder::new(first);
+    for path in rest {
+        builder.add(path);
+    }
+    builder
+        .hidden(!hidden)
+        .ignore(!no_ignore)
+        .git_ignore(!no_ignore)
+        .git_global(!no_ignore)
+        .git_exclude(!no_ignore)
+        .parents(!no_ignore)
+        .require_git(false)
+        .follow_links(follow_links)
+        .types(types.clone())
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| entry.file_name() != ".git");
+    for entry in builder.build() {
+        match entry {
+            Ok(entry) if entry.file_type().is_some_and(|kind| kind.is_dir()) => {}
+            Ok(entry) => {
+                let path = entry.path();
+                let path = path.strip_prefix(".").unwrap_or(path).to_string_lossy();
+                // Symlinks count when they resolve to a regular file; FIFOs and
+                // devices would block or never end. Named files are left to the
+                // caller.
+                match entry.depth() == 0 || entry.path().is_file() {
+                    true => files.push(String::from(path)),
+                    false if entry.path().is_dir() => {
+                        infoln!("{}: skipped (symlink to a directory)", path)
+                    }
+                    false => infoln!("{}: skipped (not a regular file)", path),
+                }
+            }
+            Err(error) => errors.push(CliError::from(error.to_string())),
+        }
+    }
+    return (files, errors);
+}
@@ -0,0 +1,76 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the files must stay unchanged before they are processed again.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Detects modified, created and recreated files by polling their modification
+/// times. A deleted file is remembered as missing so that recreating it counts
+/// as a change.
+#[derive(Debug, Default)]
+pub struct Poller {
+    seen: HashMap<String, Option<SystemTime>>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    return std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok();
+}
+
+impl Poller {
+    pub fn new(paths: &[String]) -> Poller {
+        let mut poller = Poller::default();
+        for path in paths {
+            poller.touch(path);
+        }
+        return poller;
+    }
+
+    /// Records the current state of `path`, e.g. after rp rewrote it.
+    pub fn touch(&mut self, path: &str) {
+        self.seen.insert(String::from(path), modified(path));
+    }
+
+    /// Returns the existing files among `paths` that changed since last seen.
+    pub fn changed(&mut self, paths: &[String]) -> Vec<String> {
+        let mut changed = Vec::new();
+        for path in paths {
+            let current = modified(path);
+            let previous = self.seen.insert(path.clone(), current);
+            if current.is_some() && previous.flatten() != current {
+                changed.push(path.clone());
+            }
+        }
+        let current: HashSet<&String> = paths.iter().collect();
+        for (path, time) in self.seen.iter_mut() {
+            if !current.contains(path) {
+                *time = None;
+            }
+        }
+        return changed;
+    }
+}
@@ -0,0 +1,103 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, TempDir};
+
+const INPUT: &[u8] = b"\xef\xbb\xbfhello\nhello\n";
+const EXPECTED: &[u8] = b"\xef\xbb\xbfX\nX\n";
+
+fn output(args: &[&str], stdin: &[u8]) -> Vec<u8> {
+    let dir = TempDir::new("bom");
+    dir.write("bom.txt", INPUT);
+    let output = rp(dir.path(), args, stdin);
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    return output.stdout;
+}
+
+#[test]
+fn first_line_matches_in_a_file() {
+    assert_eq!(
+        output(&["-p", "(?m)^hello", "-r", "X", "bom.txt"], b""),
+        EXPECTED
+    );
+}
+
+#[test]
+fn first_line_matches_on_streamed_stdin() {
+    assert_eq!(output(&["-p", "(?m)^hello", "-r", "X"], INPUT), EXPECTED);
+    assert_eq!(
+        output(&["-p", "(?m)^hello", "-r", "X", "--pump-limit", "8"], INPUT),
+        EXPECTED
+    );
+}
+
+#[test]
+fn first_line_matches_in_line_mode() {
+    let args = ["--line-mode", "-p", "^hello", "-r", "X"];
+    assert_eq!(output(&[&args[..], &["bom.txt"]].concat(), b""), EXPECTED);
+    assert_eq!(output(&args, INPUT), EXPECTED);
+}
+
+#[test]
+fn first_line_matches_line_buffered() {
+    assert_eq!(
+        output(&["--line-buffered", "-p", "(?m)^hello", "-r", "X"], INPUT),
+        EXPECTED
+    );
+}
+
+#[test]
+fn in_place_keeps_the_bom() {
+    let dir = TempDir::new("bom");
+    dir.write("bom.txt", INPUT);
+    let result = rp(
+        dir.path(),
+        &["-p", "(?m)^hello", "-r", "X", "-i", "bom.txt"],
+        b"",
+    );
+    assert!(result.status.success());
+    assert_eq!(dir.read("bom.txt"), EXPECTED);
+}
+
+#[test]
+fn strip_and_add() {
+    assert_eq!(
+        output(&["-p", "(?m)^hello", "-r", "X", "--strip-bom"], INPUT),
+        b"X\nX\n"
+    );
+    assert_eq!(
+        output(
+            &["-p", "(?m)^hello", "-r", "X", "--add-bom"],
+            b"hello\nhello\n"
+        ),
+        EXPECTED
+    );
+}
@@ -0,0 +1,48 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, TempDir};
+
+fn status(args: &[&str]) -> Option<i32> {
+    let dir = TempDir::new("check");
+    dir.write("changes.txt", b"x\n");
+    dir.write("same.txt", b"y\n");
+    let args = [&["--check", "-p", "x", "-r", "z"], args].concat();
+    return rp(dir.path(), &args, b"").status.code();
+}
+
+#[test]
+fn check_reports_changes() {
+    assert_eq!(status(&["same.txt"]), Some(0));
+    assert_eq!(status(&["changes.txt", "same.txt"]), Some(1));
+}
+
+#[test]
+fn check_tells_io_errors_from_usage_errors() {
+    assert_eq!(status(&["missing.txt"]), Some(4));
+    assert_eq!(status(&["missing.txt", "changes.txt"]), Some(5));
+    assert_eq!(status(&["--no-such-option", "changes.txt"]), Some(2));
+}
@@ -0,0 +1,76 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, TempDir};
+
+fn edit(args: &[&str], content: &str) -> (String, String) {
+    let dir = TempDir::new("empty-match");
+    dir.write("a.txt", content.as_bytes());
+    let mut args = args.to_vec();
+    args.push("a.txt");
+    let output = rp(dir.path(), &args, b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{:?}: {}", args, stderr);
+    return (String::from_utf8(dir.read("a.txt")).unwrap(), stderr);
+}
+
+#[test]
+fn replacing_in_place_is_refused() {
+    let dir = TempDir::new("empty-match");
+    dir.write("a.txt", b"a\n");
+    let output = rp(dir.path(), &["-i", "-p", "(?m)^", "-r", "X", "a.txt"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("between characters"));
+    assert_eq!(dir.read("a.txt"), b"a\n");
+}
+
+#[test]
+fn deleting_lines_in_place_is_allowed() {
+    let (content, stderr) = edit(&["-i", "--delete-lines", "-p", "(?m)^$"], "a\n\nb\n");
+    assert_eq!(content, "a\nb\n");
+    assert!(!stderr.contains("between characters"), "{}", stderr);
+}
+
+#[test]
+fn inserting_in_place_is_allowed() {
+    let (content, stderr) = edit(&["-i", "--insert-before", "X", "-p", "(?m)^"], "a\nb");
+    assert_eq!(content, "Xa\nXb");
+    assert!(!stderr.contains("between characters"), "{}", stderr);
+    let (content, _) = edit(
+        &[
+            "-i",
+            "--insert-after",
+            "X",
+            "--insert-newline",
+            "-p",
+            "(?m)^",
+        ],
+        "a\nb\n",
+    );
+    assert_eq!(content, "a\nX\nb\nX\n");
+}
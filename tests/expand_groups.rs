@@ -0,0 +1,108 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, TempDir};
+use std::process::Output;
+
+fn replace(replacement: &str, literal: bool) -> Output {
+    let dir = TempDir::new("expand-groups");
+    let mut args = vec!["-p", "(a)", "-r", replacement];
+    if literal {
+        args.push("--no-expand-groups");
+    }
+    return rp(dir.path(), &args, b"ab");
+}
+
+/// The table in the README, with `None` for an error.
+const TABLE: &[(&str, Option<&str>, &str)] = &[
+    ("$", Some("$b"), "$b"),
+    ("$$", Some("$b"), "$$b"),
+    ("${1}", Some("ab"), "${1}b"),
+    ("$1", Some("ab"), "$1b"),
+    ("$1x", None, "$1xb"),
+    ("$PATH", None, "$PATHb"),
+];
+
+#[test]
+fn dollars_expand_as_documented() {
+    for (replacement, expanded, _) in TABLE {
+        let output = replace(replacement, false);
+        match expanded {
+            Some(expanded) => {
+                assert!(output.status.success(), "{}", replacement);
+                assert_eq!(String::from_utf8(output.stdout).unwrap(), *expanded);
+            }
+            None => assert_eq!(output.status.code(), Some(2), "{}", replacement),
+        }
+    }
+}
+
+#[test]
+fn dollars_are_kept_without_expansion() {
+    for (replacement, _, literal) in TABLE {
+        let output = replace(replacement, true);
+        assert!(output.status.success(), "{}", replacement);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), *literal);
+    }
+}
+
+#[test]
+fn braces_without_expansion_warn() {
+    let warned = |replacement: &str| {
+        let output = replace(replacement, true);
+        return String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("the replacement contains ${");
+    };
+    assert!(warned("${1}"));
+    assert!(!warned("$1"));
+    assert!(!warned("$$"));
+}
+
+#[test]
+fn expanding_options_conflict() {
+    let dir = TempDir::new("expand-groups");
+    dir.write("map.txt", b"a b\n");
+    let cases: &[&[&str]] = &[
+        &["--numeric", "-p", "a", "-r", "b"],
+        &["--expand-env", "-p", "a", "-r", "b"],
+        &["--replace-cmd", "cat", "-p", "a"],
+        &["--map-file", "map.txt"],
+    ];
+    for case in cases {
+        let mut args = vec!["--no-expand-groups"];
+        args.extend_from_slice(case);
+        let output = rp(dir.path(), &args, b"a");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("cannot be used with") && stderr.contains(case[0]),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+    }
+}
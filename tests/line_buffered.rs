@@ -0,0 +1,75 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::TempDir;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Feeds `writes` to rp one at a time with a pause in between, so that each
+/// arrives in a read of its own.
+fn rp_slowly(dir: &TempDir, args: &[&str], writes: &[&[u8]]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rp"))
+        .args(args)
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    for write in writes {
+        input.write_all(write).unwrap();
+        input.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+    }
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "rp {:?} exited with {:?}: {}",
+        args,
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    return String::from_utf8(output.stdout).unwrap();
+}
+
+#[test]
+fn a_character_cut_by_the_pump_limit_waits_for_its_last_byte() {
+    let dir = TempDir::new("line-buffered-split");
+    let args = ["--line-buffered", "-W", "3", "-p", "a", "-r", "b"];
+    let output = rp_slowly(&dir, &args, &[b"aa\xc3", b"\xa9\n"]);
+    assert_eq!(output, "bb\u{e9}\n");
+}
+
+#[test]
+fn a_window_at_the_pump_limit_is_emitted_before_its_newline() {
+    let dir = TempDir::new("line-buffered-window");
+    let args = ["--line-buffered", "-W", "3", "-p", "a", "-r", "b"];
+    let output = rp_slowly(&dir, &args, &[b"aaa", b"a\n"]);
+    assert_eq!(output, "bbbb\n");
+}
@@ -0,0 +1,49 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp_ok, TempDir};
+
+#[test]
+fn files_may_follow_lines() {
+    let dir = TempDir::new("lines");
+    dir.write("file.txt", b"x\nx\nx\nx\n");
+    assert_eq!(
+        rp_ok(
+            dir.path(),
+            &["-p", "x", "-r", "Y", "--lines", "2:3", "file.txt"],
+            b""
+        ),
+        "x\nY\nY\nx\n"
+    );
+    assert_eq!(
+        rp_ok(
+            dir.path(),
+            &["-p", "x", "-r", "Y", "--lines", "1", "--lines", "4:", "file.txt"],
+            b""
+        ),
+        "Y\nx\nx\nY\n"
+    );
+}
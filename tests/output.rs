@@ -0,0 +1,89 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp_ok, TempDir};
+
+#[test]
+fn short_output_flag_writes_a_file() {
+    let dir = TempDir::new("output");
+    dir.write("input.txt", b"foo\n");
+    let printed = rp_ok(
+        dir.path(),
+        &["-p", "foo", "-r", "bar", "-O", "result.txt", "input.txt"],
+        b"",
+    );
+    assert_eq!(printed, "");
+    assert_eq!(dir.read("result.txt"), b"bar\n");
+    assert_eq!(dir.read("input.txt"), b"foo\n");
+}
+
+#[test]
+fn output_directory_recreates_paths() {
+    let dir = TempDir::new("output");
+    std::fs::create_dir_all(dir.path().join("src/sub")).unwrap();
+    dir.write("src/a.txt", b"foo\n");
+    dir.write("src/sub/b.txt", b"foo foo\n");
+    rp_ok(
+        dir.path(),
+        &[
+            "-p",
+            "foo",
+            "-r",
+            "bar",
+            "--output",
+            "build",
+            "src/a.txt",
+            "src/sub/b.txt",
+        ],
+        b"",
+    );
+    assert_eq!(dir.read("build/src/a.txt"), b"bar\n");
+    assert_eq!(dir.read("build/src/sub/b.txt"), b"bar bar\n");
+}
+
+#[test]
+fn file_separator_may_start_with_hyphens() {
+    let dir = TempDir::new("output");
+    dir.write("a.txt", b"a\n");
+    dir.write("b.txt", b"b\n");
+    assert_eq!(
+        rp_ok(
+            dir.path(),
+            &[
+                "-p",
+                "x",
+                "-r",
+                "y",
+                "--file-separator",
+                "--\\n",
+                "a.txt",
+                "b.txt"
+            ],
+            b"",
+        ),
+        "a\n--\nb\n"
+    );
+}
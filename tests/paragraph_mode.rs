@@ -0,0 +1,82 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp_ok, TempDir};
+
+fn paragraph_mode(args: &[&str], input: &str) -> String {
+    let dir = TempDir::new("paragraph-mode");
+    let args = [&["--paragraph-mode"], args].concat();
+    return rp_ok(dir.path(), &args, input.as_bytes());
+}
+
+#[test]
+fn untouched_input_is_kept_byte_for_byte() {
+    for input in [
+        "",
+        "\n",
+        "\n\n\na\n",
+        "a\nb\n\n\nc",
+        "a\r\n \t\r\n\r\nb\r\n\r\n",
+        "x\n   \n\ty\n\n",
+    ]
+    .iter()
+    {
+        assert_eq!(paragraph_mode(&["-p", "nomatch", "-r", "X"], input), *input);
+    }
+}
+
+#[test]
+fn anchors_apply_to_each_paragraph() {
+    assert_eq!(
+        paragraph_mode(
+            &["-p", r"\A\w+", "-r", "X"],
+            "one two\nthree\n\nfour\n \nfive"
+        ),
+        "X two\nthree\n\nX\n \nX"
+    );
+    assert_eq!(
+        paragraph_mode(&["-p", r"\w+\z", "-r", "X"], "a b\n\r\nc d\r\n"),
+        "a X\n\r\nc X\r\n"
+    );
+}
+
+#[test]
+fn if_matches_selects_paragraphs() {
+    assert_eq!(
+        paragraph_mode(
+            &[
+                "-p",
+                "port = 80",
+                "-r",
+                "port = 8080",
+                "--if-matches",
+                r"\[web\]"
+            ],
+            "[db]\nport = 80\n\n[web]\nport = 80\n"
+        ),
+        "[db]\nport = 80\n\n[web]\nport = 8080\n"
+    );
+}
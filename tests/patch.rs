@@ -0,0 +1,128 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, rp_ok, TempDir};
+use std::process::Command;
+
+/// Writes `content` to `name`, prints the --dry-run patch of running rp with
+/// `args` on it, applies that patch with git apply and checks the file ends
+/// up as rp would have written it.
+fn round_trip(name: &str, content: &str, args: &[&str]) {
+    let dir = TempDir::new("patch");
+    std::fs::create_dir_all(dir.path().join(name).parent().unwrap()).unwrap();
+    dir.write(name, content.as_bytes());
+    let expected = rp_ok(dir.path(), args, content.as_bytes());
+    assert_ne!(expected, content, "{} is not changed", name);
+
+    let patch = rp(
+        dir.path(),
+        &[&["-n", "--format", "patch"], args, &[name]].concat(),
+        b"",
+    );
+    assert!(
+        patch.status.success(),
+        "{}",
+        String::from_utf8_lossy(&patch.stderr)
+    );
+    assert_eq!(
+        dir.read(name),
+        content.as_bytes(),
+        "--dry-run changed {}",
+        name
+    );
+    let patch_path = dir.write("changes.patch", &patch.stdout);
+
+    let applied = Command::new("git")
+        .arg("apply")
+        .arg(&patch_path)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(
+        applied.status.success(),
+        "git apply failed for {}: {}\n{}",
+        name,
+        String::from_utf8_lossy(&applied.stderr),
+        String::from_utf8_lossy(&patch.stdout)
+    );
+    assert_eq!(
+        String::from_utf8(dir.read(name)).unwrap(),
+        expected,
+        "{}",
+        String::from_utf8_lossy(&patch.stdout)
+    );
+}
+
+fn replace(name: &str, content: &str, pattern: &str, replacement: &str) {
+    round_trip(name, content, &["-p", pattern, "-r", replacement]);
+}
+
+fn numbered(lines: usize) -> String {
+    return (1..=lines).map(|line| format!("line {}\n", line)).collect();
+}
+
+#[test]
+fn separate_hunks() {
+    replace("file.txt", &numbered(30), "(?m)line (3|27)$", "changed $1");
+}
+
+#[test]
+fn hunks_with_shared_context() {
+    replace("file.txt", &numbered(12), "line (4|8)\n", "changed $1\n");
+}
+
+#[test]
+fn path_in_subdirectory() {
+    replace("sub/dir/file.txt", &numbered(5), "line 2", "two");
+}
+
+#[test]
+fn no_newline_at_end_of_file() {
+    replace("file.txt", "a\nb\nfoo", "foo", "bar");
+    replace("file.txt", "a\nb\nfoo\n", "foo\n", "bar");
+    replace("file.txt", "a\nb\nfoo", "foo", "bar\n");
+}
+
+#[test]
+fn pure_deletion() {
+    for pattern in ["line 1", "line 4", "line 8"].iter() {
+        round_trip("file.txt", &numbered(8), &["-p", pattern, "--delete-lines"]);
+    }
+    round_trip("file.txt", "only\n", &["-p", "only", "--delete-lines"]);
+    round_trip("file.txt", "a\nonly", &["-p", "only", "--delete-lines"]);
+}
+
+#[test]
+fn pure_insertion() {
+    replace("file.txt", &numbered(8), "line 4\n", "line 4\nnew\n");
+    replace("file.txt", &numbered(8), r"\A", "first\n");
+    replace("file.txt", &numbered(8), r"\z", "last\n");
+}
+
+#[test]
+fn joined_lines() {
+    replace("file.txt", &numbered(6), r"line 3\n", "line 3 ");
+}
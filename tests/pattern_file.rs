@@ -0,0 +1,80 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp_ok, TempDir};
+
+fn replace_with_pattern_file(pattern_file: &[u8], extra: &[&str], input: &str) -> String {
+    let dir = TempDir::new("pattern-file");
+    dir.write("pattern.txt", pattern_file);
+    let args = [&["-P", "pattern.txt", "-r", "X"], extra].concat();
+    return rp_ok(dir.path(), &args, input.as_bytes());
+}
+
+#[test]
+fn crlf_line_ending_is_dropped() {
+    assert_eq!(
+        replace_with_pattern_file(b"foo\r\n", &[], "foo\r\nfoo\n"),
+        "X\r\nX\n"
+    );
+    assert_eq!(replace_with_pattern_file(b"foo\r", &[], "foo\r\n"), "X\r\n");
+    assert_eq!(
+        replace_with_pattern_file(
+            b"foo\r\nbar\r\n",
+            &["--keep-trailing-newline"],
+            "foo\nbar\nfoo\r\nbar\n"
+        ),
+        "Xfoo\r\nbar\n"
+    );
+}
+
+#[test]
+fn crlf_line_ending_is_kept_when_raw() {
+    assert_eq!(
+        replace_with_pattern_file(b"foo\r\n", &["--pattern-file-raw"], "foo\r\nfoo\n"),
+        "X\nfoo\n"
+    );
+}
+
+#[test]
+fn escaped_carriage_return_is_kept() {
+    // The two characters `\r` are a regex escape, not a line ending.
+    assert_eq!(
+        replace_with_pattern_file(br"foo\r", &[], "foo\r\nfoo\n"),
+        "X\nfoo\n"
+    );
+    assert_eq!(
+        replace_with_pattern_file(b"foo\\r\r\n", &[], "foo\r\nfoo\n"),
+        "X\nfoo\n"
+    );
+}
+
+#[test]
+fn carriage_return_inside_a_line_is_kept() {
+    assert_eq!(
+        replace_with_pattern_file(b"a\rb\r\n", &[], "a\rb ab\n"),
+        "X ab\n"
+    );
+}
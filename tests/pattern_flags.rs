@@ -0,0 +1,58 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp_ok, TempDir};
+
+fn replace(args: &[&str], input: &str) -> String {
+    let dir = TempDir::new("pattern-flags");
+    return rp_ok(dir.path(), args, input.as_bytes());
+}
+
+#[test]
+fn flags_travel_with_the_pattern() {
+    assert_eq!(
+        replace(&["-p", "/^warn.*$/im", "-r", "W"], "x\nWARN a\nwarn b\n"),
+        "x\nW\nW\n"
+    );
+}
+
+#[test]
+fn slashes_are_a_plain_pattern() {
+    assert_eq!(replace(&["-p", "//", "-r", "/"], "a//b"), "a/b");
+    assert_eq!(
+        replace(&["-p", "//", "-r", "/", "--until-stable"], "a////b"),
+        "a/b"
+    );
+}
+
+#[test]
+fn no_pattern_flags_matches_literally() {
+    assert_eq!(replace(&["-p", "/usr/", "-r", "X"], "/usr/ x"), "/X/ x");
+    assert_eq!(
+        replace(&["-p", "/usr/", "-r", "X", "--no-pattern-flags"], "/usr/ x"),
+        "X x"
+    );
+}
@@ -0,0 +1,105 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, rp_ok, TempDir};
+
+const TABLE: &str = "none here\none a\nmany a a a a\n\nlast a a";
+
+fn limited(limit: &str, input: &str) -> String {
+    let dir = TempDir::new("per-line-limit");
+    return rp_ok(
+        dir.path(),
+        &["-p", "a", "-r", "X", "--per-line-limit", limit],
+        input.as_bytes(),
+    );
+}
+
+#[test]
+fn first_match_per_line() {
+    assert_eq!(
+        limited("1", TABLE),
+        "none here\none X\nmXny a a a a\n\nlXst a a"
+    );
+}
+
+#[test]
+fn several_matches_per_line() {
+    assert_eq!(
+        limited("3", TABLE),
+        "none here\none X\nmXny X X a a\n\nlXst X X"
+    );
+    assert_eq!(
+        limited("100", TABLE),
+        "none here\none X\nmXny X X X X\n\nlXst X X"
+    );
+}
+
+#[test]
+fn crlf_lines() {
+    assert_eq!(limited("1", "a a\r\na a\r\n"), "X a\r\nX a\r\n");
+}
+
+#[test]
+fn zero_is_refused() {
+    let dir = TempDir::new("per-line-limit");
+    let output = rp(
+        dir.path(),
+        &["-p", "a", "-r", "X", "--per-line-limit", "0"],
+        b"a\n",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn multiline_pattern_is_refused() {
+    let dir = TempDir::new("per-line-limit");
+    for pattern in [r"a\nb", "a\nb", r"a[\n]b"].iter() {
+        let output = rp(
+            dir.path(),
+            &["-p", pattern, "-r", "X", "--per-line-limit", "1"],
+            b"a\nb\n",
+        );
+        assert_eq!(output.status.code(), Some(2), "{:?}", pattern);
+        let error = String::from_utf8(output.stderr).unwrap();
+        assert!(error.contains("--per-line-limit"), "{}", error);
+        assert!(output.stdout.is_empty(), "{:?}", pattern);
+    }
+}
+
+#[test]
+fn matches_spanning_lines_are_left_alone() {
+    let dir = TempDir::new("per-line-limit");
+    let replace = |pattern: &str, input: &str| -> String {
+        return rp_ok(
+            dir.path(),
+            &["-p", pattern, "-r", "X", "--per-line-limit", "1"],
+            input.as_bytes(),
+        );
+    };
+    assert_eq!(replace(r"a\sb", "a\nb\n"), "a\nb\n");
+    assert_eq!(replace(r"a\sb", "a\nb a b a b\n"), "a\nb X a b\n");
+    assert_eq!(replace(r"a\s*", "a\n a a\n"), "a\n Xa\n");
+}
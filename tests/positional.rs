@@ -0,0 +1,177 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, rp_ok, TempDir};
+
+fn fixture() -> TempDir {
+    let dir = TempDir::new("positional");
+    dir.write("a.txt", b"foo1 a\n");
+    dir.write("b.txt", b"foo2 b\n");
+    return dir;
+}
+
+fn run(args: &[&str], stdin: &str) -> String {
+    let dir = fixture();
+    return rp_ok(dir.path(), args, stdin.as_bytes());
+}
+
+fn refused(args: &[&str]) -> String {
+    let dir = fixture();
+    let output = rp(dir.path(), args, b"");
+    assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    assert!(output.stdout.is_empty(), "{:?}", args);
+    return String::from_utf8(output.stderr).unwrap();
+}
+
+#[test]
+fn pattern_and_replacement_on_stdin() {
+    assert_eq!(run(&[r"foo(\d)", "bar${1}"], "foo7\n"), "bar7\n");
+}
+
+#[test]
+fn pattern_and_replacement_then_files() {
+    assert_eq!(run(&[r"foo(\d)", "bar${1}", "a.txt"], ""), "bar1 a\n");
+    assert_eq!(
+        run(&[r"foo(\d)", "bar${1}", "a.txt", "b.txt"], ""),
+        "bar1 a\nbar2 b\n"
+    );
+}
+
+#[test]
+fn pattern_option_leaves_files() {
+    assert_eq!(
+        run(&["-p", "foo", "-r", "X", "a.txt", "b.txt"], ""),
+        "X1 a\nX2 b\n"
+    );
+    assert_eq!(run(&["-p", "foo", "a.txt"], ""), "1 a\n");
+}
+
+#[test]
+fn replacement_option_takes_only_the_pattern() {
+    assert_eq!(run(&["-r", "X", "foo", "a.txt"], ""), "X1 a\n");
+    assert_eq!(run(&["foo", "-r", "X"], "foo\n"), "X\n");
+    assert_eq!(run(&["foo", "a.txt", "--replacement", "X"], ""), "X1 a\n");
+}
+
+#[test]
+fn replacement_file_takes_only_the_pattern() {
+    let dir = fixture();
+    dir.write("replacement.txt", b"Y\n");
+    assert_eq!(
+        rp_ok(dir.path(), &["-R", "replacement.txt", "foo", "a.txt"], b""),
+        "Y1 a\n"
+    );
+}
+
+#[test]
+fn pattern_file_leaves_files() {
+    let dir = fixture();
+    dir.write("pattern.txt", b"foo\n");
+    assert_eq!(
+        rp_ok(dir.path(), &["-P", "pattern.txt", "-r", "Y", "a.txt"], b""),
+        "Y1 a\n"
+    );
+}
+
+#[test]
+fn modes_without_replacement_take_only_the_pattern() {
+    assert_eq!(
+        run(&["-o", r"foo\d", "a.txt", "b.txt"], ""),
+        "a.txt:foo1\nb.txt:foo2\n"
+    );
+    assert_eq!(run(&["-l", "foo1", "a.txt", "b.txt"], ""), "a.txt\n");
+}
+
+#[test]
+fn sed_expression_comes_first() {
+    assert_eq!(run(&["s/foo/X/", "a.txt"], ""), "X1 a\n");
+}
+
+#[test]
+fn incomplete_sed_expression_is_a_pattern() {
+    let dir = fixture();
+    dir.write("say.txt", b"say soy\n");
+    assert_eq!(rp_ok(dir.path(), &["s.y", "X", "say.txt"], b""), "X X\n");
+    assert_eq!(
+        rp_ok(dir.path(), &["s(ay)", "X", "say.txt"], b""),
+        "X soy\n"
+    );
+}
+
+#[test]
+fn sed_expression_followed_by_a_replacement_is_a_pattern() {
+    assert_eq!(run(&["s.o.", "X"], "s.o. sxoy\n"), "X X\n");
+}
+
+#[test]
+fn separator_ends_pattern_and_replacement() {
+    assert_eq!(run(&["foo", "X", "--", "a.txt"], ""), "X1 a\n");
+    assert_eq!(run(&["-r", "X", "foo", "--", "a.txt"], ""), "X1 a\n");
+}
+
+#[test]
+fn arguments_after_separator_are_files() {
+    let dir = fixture();
+    let output = rp(
+        dir.path(),
+        &["-p", "foo", "-r", "X", "--", "a.txt", "b.txt"],
+        b"",
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "X1 a\nX2 b\n");
+    // Without a pattern, -- keeps every argument a file.
+    let output = rp(dir.path(), &["--", "foo", "a.txt"], b"");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("foo:"));
+}
+
+#[test]
+fn single_argument_is_a_file() {
+    let dir = fixture();
+    let output = rp(dir.path(), &["-r", "X", "--", "a.txt"], b"");
+    assert!(output.status.success());
+    let output = rp(dir.path(), &["foo"], b"");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("foo:"));
+}
+
+#[test]
+fn missing_replacement_before_separator_is_refused() {
+    let error = refused(&["foo", "--", "a.txt"]);
+    assert!(error.contains("the replacement is missing"), "{}", error);
+}
+
+#[test]
+fn pattern_naming_an_existing_file_is_refused() {
+    let error = refused(&["a.txt", "X", "b.txt"]);
+    assert!(error.contains("a.txt: the first argument"), "{}", error);
+}
+
+#[test]
+fn replacement_naming_an_existing_file_is_refused() {
+    let error = refused(&["foo", "a.txt"]);
+    assert!(error.contains("a.txt: the second argument"), "{}", error);
+    // A file after it makes the intent clear.
+    assert_eq!(run(&["foo", "a.txt", "b.txt"], ""), "a.txt2 b\n");
+    assert_eq!(run(&["-p", "foo", "a.txt"], ""), "1 a\n");
+}
@@ -0,0 +1,85 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{rp, rp_ok, TempDir};
+
+#[test]
+fn multi_line_replacement_from_stdin() {
+    let dir = TempDir::new("replacement-stdin");
+    dir.write("config.toml", b"[server]\nPLACEHOLDER\nport = 80\n");
+    let body = b"host = \"a\"\nname = \"b\"\n";
+    rp_ok(
+        dir.path(),
+        &["-p", "PLACEHOLDER", "-R", "-", "-i", "config.toml"],
+        body,
+    );
+    assert_eq!(
+        String::from_utf8(dir.read("config.toml")).unwrap(),
+        "[server]\nhost = \"a\"\nname = \"b\"\nport = 80\n"
+    );
+}
+
+#[test]
+fn trailing_newline_is_kept_on_request() {
+    let dir = TempDir::new("replacement-stdin");
+    dir.write("input.txt", b"a PLACEHOLDER b\n");
+    let output = rp_ok(
+        dir.path(),
+        &[
+            "-p",
+            "PLACEHOLDER",
+            "-R",
+            "-",
+            "--keep-trailing-newline",
+            "input.txt",
+        ],
+        b"one\ntwo\n",
+    );
+    assert_eq!(output, "a one\ntwo\n b\n");
+}
+
+#[test]
+fn pattern_from_stdin() {
+    let dir = TempDir::new("replacement-stdin");
+    dir.write("input.txt", b"foo bar\n");
+    assert_eq!(
+        rp_ok(dir.path(), &["-P", "-", "-r", "X", "input.txt"], b"b.r\n"),
+        "foo X\n"
+    );
+}
+
+#[test]
+fn stdin_needed_twice_is_refused() {
+    let dir = TempDir::new("replacement-stdin");
+    let output = rp(dir.path(), &["-p", "PLACEHOLDER", "-R", "-"], b"body\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be given as files"));
+
+    dir.write("input.txt", b"x\n");
+    let output = rp(dir.path(), &["-P", "-", "-R", "-", "input.txt"], b"x\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("only be read once"));
+}
@@ -0,0 +1,96 @@
+/*
+*   Copyright (c) 2021 Neil F Jones
+*   All rights reserved.
+
+*   Permission is hereby granted, free of charge, to any person obtaining a copy
+*   of this software and associated documentation files (the "Software"), to deal
+*   in the Software without restriction, including without limitation the rights
+*   to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+*   copies of the Software, and to permit persons to whom the Software is
+*   furnished to do so, subject to the following conditions:
+
+*   The above copyright notice and this permission notice shall be included in all
+*   copies or substantial portions of the Software.
+
+*   THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+*   IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+*   FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+*   AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+*   LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+*   OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+*   SOFTWARE.
+*/
+#![allow(clippy::needless_return)]
+
+use regex::Regex;
+use rp::replacer::stream::replace_stream;
+
+/// Hands out at most `size` bytes per read, like a pipe delivering small writes.
+struct Chunked<'a> {
+    data: &'a [u8],
+    size: usize,
+}
+
+impl<'a> std::io::Read for Chunked<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.size.min(buf.len()).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        return Ok(n);
+    }
+}
+
+/// A xorshift generator, so failures reproduce without an extra dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return (self.0 % bound as u64) as usize;
+    }
+}
+
+#[test]
+fn matches_replace_all_on_random_input() {
+    let patterns = [
+        r"ab", r"a*", r"(?m)^b", r"\bab\b", r"b?", r"\Aa", r"a\z", r"(?m)a$", r"é+", r"x|",
+    ];
+    let alphabet = ['a', 'b', ' ', '\n', 'é'];
+    let mut rng = Rng(12345);
+    for _ in 0..20000 {
+        let length = rng.next(30);
+        let input: String = (0..length)
+            .map(|_| alphabet[rng.next(alphabet.len())])
+            .collect();
+        let pattern = patterns[rng.next(patterns.len())];
+        let window = 4 + rng.next(6);
+        let size = 1 + rng.next(5);
+
+        let regex = Regex::new(pattern).unwrap();
+        let reader = Chunked {
+            data: input.as_bytes(),
+            size,
+        };
+        let mut output = Vec::new();
+        let count = replace_stream(&regex, "[$0]", reader, &mut output, window).unwrap();
+
+        let context = format!(
+            "input {:?}, pattern {:?}, window {}, read size {}",
+            input, pattern, window, size
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            regex.replace_all(&input, "[$0]"),
+            "{}",
+            context
+        );
+        assert_eq!(
+            count as usize,
+            regex.find_iter(&input).count(),
+            "{}",
+            context
+        );
+    }
+}